@@ -12,6 +12,12 @@ pub struct AuthSession {
     timeout: Duration,
 }
 
+impl AuthSession {
+    pub fn expiry(&self) -> SystemTime {
+        self.expiry
+    }
+}
+
 define_uuid_key!(AuthToken);
 
 pub type AuthDb = db::Database<AuthToken, AuthSession>;
@@ -80,6 +86,23 @@ impl AuthManager {
         }
     }
 
+    /// Lists the sessions a user is logged in with. The tokens aren't included, as they would let anyone log in as the user.
+    pub fn user_sessions(&self, user_id: &UserKey) -> Vec<AuthSession> {
+        let mut sessions: Vec<AuthSession> = Vec::new();
+        self.db().for_each_val(|session| {
+            if &session.user_id == user_id && session.expiry > SystemTime::now() {
+                sessions.push(session);
+            }
+        });
+        sessions
+    }
+
+    /// Logs a user out everywhere.
+    pub fn destroy_user_sessions(&self, user_id: &UserKey) {
+        let user_id: UserKey = *user_id;
+        self.db().retain(true, move |v| v.user_id != user_id);
+    }
+
     pub fn clear_expired_sessions(&self) {
         self.db().retain(false, |v| {
             if v.expiry > SystemTime::now() {
@@ -104,11 +127,15 @@ impl AuthContext {
     }
 
     pub fn org_items(&self, org_id: org::OrgKey, org: &org::Org) -> Vec<(String, String)> {
-        vec![
+        let mut items = vec![
             (
                 dir::org_path(org_id) + dir::CLIENTS_PAGE,
                 "Pupils (".to_owned() + &org.clients.len().to_string() + ")",
             ),
+            (
+                dir::org_path(org_id) + dir::CLASSES_PAGE,
+                dir::CLASSES_TITLE.to_owned(),
+            ),
             (
                 dir::org_path(org_id) + dir::ASSOCIATES_PAGE,
                 "Teachers (".to_owned() + &org.associates.len().to_string() + ")",
@@ -119,6 +146,33 @@ impl AuthContext {
                     + &org.unreviewed_sections.len().to_string()
                     + ")",
             ),
-        ]
+            (
+                dir::org_path(org_id) + dir::CREDITS_PATH,
+                "Credits (".to_owned() + &org.credits.to_string() + ")",
+            ),
+        ];
+
+        if self.user.user_agent.can_manage_org_admins(&org_id) {
+            items.push((
+                dir::org_path(org_id) + dir::ORG_ADMINS_PAGE,
+                dir::ORG_ADMINS_TITLE.to_owned(),
+            ));
+        }
+
+        if self.user.user_agent.can_rollover_org(&org_id) {
+            items.push((
+                dir::org_path(org_id) + dir::ROLLOVER_PAGE,
+                dir::ROLLOVER_TITLE.to_owned(),
+            ));
+        }
+
+        if self.user.user_agent.can_edit_org(&org_id) {
+            items.push((
+                dir::org_path(org_id) + dir::ORG_SETTINGS_PATH,
+                dir::ORG_SETTINGS_TITLE.to_owned(),
+            ));
+        }
+
+        items
     }
 }
@@ -0,0 +1,234 @@
+use crate::{db, dir, user, util};
+use printpdf::{
+    BuiltinFont, Color, Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfLayerReference, Point, Rgb,
+};
+use serde::{Deserialize, Serialize};
+use std::io::BufWriter;
+use std::time::SystemTime;
+
+/// The size of the icon embedded in certificates, in pixels.
+const ICON_SIZE: u32 = 300;
+
+const PAGE_WIDTH: f64 = 297.0;
+const PAGE_HEIGHT: f64 = 210.0;
+
+/// The size of the verification QR code printed on certificates, in mm.
+const QR_SIZE: f64 = 18.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateActivity {
+    pub section: String,
+    pub activity: String,
+}
+
+/// A record of a completed award. Everything shown on the certificate is copied in when it is issued,
+/// so that it stays the same if the pupil, org or activities are changed later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Certificate {
+    pub number: String,
+    pub user_id: user::UserKey,
+    pub name: String,
+    pub award: String,
+    pub award_name: String,
+    pub org_name: String,
+    pub completed: SystemTime,
+    pub activities: Vec<CertificateActivity>,
+}
+
+define_uuid_key!(CertificateKey);
+
+pub type CertificateDb = db::Database<CertificateKey, Certificate>;
+
+/// Maps certificate numbers to certificates, so that they can be looked up from the verification page.
+pub type CertificateCodeDb = db::Database<str, CertificateKey>;
+
+/// Stored alongside a certificate once it has been revoked. Revoked certificates are kept so that
+/// the verification page can report them as revoked rather than unknown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revocation {
+    pub revoked: SystemTime,
+    pub revoked_by: user::UserKey,
+    pub reason: String,
+}
+
+pub type RevocationDb = db::Database<CertificateKey, Revocation>;
+
+impl Certificate {
+    /// Generates a certificate number such as `SD-2026-4F7KQ2ZD`.
+    pub fn generate_number() -> String {
+        format!(
+            "SD-{}-{}",
+            chrono::Local::now().format("%Y"),
+            util::gen_password(8).to_uppercase()
+        )
+    }
+
+    /// Certificate numbers are printed in upper case, but people typing them in may not.
+    pub fn normalise_number(number: &str) -> String {
+        number.trim().to_uppercase()
+    }
+
+    pub fn verify_path(&self) -> String {
+        dir::VERIFY_PATH.to_owned() + "/" + &self.number
+    }
+
+    pub fn filename(&self) -> String {
+        sanitize_filename::sanitize(self.name.clone() + " - " + &self.award_name + " Certificate.pdf")
+    }
+
+    /// Renders the certificate as a landscape A4 PDF. `icon_path` is the award icon on disk.
+    pub fn render_pdf(&self, icon_path: &str) -> Result<Vec<u8>, printpdf::Error> {
+        let (doc, page, layer) = PdfDocument::new(
+            self.award_name.clone() + " Certificate",
+            Mm(PAGE_WIDTH),
+            Mm(PAGE_HEIGHT),
+            "Certificate",
+        );
+        let layer = doc.get_page(page).get_layer(layer);
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let italic = doc.add_builtin_font(BuiltinFont::HelveticaOblique)?;
+
+        // Border
+        layer.set_outline_color(Color::Rgb(Rgb::new(0.85, 0.55, 0.1, None)));
+        for (inset, thickness) in [(8.0, 4.0), (12.0, 1.0)].iter() {
+            layer.set_outline_thickness(*thickness);
+            layer.add_shape(Line {
+                points: vec![
+                    (Point::new(Mm(*inset), Mm(*inset)), false),
+                    (Point::new(Mm(PAGE_WIDTH - inset), Mm(*inset)), false),
+                    (Point::new(Mm(PAGE_WIDTH - inset), Mm(PAGE_HEIGHT - inset)), false),
+                    (Point::new(Mm(*inset), Mm(PAGE_HEIGHT - inset)), false),
+                ],
+                is_closed: true,
+                has_fill: false,
+                has_stroke: true,
+                is_clipping_path: false,
+            });
+        }
+
+        match load_icon(icon_path) {
+            Some(icon) => {
+                // At 300 dpi the icon is roughly 25mm across.
+                let size: f64 = ICON_SIZE as f64 / 300.0 * 25.4;
+                icon.add_to_layer(
+                    layer.clone(),
+                    ImageTransform {
+                        translate_x: Some(Mm((PAGE_WIDTH - size) / 2.0)),
+                        translate_y: Some(Mm(PAGE_HEIGHT - 22.0 - size)),
+                        dpi: Some(300.0),
+                        ..Default::default()
+                    },
+                );
+            }
+            None => log::warn!("Failed to load certificate icon: {}", icon_path),
+        }
+
+        layer.set_fill_color(Color::Rgb(Rgb::new(0.1, 0.1, 0.1, None)));
+        centered_text(&layer, "Certificate of Achievement", 30.0, 145.0, &bold);
+        centered_text(&layer, "This is to certify that", 14.0, 132.0, &italic);
+        centered_text(&layer, &self.name, 28.0, 119.0, &bold);
+        centered_text(&layer, &("of ".to_owned() + &self.org_name), 14.0, 109.0, &regular);
+        centered_text(&layer, "has successfully completed the", 14.0, 99.0, &italic);
+        centered_text(&layer, &self.award_name, 22.0, 88.0, &bold);
+
+        // Activities are laid out in two columns.
+        let mut y: f64 = 74.0;
+        for row in self.activities.chunks(2) {
+            for (i, activity) in row.iter().enumerate() {
+                let x: f64 = if i == 0 { 40.0 } else { PAGE_WIDTH / 2.0 + 10.0 };
+                layer.use_text(activity.section.clone() + ":", 10.0, Mm(x), Mm(y), &bold);
+                layer.use_text(activity.activity.clone(), 10.0, Mm(x), Mm(y - 5.0), &regular);
+            }
+            y -= 13.0;
+        }
+
+        let completed: chrono::DateTime<chrono::offset::Local> = self.completed.into();
+        layer.use_text(
+            "Completed on ".to_owned() + &completed.format("%d %B %Y").to_string(),
+            11.0,
+            Mm(25.0),
+            Mm(22.0),
+            &regular,
+        );
+        let number_text: String = "Certificate No. ".to_owned() + &self.number;
+        layer.use_text(
+            number_text.clone(),
+            11.0,
+            Mm(PAGE_WIDTH - 25.0 - text_width(&number_text, 11.0)),
+            Mm(22.0),
+            &regular,
+        );
+
+        let verify_url: String = dir::make_absolute_url(&self.verify_path());
+        match qr_code(&layer, &verify_url) {
+            Ok(()) => centered_text(&layer, &("Verify at ".to_owned() + &verify_url), 8.0, 14.5, &regular),
+            Err(e) => log::warn!("Failed to create certificate QR code: {}", e),
+        }
+
+        let mut buf = BufWriter::new(Vec::new());
+        doc.save(&mut buf)?;
+        buf.into_inner()
+            .map_err(|e| printpdf::Error::Io(e.into_error()))
+    }
+}
+
+/// Approximates the width of text in the built in Helvetica fonts, which don't come with metrics.
+pub fn text_width(text: &str, font_size: f64) -> f64 {
+    let points: f64 = text.chars().count() as f64 * font_size * 0.52;
+    points * 25.4 / 72.0
+}
+
+fn centered_text(layer: &PdfLayerReference, text: &str, font_size: f64, y: f64, font: &IndirectFontRef) {
+    let x: f64 = (PAGE_WIDTH - text_width(text, font_size)) / 2.0;
+    layer.use_text(text, font_size, Mm(x), Mm(y), font);
+}
+
+/// Draws a QR code linking to `url` at the bottom of the page, one filled square per dark module.
+fn qr_code(layer: &PdfLayerReference, url: &str) -> Result<(), qrcode::types::QrError> {
+    let code = qrcode::QrCode::new(url.as_bytes())?;
+    let width: usize = code.width();
+    let module: f64 = QR_SIZE / width as f64;
+    let left: f64 = (PAGE_WIDTH - QR_SIZE) / 2.0;
+    let top: f64 = 18.0 + QR_SIZE;
+
+    for (i, color) in code.to_colors().iter().enumerate() {
+        if *color == qrcode::Color::Dark {
+            let x: f64 = left + (i % width) as f64 * module;
+            let y: f64 = top - (i / width + 1) as f64 * module;
+            layer.add_shape(Line {
+                points: vec![
+                    (Point::new(Mm(x), Mm(y)), false),
+                    (Point::new(Mm(x + module), Mm(y)), false),
+                    (Point::new(Mm(x + module), Mm(y + module)), false),
+                    (Point::new(Mm(x), Mm(y + module)), false),
+                ],
+                is_closed: true,
+                has_fill: true,
+                has_stroke: false,
+                is_clipping_path: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Loads and scales down the award icon. PDF images don't support transparency here,
+/// so the icon is drawn onto a white background.
+fn load_icon(path: &str) -> Option<Image> {
+    let icon = image::open(path).ok()?;
+    let rgba = image::imageops::resize(
+        &icon.to_rgba8(),
+        ICON_SIZE,
+        ICON_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+    let mut rgb = image::RgbImage::new(rgba.width(), rgba.height());
+    for (x, y, p) in rgba.enumerate_pixels() {
+        let a = p[3] as u32;
+        let blend = |v: u8| ((v as u32 * a + 255 * (255 - a)) / 255) as u8;
+        rgb.put_pixel(x, y, image::Rgb([blend(p[0]), blend(p[1]), blend(p[2])]));
+    }
+    Some(Image::from_dynamic_image(&image::DynamicImage::ImageRgb8(rgb)))
+}
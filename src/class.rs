@@ -0,0 +1,68 @@
+use crate::{db, org::OrgKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A group of pupils within an org, such as a form or tutor group, with the teachers responsible for it.
+/// Each pupil is in at most one class, and the class name is kept in their `UserAgent::Client` for display.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Class {
+    pub org_id: OrgKey,
+    pub name: String,
+    pub year_group: String,
+    pub associates: Vec<UserKey>,
+    pub clients: Vec<UserKey>,
+}
+
+impl Class {
+    pub fn new(org_id: OrgKey, name: String, year_group: String) -> Self {
+        Self {
+            org_id,
+            name,
+            year_group,
+            associates: Vec::new(),
+            clients: Vec::new(),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        if self.year_group.is_empty() {
+            self.name.clone()
+        } else {
+            self.name.clone() + " (" + &self.year_group + ")"
+        }
+    }
+}
+
+define_uuid_key!(ClassKey);
+
+pub type ClassDb = db::Database<ClassKey, Class>;
+
+/// The pupils assigned to each teacher individually, in addition to those in the classes they teach.
+pub type CaseloadDb = db::Database<UserKey, Vec<UserKey>>;
+
+#[derive(Debug)]
+pub enum ClassError {
+    NotClient,
+    NotAssociate,
+    NoClass,
+    NameExists,
+    DbError(db::Error),
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassError::NotClient => write!(f, "Only pupils in this organisation can be added to a class."),
+            ClassError::NotAssociate => write!(f, "Only teachers in this organisation can be assigned pupils."),
+            ClassError::NoClass => write!(f, "The class could not be found."),
+            ClassError::NameExists => write!(f, "A class with this name already exists."),
+            ClassError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<db::Error> for ClassError {
+    fn from(e: db::Error) -> Self {
+        ClassError::DbError(e)
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{db, org::OrgKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CreditTransactionKind {
+    Purchase,
+    /// A credit used to create a pupil account.
+    Consumption,
+    /// A credit returned when a pupil account is deleted.
+    Refund,
+    Adjustment,
+    Expiry,
+}
+
+impl fmt::Display for CreditTransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CreditTransactionKind::Purchase => write!(f, "Purchase"),
+            CreditTransactionKind::Consumption => write!(f, "Pupil Added"),
+            CreditTransactionKind::Refund => write!(f, "Pupil Removed"),
+            CreditTransactionKind::Adjustment => write!(f, "Adjustment"),
+            CreditTransactionKind::Expiry => write!(f, "Expiry"),
+        }
+    }
+}
+
+/// A single change to the credits of an organisation. Transactions are never changed or removed once recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditTransaction {
+    pub time: SystemTime,
+    pub kind: CreditTransactionKind,
+    pub amount: i64,
+    /// The user who made the change, if it wasn't made by the system.
+    pub actor: Option<UserKey>,
+    /// The pupil the credit was used for or returned from.
+    pub user_id: Option<UserKey>,
+    pub reason: String,
+}
+
+impl CreditTransaction {
+    pub fn new(kind: CreditTransactionKind, amount: i64, actor: Option<UserKey>, reason: String) -> Self {
+        Self {
+            time: SystemTime::now(),
+            kind,
+            amount,
+            actor,
+            user_id: None,
+            reason,
+        }
+    }
+}
+
+/// The credit transactions of each organisation, oldest first.
+pub type CreditLedgerDb = db::Database<OrgKey, Vec<CreditTransaction>>;
+
+pub fn balance(transactions: &[CreditTransaction]) -> i64 {
+    transactions.iter().map(|x| x.amount).sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CreditRequestStatus {
+    Pending,
+    Approved,
+    Declined,
+}
+
+impl fmt::Display for CreditRequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CreditRequestStatus::Pending => write!(f, "Pending"),
+            CreditRequestStatus::Approved => write!(f, "Approved"),
+            CreditRequestStatus::Declined => write!(f, "Declined"),
+        }
+    }
+}
+
+/// A request from an organisation to buy more credits, which is approved or declined by an owner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreditRequest {
+    pub org_id: OrgKey,
+    pub requested_by: UserKey,
+    pub requested: SystemTime,
+    pub quantity: u32,
+    /// The purchase order reference given by the organisation.
+    pub reference: String,
+    pub notes: String,
+    pub status: CreditRequestStatus,
+    pub decided_by: Option<UserKey>,
+    pub decided: Option<SystemTime>,
+    /// A message from the owner who approved or declined the request.
+    pub response: String,
+}
+
+impl CreditRequest {
+    pub fn new(org_id: OrgKey, requested_by: UserKey, quantity: u32, reference: String, notes: String) -> Self {
+        Self {
+            org_id,
+            requested_by,
+            requested: SystemTime::now(),
+            quantity,
+            reference,
+            notes,
+            status: CreditRequestStatus::Pending,
+            decided_by: None,
+            decided: None,
+            response: String::new(),
+        }
+    }
+}
+
+define_uuid_key!(CreditRequestKey);
+
+pub type CreditRequestDb = db::Database<CreditRequestKey, CreditRequest>;
+
+#[derive(Debug)]
+pub enum CreditError {
+    InsufficientCredits,
+    NoRequest,
+    AlreadyDecided,
+    OrgArchived,
+    DbError(db::Error),
+}
+
+impl fmt::Display for CreditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CreditError::InsufficientCredits => write!(f, "The organisation does not have enough credits."),
+            CreditError::NoRequest => write!(f, "The credit request could not be found."),
+            CreditError::AlreadyDecided => write!(f, "This credit request has already been approved or declined."),
+            CreditError::OrgArchived => write!(f, "Credits can't be added to an archived organisation."),
+            CreditError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<db::Error> for CreditError {
+    fn from(e: db::Error) -> Self {
+        CreditError::DbError(e)
+    }
+}
@@ -17,8 +17,27 @@ pub struct SharedData {
     pub login_db: login::LoginDb,
     pub user_db: user::UserDb,
     pub org_db: org::OrgDb,
+    pub org_settings_db: org::OrgSettingsDb,
+    pub org_admins_db: org::OrgAdminsDb,
+    pub org_archive_db: org::OrgArchiveDb,
+    pub org_history_db: org::OrgHistoryDb,
+    pub class_db: class::ClassDb,
+    pub caseload_db: class::CaseloadDb,
     pub section_db: section::SectionDb,
     pub outstanding_sections_db: db::Database<section::SectionKey, ()>,
+    pub section_drafts_db: db::Database<section::SectionKey, section::SectionDraft>,
+    pub trash_db: trash::TrashDb,
+    pub section_attempts_db: db::Database<section::SectionKey, Vec<section::SectionAttempt>>,
+    pub certificate_db: certificate::CertificateDb,
+    pub certificate_codes_db: certificate::CertificateCodeDb,
+    pub certificate_revocations_db: certificate::RevocationDb,
+    pub enrolments_db: enrolment::EnrolmentDb,
+    pub credit_ledger_db: credit::CreditLedgerDb,
+    pub credit_requests_db: credit::CreditRequestDb,
+    pub transfer_db: transfer::TransferDb,
+    pub archived_clients_db: rollover::ArchivedClientDb,
+    pub import_db: import::ImportDb,
+    pub erased_clients_db: erasure::ErasedClientDb,
 
     pub noreply_addr: String,
     pub mailer: Mutex<SmtpTransport>,
@@ -37,9 +56,29 @@ impl SharedData {
         let login_db = login::LoginDb::open(fs_root.clone() + "/login.sleddb")?;
         let user_db = user::UserDb::open(fs_root.clone() + "/user.sleddb")?;
         let org_db = org::OrgDb::open(fs_root.clone() + "/org.sleddb")?;
+        let org_settings_db = org::OrgSettingsDb::open(fs_root.clone() + "/org_settings.sleddb")?;
+        let org_admins_db = org::OrgAdminsDb::open(fs_root.clone() + "/org_admins.sleddb")?;
+        let org_archive_db = org::OrgArchiveDb::open(fs_root.clone() + "/org_archive.sleddb")?;
+        let org_history_db = org::OrgHistoryDb::open(fs_root.clone() + "/org_history.sleddb")?;
+        let class_db = class::ClassDb::open(fs_root.clone() + "/class.sleddb")?;
+        let caseload_db = class::CaseloadDb::open(fs_root.clone() + "/caseload.sleddb")?;
         let section_db = section::SectionDb::open(fs_root.clone() + "/section.sleddb")?;
         let outstanding_sections_db =
             db::Database::open(fs_root.clone() + "/outstanding_sections.sleddb")?;
+        let section_drafts_db = db::Database::open(fs_root.clone() + "/section_drafts.sleddb")?;
+        let trash_db = trash::TrashDb::open(fs_root.clone() + "/trash.sleddb")?;
+        let section_attempts_db = db::Database::open(fs_root.clone() + "/section_attempts.sleddb")?;
+        let certificate_db = certificate::CertificateDb::open(fs_root.clone() + "/certificate.sleddb")?;
+        let certificate_codes_db = certificate::CertificateCodeDb::open(fs_root.clone() + "/certificate_codes.sleddb")?;
+        let certificate_revocations_db =
+            certificate::RevocationDb::open(fs_root.clone() + "/certificate_revocations.sleddb")?;
+        let enrolments_db = enrolment::EnrolmentDb::open(fs_root.clone() + "/enrolments.sleddb")?;
+        let credit_ledger_db = credit::CreditLedgerDb::open(fs_root.clone() + "/credit_ledger.sleddb")?;
+        let credit_requests_db = credit::CreditRequestDb::open(fs_root.clone() + "/credit_requests.sleddb")?;
+        let transfer_db = transfer::TransferDb::open(fs_root.clone() + "/transfer.sleddb")?;
+        let archived_clients_db = rollover::ArchivedClientDb::open(fs_root.clone() + "/archived_clients.sleddb")?;
+        let import_db = import::ImportDb::open(fs_root.clone() + "/imports.sleddb")?;
+        let erased_clients_db = erasure::ErasedClientDb::open(fs_root.clone() + "/erased_clients.sleddb")?;
 
         let noreply_addr = "noreply@juniorduke.com".to_owned();
         let creds = Credentials::new(noreply_addr.clone(), "Haggis21".to_owned());
@@ -55,20 +94,42 @@ impl SharedData {
         let auth_manager = auth::AuthManager::open(fs_root.clone() + "/auth.sleddb")?;
         let link_manager = link::LinkManager::open(fs_root.clone() + "/link.sleddb")?;
 
+        // Generated archives are only needed for the duration of a request.
+        let _ = std::fs::remove_dir_all(fs_root.clone() + "/tmp");
+
         let mut handlebars = handlebars::Handlebars::new();
 
         handlebars
             .register_templates_directory(".html", "./templates".to_string())
             .unwrap();
 
-        Ok(Self {
+        let data = Self {
             fs_root,
 
             login_db,
             user_db,
             org_db,
+            org_settings_db,
+            org_admins_db,
+            org_archive_db,
+            org_history_db,
+            class_db,
+            caseload_db,
             section_db,
             outstanding_sections_db,
+            section_drafts_db,
+            trash_db,
+            section_attempts_db,
+            certificate_db,
+            certificate_codes_db,
+            certificate_revocations_db,
+            enrolments_db,
+            credit_ledger_db,
+            credit_requests_db,
+            transfer_db,
+            archived_clients_db,
+            import_db,
+            erased_clients_db,
 
             noreply_addr,
             mailer: Mutex::new(mailer),
@@ -80,7 +141,12 @@ impl SharedData {
             auth_manager,
 
             handlebars,
-        })
+        };
+        data.user_db.upgrade(|v1: user::UserV1| v1.into());
+        data.reconcile_credits();
+        data.migrate_classes();
+        data.migrate_emails();
+        Ok(data)
     }
 
     pub fn login(
@@ -89,9 +155,12 @@ impl SharedData {
         password: &str,
         timeout: Duration,
     ) -> Result<AuthContext, login::AuthError> {
-        match self.login_db.authenticate(username, password) {
+        match self.login_db.authenticate(&self.login_key(username), password) {
             Ok(user_id) => {
                 match self.user_db.fetch(&user_id) {
+                    Ok(Some(user)) if self.is_user_archived(&user.user_agent) || self.is_client_archived(&user_id) => {
+                        Err(login::AuthError::OrgArchived)
+                    }
                     Ok(Some(user)) => {
                         // Create session.
                         match self.auth_manager.create_session(&user_id, timeout) {
@@ -111,6 +180,17 @@ impl SharedData {
         }
     }
 
+    /// The login entry for an email, which is normalised unless the account has an email that couldn't be normalised
+    /// because it would be the same as another account's.
+    pub fn login_key(&self, email: &str) -> String {
+        let normalised: String = util::normalise_email(email);
+        if self.login_db.db().contains_key(&normalised).unwrap_or(false) {
+            normalised
+        } else {
+            email.trim().to_owned()
+        }
+    }
+
     pub fn logout(&self, req: &HttpRequest) -> sled::Result<()> {
         match req.cookie(dir::AUTH_COOKIE) {
             Some(auth_token) => match AuthToken::from_str(auth_token.value()) {
@@ -126,7 +206,12 @@ impl SharedData {
         user: &User,
         password: &str,
         default_password: bool,
+        actor: Option<UserKey>,
     ) -> Result<UserKey, login::LoginEntryError> {
+        let user = &User {
+            email: util::normalise_email(&user.email),
+            ..user.clone()
+        };
         match self.login_db.db().contains_key(&user.email) {
             Ok(exists) => {
                 if !exists {
@@ -142,13 +227,23 @@ impl SharedData {
                             match self.login_db.add_entry(&user.email, &login_entry) {
                                 Ok(_) => {
                                     match user.user_agent {
-                                        UserAgent::Client { org_id, .. } => {
+                                        UserAgent::Client { org_id, ref class, ref award, .. } => {
+                                            // The credit is taken first, so that a client is never added to the org without paying for it.
                                             if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
-                                                if !org.clients.contains(&user_id)
-                                                    && org.credits > 0
-                                                {
+                                                if !org.clients.contains(&user_id) {
+                                                    let mut transaction = credit::CreditTransaction::new(
+                                                        credit::CreditTransactionKind::Consumption,
+                                                        -1,
+                                                        actor,
+                                                        "Account created for ".to_owned() + &user.name(),
+                                                    );
+                                                    transaction.user_id = Some(user_id);
+                                                    if let Err(e) = self.add_credit_transaction(&org_id, &mut org, transaction) {
+                                                        let _ = self.login_db.db().remove_silent(&user.email);
+                                                        let _ = self.user_db.remove_silent(&user_id);
+                                                        return Err(login::LoginEntryError::CreditError(e));
+                                                    }
                                                     org.clients.push(user_id);
-                                                    org.credits -= 1;
                                                     if let Err(e) =
                                                         self.org_db.insert(&org_id, &org)
                                                     {
@@ -156,6 +251,18 @@ impl SharedData {
                                                     }
                                                 }
                                             }
+                                            if !class.is_empty() {
+                                                if let Err(e) = self.add_client_to_class_by_name(&org_id, &user_id, class) {
+                                                    log::error!("Failed to add new client to class! {}", e);
+                                                }
+                                            }
+                                            let enrolments = vec![enrolment::Enrolment::new(
+                                                award.clone(),
+                                                Some(std::time::SystemTime::now()),
+                                            )];
+                                            if let Err(e) = self.enrolments_db.insert(&user_id, &enrolments) {
+                                                log::error!("Failed to record enrolment for new client! {}", e);
+                                            }
                                         }
                                         UserAgent::Associate(org_id) => {
                                             if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
@@ -171,6 +278,11 @@ impl SharedData {
                                         }
                                         UserAgent::Organisation(org_id) => {
                                             if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
+                                                let mut admins = self.org_admins(&org_id, &org);
+                                                admins.push(user_id);
+                                                if let Err(e) = self.org_admins_db.insert(&org_id, &admins) {
+                                                    log::error!("Failed to update admins for new org admin! {}", e);
+                                                }
                                                 if org.admin.is_none() {
                                                     org.admin = Some(user_id);
                                                     if let Err(e) =
@@ -178,14 +290,6 @@ impl SharedData {
                                                     {
                                                         log::error!("Failed to update org db for new org admin! {}", e);
                                                     }
-                                                } else {
-                                                    let _ = self.user_db.remove_silent(&user_id);
-                                                    let _ = self
-                                                        .login_db
-                                                        .db()
-                                                        .remove_silent(&user.email);
-
-                                                    return Err(login::LoginEntryError::NotUnique);
                                                 }
                                             }
                                         }
@@ -210,6 +314,248 @@ impl SharedData {
         }
     }
 
+    /// Finds the award given in an import, by id or by name, falling back to the default award of the org.
+    fn import_award(&self, value: &str, default_award: &Option<String>) -> Option<String> {
+        if value.is_empty() {
+            return default_award.clone();
+        }
+        self.awards
+            .iter()
+            .find(|(id, award)| id.eq_ignore_ascii_case(value) || award.name.eq_ignore_ascii_case(value))
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Checks the details of a user in an import, given the emails of the valid rows before it.
+    fn check_import_user(&self, forename: &str, surname: &str, email: &str, emails: &[String]) -> Option<String> {
+        if !util::is_string_server_valid(forename) {
+            Some("Invalid forename".to_owned())
+        } else if !util::is_string_server_valid(surname) {
+            Some("Invalid surname".to_owned())
+        } else if !util::is_email_valid(email) {
+            Some("Invalid email".to_owned())
+        } else if emails.iter().any(|x| x == email) {
+            Some("Email appears earlier in the file".to_owned())
+        } else if self.login_db.db().contains_key(email).unwrap_or(true) {
+            Some("Email is associated with another account".to_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Checks the rows of a pupil import in order. Each row is checked as if the valid rows before it had already
+    /// been created, so emails repeated within the file and running out of credits are reported on the row affected.
+    pub fn check_client_import(
+        &self,
+        org_id: &org::OrgKey,
+        org: &org::Org,
+        records: &[import::Record],
+    ) -> Vec<import::CheckedRow<import::ClientRow>> {
+        let default_award = self.org_settings(org_id).default_award;
+        let mut emails: Vec<String> = Vec::new();
+        let mut credits: u32 = org.credits;
+        records
+            .iter()
+            .map(|record| {
+                let award: String = record.get("award");
+                let row = import::ClientRow {
+                    forename: record.get("forename"),
+                    surname: record.get("surname"),
+                    email: util::normalise_email(&record.get("email")),
+                    class: record.get("class"),
+                    award: self.import_award(&award, &default_award).unwrap_or_else(|| award.clone()),
+                    external_id: record.get("externalid"),
+                };
+                let problem: Option<String> = if let Some(problem) =
+                    self.check_import_user(&row.forename, &row.surname, &row.email, &emails)
+                {
+                    Some(problem)
+                } else if !util::is_optional_string_server_valid(&row.class) {
+                    Some("Invalid class name".to_owned())
+                } else if !util::is_optional_string_server_valid(&row.external_id) {
+                    Some("Invalid external id".to_owned())
+                } else if !self.awards.contains_key(&row.award) {
+                    if award.is_empty() {
+                        Some("No award given and the organisation has no default award".to_owned())
+                    } else {
+                        Some("Unknown award \"".to_owned() + &award + "\"")
+                    }
+                } else if credits == 0 {
+                    Some("Not enough credits".to_owned())
+                } else {
+                    None
+                };
+                if problem.is_none() {
+                    emails.push(row.email.clone());
+                    credits -= 1;
+                }
+                import::CheckedRow {
+                    line: record.line,
+                    row,
+                    problem,
+                }
+            })
+            .collect()
+    }
+
+    /// Checks the rows of a teacher import in order, reporting emails repeated within the file on the later row.
+    pub fn check_associate_import(&self, records: &[import::Record]) -> Vec<import::CheckedRow<import::AssociateRow>> {
+        let mut emails: Vec<String> = Vec::new();
+        records
+            .iter()
+            .map(|record| {
+                let row = import::AssociateRow {
+                    forename: record.get("forename"),
+                    surname: record.get("surname"),
+                    email: util::normalise_email(&record.get("email")),
+                };
+                let problem = self.check_import_user(&row.forename, &row.surname, &row.email, &emails);
+                if problem.is_none() {
+                    emails.push(row.email.clone());
+                }
+                import::CheckedRow {
+                    line: record.line,
+                    row,
+                    problem,
+                }
+            })
+            .collect()
+    }
+
+    /// Compares a roster with the pupils of an org. Rows are matched to pupils by external id, or by email if the
+    /// row has no external id or no pupil has it. Rows that don't match are checked as for a pupil import.
+    pub fn roster_diff(&self, org_id: &org::OrgKey, org: &org::Org, records: &[import::Record]) -> import::RosterDiff {
+        let clients: Vec<(UserKey, User)> = org
+            .clients
+            .iter()
+            .filter_map(|user_id| self.user_db.fetch(user_id).ok().flatten().map(|user| (*user_id, user)))
+            .collect();
+        let mut matched: Vec<UserKey> = Vec::new();
+        let mut unmatched: Vec<import::Record> = Vec::new();
+        let mut updates: Vec<import::RosterUpdate> = Vec::new();
+        let mut unchanged: usize = 0;
+
+        for record in records.iter() {
+            let external_id: String = record.get("external id");
+            let email: String = util::normalise_email(&record.get("email"));
+            let found = clients
+                .iter()
+                .find(|(_, user)| !external_id.is_empty() && user.external_id.as_deref() == Some(external_id.as_str()))
+                .or_else(|| {
+                    clients
+                        .iter()
+                        .find(|(_, user)| !email.is_empty() && util::normalise_email(&user.email) == email)
+                });
+            let (user_id, user) = match found {
+                Some(found) => found,
+                None => {
+                    unmatched.push(record.clone());
+                    continue;
+                }
+            };
+
+            let mut update = import::RosterUpdate {
+                line: record.line,
+                user_id: *user_id,
+                email: user.email.clone(),
+                forename: record.get("forename"),
+                surname: record.get("surname"),
+                class: if record.has("class") { Some(record.get("class")) } else { None },
+                external_id: Some(external_id).filter(|x| !x.is_empty()),
+                changes: Vec::new(),
+                problem: None,
+            };
+            if matched.contains(user_id) {
+                update.problem = Some("Pupil appears earlier in the file".to_owned());
+            } else if !util::is_string_server_valid(&update.forename) {
+                update.problem = Some("Invalid forename".to_owned());
+            } else if !util::is_string_server_valid(&update.surname) {
+                update.problem = Some("Invalid surname".to_owned());
+            } else if !update.class.as_ref().map_or(true, |x| util::is_optional_string_server_valid(x)) {
+                update.problem = Some("Invalid class name".to_owned());
+            } else if !update.external_id.as_ref().map_or(true, |x| util::is_optional_string_server_valid(x)) {
+                update.problem = Some("Invalid external id".to_owned());
+            }
+            matched.push(*user_id);
+
+            if update.forename != user.forename || update.surname != user.surname {
+                update.changes.push(format!("Name: {} to {} {}", user.name(), update.forename, update.surname));
+            }
+            if let (Some(class), UserAgent::Client { class: current, .. }) = (&update.class, &user.user_agent) {
+                if class != current {
+                    let name = |x: &str| if x.is_empty() { "No Class".to_owned() } else { x.to_owned() };
+                    update.changes.push(format!("Class: {} to {}", name(current), name(class)));
+                }
+            }
+            if update.external_id.is_some() && update.external_id != user.external_id {
+                update.changes.push("External id: ".to_owned() + update.external_id.as_deref().unwrap_or_default());
+            }
+
+            if update.changes.is_empty() && update.problem.is_none() {
+                unchanged += 1;
+            } else {
+                updates.push(update);
+            }
+        }
+
+        import::RosterDiff {
+            adds: self.check_client_import(org_id, org, &unmatched),
+            updates,
+            unchanged,
+            removals: clients
+                .iter()
+                .filter(|(user_id, _)| !matched.contains(user_id))
+                .map(|(user_id, user)| (*user_id, user.name()))
+                .collect(),
+        }
+    }
+
+    /// Applies the changes to a pupil found when syncing a roster.
+    pub fn apply_roster_update(&self, org_id: &org::OrgKey, update: &import::RosterUpdate) -> Result<(), class::ClassError> {
+        let mut user = match self.user_db.fetch(&update.user_id)? {
+            Some(user) if matches!(user.user_agent, UserAgent::Client { org_id: client_org_id, .. } if &client_org_id == org_id) => user,
+            _ => return Err(class::ClassError::NotClient),
+        };
+        user.forename = update.forename.clone();
+        user.surname = update.surname.clone();
+        if update.external_id.is_some() {
+            user.external_id = update.external_id.clone();
+        }
+        self.user_db.insert(&update.user_id, &user)?;
+
+        if let (Some(class), UserAgent::Client { class: current, .. }) = (&update.class, &user.user_agent) {
+            if class != current {
+                if class.is_empty() {
+                    self.move_client(org_id, &update.user_id, None)?;
+                } else {
+                    self.add_client_to_class_by_name(org_id, &update.user_id, class)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the email a user logs in with, returning their old email. The login entry is moved first, and is
+    /// moved back if the user can't be updated, so the email shown on an account is always the one it logs in with.
+    pub fn change_email(&self, user_id: &UserKey, email: &str) -> Result<String, login::LoginEntryError> {
+        let email: &str = &util::normalise_email(email);
+        let mut user = self
+            .user_db
+            .fetch(user_id)
+            .map_err(login::LoginEntryError::DbError)?
+            .ok_or(login::LoginEntryError::NoEntry)?;
+        let old_email: String = user.email.clone();
+        self.login_db.change_username(&old_email, email)?;
+
+        user.email = email.to_owned();
+        if let Err(e) = self.user_db.insert(user_id, &user) {
+            if let Err(e) = self.login_db.change_username(email, &old_email) {
+                log::error!("Failed to restore login for user {}: {}", user_id.to_string(), e);
+            }
+            return Err(login::LoginEntryError::DbError(e));
+        }
+        Ok(old_email)
+    }
+
     fn delete_user_entry(&self, user_id: &UserKey) -> Result<Option<User>, db::Error> {
         match self.user_db.remove(user_id) {
             Ok(Some(user)) => {
@@ -223,22 +569,184 @@ impl SharedData {
         }
     }
 
+    /// Deletes a user and scrubs the personal data held about them elsewhere, for a request for erasure.
+    /// The outcomes of a pupil's sections are kept without any of their work, so that they still count towards
+    /// the award and activity statistics. Returns true if the user existed and was erased.
+    pub fn erase_user(&self, user_id: &UserKey, actor: Option<UserKey>) -> bool {
+        let user = match self.user_db.fetch(user_id) {
+            Ok(Some(user)) => user,
+            _ => return false,
+        };
+
+        // The outcomes are kept before the user is deleted, and nothing is scrubbed until the user has been deleted,
+        // so a failure part way through never leaves the pupil counted twice or their work gone from a live account.
+        let mut section_ids: Vec<section::SectionKey> = Vec::new();
+        if let UserAgent::Client { award, sections, .. } = &user.user_agent {
+            let enrolments: Vec<enrolment::Enrolment> = self
+                .enrolments(user_id, &user)
+                .into_iter()
+                .filter(|x| !x.is_active())
+                .collect();
+            section_ids = sections.iter().chain(enrolments.iter().flat_map(|x| x.sections.iter())).flatten().copied().collect();
+            let erased = erasure::ErasedClient::new(award.clone(), *sections, enrolments);
+            if let Err(e) = self.erased_clients_db.insert(user_id, &erased) {
+                log::error!("Failed to keep the outcomes of erased client! {}", e);
+                return false;
+            }
+        }
+
+        if !self.delete_user_with_sections(user_id, actor, false) {
+            if let Err(e) = self.erased_clients_db.remove_silent(user_id) {
+                log::error!("Failed to remove the outcomes of a client that couldn't be erased! {}", e);
+            }
+            return false;
+        }
+
+        for section_id in section_ids.iter() {
+            self.anonymise_section(section_id);
+        }
+
+        let mut trashed: Vec<trash::TrashKey> = Vec::new();
+        self.trash_db.for_each(|trash_id: &trash::TrashKey, entry| {
+            if &entry.user_id == user_id {
+                trashed.push(*trash_id);
+            }
+        });
+        for trash_id in trashed.iter() {
+            self.purge_trash(trash_id);
+        }
+
+        self.auth_manager.destroy_user_sessions(user_id);
+        self.link_manager.destroy_user_links(user_id);
+        self.scrub_user_records(user_id, &user);
+        true
+    }
+
+    /// Removes the work from a section and its previous attempts, leaving the activity and whether it was completed.
+    fn anonymise_section(&self, section_id: &section::SectionKey) {
+        if let Ok(Some(mut section)) = self.section_db.fetch(section_id) {
+            section.anonymise();
+            if let Err(e) = self.section_db.insert(section_id, &section) {
+                log::error!("Failed to anonymise section! {}", e);
+            }
+        }
+        if let Ok(Some(mut attempts)) = self.section_attempts_db.fetch(section_id) {
+            for attempt in attempts.iter_mut() {
+                attempt.section.anonymise();
+            }
+            if let Err(e) = self.section_attempts_db.insert(section_id, &attempts) {
+                log::error!("Failed to anonymise section attempts! {}", e);
+            }
+        }
+        let _ = self.section_drafts_db.remove_silent(section_id);
+        // Either directory is missing if nothing was uploaded.
+        let _ = std::fs::remove_dir_all(self.section_path(section_id));
+        let _ = std::fs::remove_dir_all(self.attempts_path(section_id));
+    }
+
+    /// Replaces the name and email of an erased user in the records that mention them but have to be kept.
+    fn scrub_user_records(&self, user_id: &UserKey, user: &User) {
+        let scrub = |text: &str| -> String {
+            text.replace(&user.name(), dir::ERASED_NAME)
+                .replace(&user.email, dir::ERASED_NAME)
+        };
+
+        self.certificate_db.for_each_write(|mut certificate| {
+            if &certificate.user_id == user_id {
+                certificate.name = dir::ERASED_NAME.to_owned();
+            }
+        });
+
+        let mut org_ids: Vec<org::OrgKey> = user.user_agent.org_id().into_iter().collect();
+        self.transfer_db.for_each_write(|mut transfer| {
+            if &transfer.user_id == user_id {
+                org_ids.push(transfer.from_org);
+                org_ids.push(transfer.to_org);
+                transfer.name = dir::ERASED_NAME.to_owned();
+            }
+        });
+
+        self.credit_ledger_db.for_each_write(|mut transactions| {
+            if transactions.iter().any(|x| x.user_id.as_ref() == Some(user_id)) {
+                for transaction in transactions.iter_mut().filter(|x| x.user_id.as_ref() == Some(user_id)) {
+                    transaction.reason = scrub(&transaction.reason);
+                }
+            }
+        });
+
+        for org_id in org_ids.iter() {
+            let mut history = self.org_history(org_id);
+            if history.iter().any(|x| scrub(&x.description) != x.description) {
+                for event in history.iter_mut() {
+                    event.description = scrub(&event.description);
+                }
+                if let Err(e) = self.org_history_db.insert(org_id, &history) {
+                    log::error!("Failed to scrub org history! {}", e);
+                }
+            }
+        }
+
+        self.import_db.for_each_write(|mut import| {
+            if import.results.iter().any(|x| x.user_id.as_ref() == Some(user_id)) {
+                for result in import.results.iter_mut().filter(|x| x.user_id.as_ref() == Some(user_id)) {
+                    result.name = dir::ERASED_NAME.to_owned();
+                    result.email = String::new();
+                    result.message = scrub(&result.message);
+                }
+            }
+        });
+    }
+
     /// Deletes user even if invalid by searching login database.
     /// Returns true if the user existed and was removed.
-    pub fn delete_user(&self, user_id: &UserKey) -> bool {
+    pub fn delete_user(&self, user_id: &UserKey, actor: Option<UserKey>) -> bool {
+        self.delete_user_with_sections(user_id, actor, true)
+    }
+
+    /// Deletes a user, also deleting the sections archived with a pupil's ended enrolments if `delete_sections` is set.
+    /// Erasure keeps those sections so that their outcomes can be anonymised.
+    fn delete_user_with_sections(&self, user_id: &UserKey, actor: Option<UserKey>, delete_sections: bool) -> bool {
         match self.delete_user_entry(user_id) {
             Ok(Some(user)) => {
                 match user.user_agent {
-                    UserAgent::Client { org_id, .. } => {
+                    UserAgent::Client { org_id, sections, .. } => {
+                        if delete_sections {
+                            if let Ok(Some(enrolments)) = self.enrolments_db.fetch(user_id) {
+                                let archived = enrolments
+                                    .iter()
+                                    .filter(|x| !x.is_active())
+                                    .flat_map(|x| x.sections.iter())
+                                    .flatten()
+                                    .filter(|x| !sections.contains(&Some(**x)));
+                                for section_id in archived {
+                                    if let Err(e) = self.delete_section(section_id) {
+                                        log::error!("Failed to delete archived section of deleted client! {}", e);
+                                    }
+                                }
+                            }
+                        }
+                        let _ = self.enrolments_db.remove_silent(user_id);
+                        let _ = self.archived_clients_db.remove_silent(user_id);
+                        self.remove_from_assignments(user_id);
                         if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
                             org.clients.retain(|x| x != user_id);
-                            org.credits += 1;
+                            let mut transaction = credit::CreditTransaction::new(
+                                credit::CreditTransactionKind::Refund,
+                                1,
+                                actor,
+                                "Account deleted for ".to_owned() + &user.name(),
+                            );
+                            transaction.user_id = Some(*user_id);
+                            if let Err(e) = self.add_credit_transaction(&org_id, &mut org, transaction) {
+                                log::error!("Failed to record credit for deleted client! {}", e);
+                            }
                             if let Err(e) = self.org_db.insert(&org_id, &org) {
                                 log::error!("Failed to update org db for new client! {}", e);
                             }
                         }
                     }
                     UserAgent::Associate(org_id) => {
+                        self.remove_from_assignments(user_id);
                         if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
                             org.associates.retain(|x| x != user_id);
                             if let Err(e) = self.org_db.insert(&org_id, &org) {
@@ -248,10 +756,9 @@ impl SharedData {
                     }
                     UserAgent::Organisation(org_id) => {
                         if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
-                            if org.admin == Some(*user_id) {
-                                org.admin = None;
+                            if self.remove_org_admin_entry(&org_id, &mut org, user_id) {
                                 if let Err(e) = self.org_db.insert(&org_id, &org) {
-                                    log::error!("Failed to update org db for new associate! {}", e);
+                                    log::error!("Failed to update org db for deleted org admin! {}", e);
                                 }
                             }
                         }
@@ -272,14 +779,23 @@ impl SharedData {
                     }
                 });
 
+                self.remove_from_assignments(user_id);
                 self.org_db.for_each(|org_id, mut org| {
                     let mut changed = false;
-                    if org.admin == Some(*user_id) {
-                        org.admin = None;
-                        changed = true;
+                    if self.org_admins(org_id, &org).contains(user_id) {
+                        changed = self.remove_org_admin_entry(org_id, &mut org, user_id);
                     } else if let Some(idx) = org.clients.iter().position(|v| v == user_id) {
                         org.clients.remove(idx);
-                        org.credits += 1;
+                        let mut transaction = credit::CreditTransaction::new(
+                            credit::CreditTransactionKind::Refund,
+                            1,
+                            actor,
+                            "Account deleted".to_owned(),
+                        );
+                        transaction.user_id = Some(*user_id);
+                        if let Err(e) = self.add_credit_transaction(org_id, &mut org, transaction) {
+                            log::error!("Failed to record credit for deleted client! {}", e);
+                        }
                         changed = true;
                     } else if let Some(idx) = org.associates.iter().position(|v| v == user_id) {
                         org.associates.remove(idx);
@@ -298,17 +814,38 @@ impl SharedData {
     pub fn delete_org(&self, org_id: &org::OrgKey) -> Result<(), db::Error> {
         match self.org_db.remove(org_id) {
             Ok(Some(org)) => {
+                let admins = self.org_admins(org_id, &org);
                 for user_id in org.clients {
-                    if self.delete_user(&user_id) == false {
+                    if !self.delete_user(&user_id, None) {
                         log::error!("Failed to delete client {}", user_id.to_string());
                     }
                 }
 
                 for user_id in org.associates {
-                    if self.delete_user(&user_id) == false {
+                    if !self.delete_user(&user_id, None) {
                         log::error!("Failed to delete associate {}", user_id.to_string());
                     }
                 }
+
+                for user_id in admins {
+                    if !self.delete_user(&user_id, None) {
+                        log::error!("Failed to delete org admin {}", user_id.to_string());
+                    }
+                }
+
+                for (user_id, _) in self.archived_clients(org_id) {
+                    if !self.delete_user(&user_id, None) {
+                        log::error!("Failed to delete archived client {}", user_id.to_string());
+                    }
+                }
+                let _ = self.org_admins_db.remove_silent(org_id);
+                self.class_db.retain(false, |class| &class.org_id != org_id);
+                // The credit ledger is kept as a financial record of the org.
+                self.credit_requests_db.retain(false, |request| &request.org_id != org_id);
+                let _ = self.org_settings_db.remove_silent(org_id);
+                let _ = self.org_archive_db.remove_silent(org_id);
+                let _ = self.org_history_db.remove_silent(org_id);
+                let _ = std::fs::remove_dir_all(self.org_files_path(org_id));
                 Ok(())
             }
             Ok(None) => Ok(()),
@@ -316,6 +853,270 @@ impl SharedData {
         }
     }
 
+    pub fn is_org_archived(&self, org_id: &org::OrgKey) -> bool {
+        self.org_archive_db.contains_key(org_id).unwrap_or(false)
+    }
+
+    /// Whether a user belongs to an archived org, in which case they can't log in and their data can't be changed.
+    pub fn is_user_archived(&self, user_agent: &UserAgent) -> bool {
+        matches!(user_agent.org_id(), Some(org_id) if self.is_org_archived(&org_id))
+    }
+
+    /// Like `is_user_archived`, for when only the id of the user is known. Deleted users aren't archived.
+    pub fn is_user_id_archived(&self, user_id: &UserKey) -> bool {
+        matches!(self.user_db.fetch(user_id), Ok(Some(user)) if self.is_user_archived(&user.user_agent))
+    }
+
+    /// Archives an org instead of deleting it, so that it can be restored until `retention` has passed.
+    pub fn archive_org(&self, org_id: &org::OrgKey, archived_by: UserKey, retention: Duration) -> Result<(), db::Error> {
+        if self.org_db.fetch(org_id)?.is_some() && !self.is_org_archived(org_id) {
+            self.org_archive_db.insert(org_id, &org::ArchivedOrg::new(archived_by, retention))?;
+        }
+        Ok(())
+    }
+
+    pub fn restore_org(&self, org_id: &org::OrgKey) -> Result<(), db::Error> {
+        self.org_archive_db.remove_silent(org_id).map_err(db::Error::DbError)
+    }
+
+    /// Permanently deletes the archived orgs whose retention period has ended, returning a description of each.
+    pub fn purge_archived_orgs(&self) -> Vec<String> {
+        let now = std::time::SystemTime::now();
+        let mut expired: Vec<org::OrgKey> = Vec::new();
+        self.org_archive_db.for_each(|org_id: &org::OrgKey, archived| {
+            if now > archived.purge_after {
+                expired.push(*org_id);
+            }
+        });
+
+        let mut removed: Vec<String> = Vec::new();
+        for org_id in expired.iter() {
+            let description: String = match self.org_db.fetch(org_id) {
+                Ok(Some(org)) => format!(
+                    "{} ({} pupils, {} teachers, {} administrators, {} classes)",
+                    org.name,
+                    org.clients.len(),
+                    org.associates.len(),
+                    self.org_admins(org_id, &org).len(),
+                    self.org_classes(org_id).len(),
+                ),
+                _ => org_id.to_string(),
+            };
+            match self.delete_org(org_id) {
+                Ok(()) => removed.push(description),
+                Err(e) => log::error!("Failed to purge archived org {}: {}", description, e),
+            }
+        }
+        removed
+    }
+
+    pub fn org_history(&self, org_id: &org::OrgKey) -> Vec<org::OrgEvent> {
+        self.org_history_db.fetch(org_id).ok().flatten().unwrap_or_default()
+    }
+
+    pub fn add_org_event(&self, org_id: &org::OrgKey, event: org::OrgEvent) -> Result<(), db::Error> {
+        let mut history = self.org_history(org_id);
+        history.push(event);
+        self.org_history_db.insert(org_id, &history)
+    }
+
+    pub fn is_client_archived(&self, user_id: &UserKey) -> bool {
+        self.archived_clients_db.contains_key(user_id).unwrap_or(false)
+    }
+
+    /// Lists the pupils archived from an org, most recently archived first.
+    pub fn archived_clients(&self, org_id: &org::OrgKey) -> Vec<(UserKey, rollover::ArchivedClient)> {
+        let mut clients: Vec<(UserKey, rollover::ArchivedClient)> = Vec::new();
+        self.archived_clients_db.for_each(|user_id: &UserKey, archived| {
+            if &archived.org_id == org_id {
+                clients.push((*user_id, archived));
+            }
+        });
+        clients.sort_by_key(|x| std::cmp::Reverse(x.1.archived));
+        clients
+    }
+
+    /// Removes a pupil from their org without deleting their account or work, so that they can be restored later.
+    /// The credit used by the pupil isn't returned.
+    pub fn archive_client(&self, user_id: &UserKey, archived_by: UserKey) -> Result<(), db::Error> {
+        let org_id = match self.user_db.fetch(user_id)? {
+            Some(User {
+                user_agent: UserAgent::Client { org_id, .. },
+                ..
+            }) => org_id,
+            _ => return Ok(()),
+        };
+        self.remove_from_assignments(user_id);
+        self.set_client_class_name(user_id, "")?;
+        if let Some(mut org) = self.org_db.fetch(&org_id)? {
+            org.clients.retain(|x| x != user_id);
+            org.unreviewed_sections.retain(|section_id| {
+                !matches!(self.section_db.fetch(section_id), Ok(Some(section)) if &section.user_id == user_id)
+            });
+            self.org_db.insert(&org_id, &org)?;
+        }
+        self.archived_clients_db.insert(
+            user_id,
+            &rollover::ArchivedClient {
+                org_id,
+                archived: std::time::SystemTime::now(),
+                archived_by,
+            },
+        )
+    }
+
+    /// Returns an archived pupil to their org, without a class.
+    pub fn restore_client(&self, user_id: &UserKey) -> Result<(), db::Error> {
+        let archived = match self.archived_clients_db.fetch(user_id)? {
+            Some(archived) => archived,
+            None => return Ok(()),
+        };
+        let sections = match self.user_db.fetch(user_id)? {
+            Some(User {
+                user_agent: UserAgent::Client { sections, .. },
+                ..
+            }) => sections,
+            _ => return Ok(()),
+        };
+        if let Some(mut org) = self.org_db.fetch(&archived.org_id)? {
+            if !org.clients.contains(user_id) {
+                org.clients.push(*user_id);
+            }
+            for section_id in sections.iter().flatten() {
+                if let Some(section) = self.section_db.fetch(section_id)? {
+                    if matches!(section.state, section::SectionState::InReview(_))
+                        && !org.unreviewed_sections.contains(section_id)
+                    {
+                        org.unreviewed_sections.push(*section_id);
+                    }
+                }
+            }
+            self.org_db.insert(&archived.org_id, &org)?;
+        }
+        self.archived_clients_db.remove_silent(user_id).map_err(db::Error::DbError)
+    }
+
+    /// Whether a pupil has completed every section of their current award.
+    pub fn is_client_completed(&self, sections: &[Option<section::SectionKey>; 6]) -> bool {
+        sections.iter().all(|section_id| match section_id {
+            Some(section_id) => matches!(self.section_db.fetch(section_id), Ok(Some(section)) if section.state.is_completed()),
+            None => false,
+        })
+    }
+
+    /// Closes out the year for an org. Certificates are issued to every pupil who has completed their award, then
+    /// each group of pupils is archived, deleted or moved to a new class as planned. The result is added to the history of the org.
+    pub fn rollover_org(
+        &self,
+        org_id: &org::OrgKey,
+        actor: UserKey,
+        plan: &rollover::RolloverPlan,
+    ) -> Result<rollover::RolloverSummary, db::Error> {
+        let mut summary = rollover::RolloverSummary::default();
+        let org = match self.org_db.fetch(org_id)? {
+            Some(org) => org,
+            None => return Ok(summary),
+        };
+        let classes = self.org_classes(org_id);
+
+        // The pupils of every group are found before anything changes, so moved pupils aren't processed twice.
+        let mut groups: Vec<(&rollover::GroupPlan, Vec<UserKey>)> = Vec::new();
+        for group in plan.groups.iter() {
+            let members: Vec<UserKey> = match &group.class_id {
+                Some(class_id) => match classes.iter().find(|(id, _)| id == class_id) {
+                    Some((_, class)) => class.clients.clone(),
+                    None => continue,
+                },
+                None => org
+                    .clients
+                    .iter()
+                    .filter(|x| !classes.iter().any(|(_, class)| class.clients.contains(x)))
+                    .copied()
+                    .collect(),
+            };
+            groups.push((group, members));
+        }
+
+        // The event is recorded even if the rollover stops part way through, so the history shows what was changed.
+        let result = self.rollover_clients(org_id, actor, plan, &groups, &mut summary);
+        let description: String = match &result {
+            Ok(()) => summary.to_string(),
+            Err(e) => format!("{} The rollover stopped part way through: {}", summary, e),
+        };
+        self.add_org_event(org_id, org::OrgEvent::new(Some(actor), description))?;
+        result.map(|()| summary)
+    }
+
+    /// Carries out the plan of a rollover for each group of pupils, counting the changes made in `summary`.
+    fn rollover_clients(
+        &self,
+        org_id: &org::OrgKey,
+        actor: UserKey,
+        plan: &rollover::RolloverPlan,
+        groups: &[(&rollover::GroupPlan, Vec<UserKey>)],
+        summary: &mut rollover::RolloverSummary,
+    ) -> Result<(), db::Error> {
+        for (group, members) in groups.iter() {
+            for user_id in members.iter() {
+                let completed: bool = match self.user_db.fetch(user_id)? {
+                    Some(User {
+                        user_agent: UserAgent::Client { sections, .. },
+                        ..
+                    }) => self.is_client_completed(&sections),
+                    _ => continue,
+                };
+                if completed && self.issue_certificate(user_id)?.is_some() {
+                    summary.certified += 1;
+                }
+                match if completed { group.completed } else { group.incomplete } {
+                    rollover::ClientAction::Keep => {
+                        let target = match group.move_to {
+                            rollover::ClassTarget::Stay => continue,
+                            rollover::ClassTarget::NoClass => None,
+                            rollover::ClassTarget::Class(class_id) => Some(class_id),
+                        };
+                        if target == group.class_id {
+                            continue;
+                        }
+                        match self.move_client(org_id, user_id, target.as_ref()) {
+                            Ok(()) => summary.moved += 1,
+                            Err(e) => log::error!("Failed to move client during rollover: {}", e),
+                        }
+                    }
+                    rollover::ClientAction::Archive => {
+                        self.archive_client(user_id, actor)?;
+                        summary.archived += 1;
+                    }
+                    rollover::ClientAction::Delete => {
+                        if self.delete_user(user_id, Some(actor)) {
+                            summary.deleted += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        if plan.expire_credits {
+            if let Some(mut org) = self.org_db.fetch(org_id)? {
+                if org.credits > 0 {
+                    summary.expired_credits = org.credits;
+                    let transaction = credit::CreditTransaction::new(
+                        credit::CreditTransactionKind::Expiry,
+                        -(org.credits as i64),
+                        Some(actor),
+                        "Unused credits expired at year rollover".to_owned(),
+                    );
+                    if let Err(e) = self.add_credit_transaction(org_id, &mut org, transaction) {
+                        log::error!("Failed to expire credits during rollover: {}", e);
+                    }
+                    self.org_db.insert(org_id, &org)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn authenticate_context_from_request(
         &self,
         req: &HttpRequest,
@@ -337,6 +1138,8 @@ impl SharedData {
     ) -> Result<Option<AuthContext>, db::Error> {
         match self.auth_manager.check_token(&auth_token, push_expiry) {
             Ok(Some(user_id)) => match self.user_db.fetch(&user_id) {
+                // Users of an org that has been archived since they logged in are treated as logged out.
+                Ok(Some(user)) if self.is_user_archived(&user.user_agent) || self.is_client_archived(&user_id) => Ok(None),
                 Ok(Some(user)) => Ok(Some(AuthContext {
                     auth_token,
                     user,
@@ -362,15 +1165,76 @@ impl SharedData {
     }
 
     pub fn delete_section(&self, section_id: &section::SectionKey) -> Result<(), db::Error> {
-        match self.section_db.remove(section_id) {
-            Ok(Some(section)) => {
-                // Delete assets of section
-                let path = self.section_path(section_id);
-                if let Err(e) = std::fs::remove_dir_all(path) {
-                    log::error!("Failed to remove assets dir: {}", e);
+        if self.detach_section(section_id)?.is_some() {
+            // Delete assets of section
+            let path = self.section_path(section_id);
+            if let Err(e) = std::fs::remove_dir_all(path) {
+                log::error!("Failed to remove assets dir: {}", e);
+            }
+            self.delete_section_attempts(section_id);
+        }
+        Ok(())
+    }
+
+    fn delete_section_attempts(&self, section_id: &section::SectionKey) {
+        if let Ok(Some(_)) = self.section_attempts_db.remove(section_id) {
+            if let Err(e) = std::fs::remove_dir_all(self.attempts_path(section_id)) {
+                log::error!("Failed to remove attempts dir: {}", e);
+            }
+        }
+    }
+
+    /// Archives the current attempt at a section along with its assets, and starts a new attempt with the given activity.
+    pub fn change_section_activity(
+        &self,
+        section_id: &section::SectionKey,
+        section: section::Section,
+        activity: String,
+        changed_by: UserKey,
+    ) -> Result<(), section::SectionError> {
+        let mut attempts: Vec<section::SectionAttempt> = self.section_attempts_db.fetch(section_id)?.unwrap_or_default();
+
+        let path = self.section_path(section_id);
+        if std::path::Path::new(&path).exists() {
+            std::fs::create_dir_all(self.attempts_path(section_id))?;
+            std::fs::rename(&path, self.attempt_path(section_id, attempts.len()))?;
+        }
+
+        let new_section = section::Section::new(section.section_index, section.award.clone(), activity, section.user_id);
+
+        // The archived attempt no longer needs reviewing.
+        if let section::SectionState::InReview(_) = section.state {
+            if let Ok(Some(user)) = self.user_db.fetch(&section.user_id) {
+                if let Some(org_id) = user.user_agent.org_id() {
+                    if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
+                        org.unreviewed_sections.retain(|x| x != section_id);
+                        if let Err(e) = self.org_db.insert(&org_id, &org) {
+                            log::error!("Failed to update org: {}", e);
+                        }
+                    }
                 }
+            }
+        }
+        let _ = self.outstanding_sections_db.remove_silent(section_id);
+        let _ = self.section_drafts_db.remove_silent(section_id);
+
+        attempts.push(section::SectionAttempt {
+            section,
+            archived: std::time::SystemTime::now(),
+            archived_by: changed_by,
+        });
+        self.section_attempts_db.insert(section_id, &attempts)?;
+        self.section_db.insert(section_id, &new_section)?;
+        Ok(())
+    }
 
+    /// Removes the section from the database and from the pupil and org that refer to it.
+    /// The assets of the section are left on disk.
+    fn detach_section(&self, section_id: &section::SectionKey) -> Result<Option<section::Section>, db::Error> {
+        match self.section_db.remove(section_id) {
+            Ok(Some(section)) => {
                 let _ = self.outstanding_sections_db.remove_silent(section_id);
+                let _ = self.section_drafts_db.remove_silent(section_id);
 
                 if let Ok(Some(mut client)) = self.user_db.fetch(&section.user_id) {
                     let mut changed = false;
@@ -405,13 +1269,533 @@ impl SharedData {
                         }
                     }
                 }
-                Ok(())
+                Ok(Some(section))
             }
-            Ok(None) => Ok(()),
+            Ok(None) => Ok(None),
             Err(e) => Err(e),
         }
     }
 
+    /// Lists the classes of an org, ordered by year group and then name.
+    pub fn org_classes(&self, org_id: &org::OrgKey) -> Vec<(class::ClassKey, class::Class)> {
+        let mut classes = Vec::new();
+        self.class_db.for_each(|class_id: &class::ClassKey, class| {
+            if &class.org_id == org_id {
+                classes.push((*class_id, class));
+            }
+        });
+        classes.sort_by(|a, b| (&a.1.year_group, &a.1.name).cmp(&(&b.1.year_group, &b.1.name)));
+        classes
+    }
+
+    pub fn client_class(&self, org_id: &org::OrgKey, user_id: &UserKey) -> Option<(class::ClassKey, class::Class)> {
+        self.org_classes(org_id)
+            .into_iter()
+            .find(|(_, class)| class.clients.contains(user_id))
+    }
+
+    pub fn create_class(
+        &self,
+        org_id: &org::OrgKey,
+        name: &str,
+        year_group: &str,
+    ) -> Result<class::ClassKey, class::ClassError> {
+        if self.org_classes(org_id).iter().any(|(_, x)| x.name == name) {
+            return Err(class::ClassError::NameExists);
+        }
+        let class_id = class::ClassKey::generate();
+        self.class_db.insert(
+            &class_id,
+            &class::Class::new(*org_id, name.to_owned(), year_group.to_owned()),
+        )?;
+        Ok(class_id)
+    }
+
+    /// Updates the details and teachers of a class. If the class is renamed its pupils are updated to match.
+    pub fn update_class(
+        &self,
+        class_id: &class::ClassKey,
+        name: &str,
+        year_group: &str,
+        associates: Vec<UserKey>,
+    ) -> Result<(), class::ClassError> {
+        let mut class = self.class_db.fetch(class_id)?.ok_or(class::ClassError::NoClass)?;
+        if class.name != name {
+            if self
+                .org_classes(&class.org_id)
+                .iter()
+                .any(|(id, x)| id != class_id && x.name == name)
+            {
+                return Err(class::ClassError::NameExists);
+            }
+            for user_id in class.clients.iter() {
+                self.set_client_class_name(user_id, name)?;
+            }
+        }
+        class.name = name.to_owned();
+        class.year_group = year_group.to_owned();
+        class.associates = associates;
+        self.class_db.insert(class_id, &class)?;
+        Ok(())
+    }
+
+    /// Deletes a class. Its pupils are kept, but are no longer in a class.
+    pub fn delete_class(&self, class_id: &class::ClassKey) -> Result<(), class::ClassError> {
+        let class = self.class_db.remove(class_id)?.ok_or(class::ClassError::NoClass)?;
+        for user_id in class.clients.iter() {
+            self.set_client_class_name(user_id, "")?;
+        }
+        Ok(())
+    }
+
+    /// Moves a pupil into a class, or out of their current class if `to` is `None`.
+    pub fn move_client(
+        &self,
+        org_id: &org::OrgKey,
+        user_id: &UserKey,
+        to: Option<&class::ClassKey>,
+    ) -> Result<(), class::ClassError> {
+        match self.user_db.fetch(user_id)? {
+            Some(User {
+                user_agent: UserAgent::Client { org_id: client_org_id, .. },
+                ..
+            }) if &client_org_id == org_id => {}
+            _ => return Err(class::ClassError::NotClient),
+        }
+        let target = match to {
+            Some(class_id) => match self.class_db.fetch(class_id)? {
+                Some(class) if &class.org_id == org_id => Some((*class_id, class)),
+                _ => return Err(class::ClassError::NoClass),
+            },
+            None => None,
+        };
+
+        self.remove_from_classes(user_id);
+        match target {
+            Some((class_id, mut class)) => {
+                class.clients.retain(|x| x != user_id);
+                class.clients.push(*user_id);
+                self.class_db.insert(&class_id, &class)?;
+                self.set_client_class_name(user_id, &class.name)?;
+            }
+            None => self.set_client_class_name(user_id, "")?,
+        }
+        Ok(())
+    }
+
+    fn add_client_to_class_by_name(
+        &self,
+        org_id: &org::OrgKey,
+        user_id: &UserKey,
+        name: &str,
+    ) -> Result<(), class::ClassError> {
+        let class_id = match self.org_classes(org_id).into_iter().find(|(_, x)| x.name == name) {
+            Some((class_id, _)) => class_id,
+            None => self.create_class(org_id, name, "")?,
+        };
+        self.move_client(org_id, user_id, Some(&class_id))
+    }
+
+    /// Removes a pupil or teacher from every class they are in.
+    fn remove_from_classes(&self, user_id: &UserKey) {
+        self.class_db.for_each_write(|mut class| {
+            if class.clients.contains(user_id) || class.associates.contains(user_id) {
+                class.clients.retain(|x| x != user_id);
+                class.associates.retain(|x| x != user_id);
+            }
+        });
+    }
+
+    /// Removes a pupil or teacher from every class and caseload, for when they are deleted.
+    fn remove_from_assignments(&self, user_id: &UserKey) {
+        self.remove_from_classes(user_id);
+        let _ = self.caseload_db.remove_silent(user_id);
+        self.caseload_db.for_each_write(|mut caseload| {
+            if caseload.contains(user_id) {
+                caseload.retain(|x| x != user_id);
+            }
+        });
+    }
+
+    /// The pupils a teacher is responsible for, through their classes or assigned to them individually.
+    pub fn assigned_clients(&self, associate_id: &UserKey, org_id: &org::OrgKey) -> Vec<UserKey> {
+        let mut clients: Vec<UserKey> = self.caseload_db.fetch(associate_id).ok().flatten().unwrap_or_default();
+        for (_, class) in self.org_classes(org_id) {
+            if class.associates.contains(associate_id) {
+                clients.extend(class.clients.iter().filter(|x| !clients.contains(x)).copied().collect::<Vec<_>>());
+            }
+        }
+        clients
+    }
+
+    /// Assigns a pupil to a teacher individually, or removes the assignment, checking both are in the org.
+    pub fn set_caseload_assignment(
+        &self,
+        org_id: &org::OrgKey,
+        associate_id: &UserKey,
+        user_id: &UserKey,
+        assigned: bool,
+    ) -> Result<(), class::ClassError> {
+        match self.user_db.fetch(associate_id)? {
+            Some(User {
+                user_agent: UserAgent::Associate(associate_org_id),
+                ..
+            }) if &associate_org_id == org_id => {}
+            _ => return Err(class::ClassError::NotAssociate),
+        }
+        match self.user_db.fetch(user_id)? {
+            Some(User {
+                user_agent: UserAgent::Client { org_id: client_org_id, .. },
+                ..
+            }) if &client_org_id == org_id => {}
+            _ => return Err(class::ClassError::NotClient),
+        }
+        let mut caseload: Vec<UserKey> = self.caseload_db.fetch(associate_id)?.unwrap_or_default();
+        caseload.retain(|x| x != user_id);
+        if assigned {
+            caseload.push(*user_id);
+        }
+        self.caseload_db.insert(associate_id, &caseload)?;
+        Ok(())
+    }
+
+    /// Whether the user of a context can view another user. This is `UserAgent::can_view_user`, except that
+    /// teachers in orgs that restrict them can only view the pupils assigned to them.
+    pub fn can_view_user(&self, ctx: &AuthContext, user_id: &UserKey, user: &User) -> bool {
+        if !ctx.user.user_agent.can_view_user(&user.user_agent) {
+            return false;
+        }
+        match self.associate_restriction(ctx) {
+            Some(clients) if user.user_agent.is_client() => clients.contains(user_id),
+            _ => true,
+        }
+    }
+
+    /// The only pupils the user of a context may see, if they are a teacher in an org that restricts teachers to their own pupils.
+    pub fn associate_restriction(&self, ctx: &AuthContext) -> Option<Vec<UserKey>> {
+        match ctx.user.user_agent {
+            UserAgent::Associate(org_id) if self.org_settings(&org_id).restrict_associates => {
+                Some(self.assigned_clients(&ctx.user_id, &org_id))
+            }
+            _ => None,
+        }
+    }
+
+    fn set_client_class_name(&self, user_id: &UserKey, name: &str) -> Result<(), db::Error> {
+        if let Some(mut user) = self.user_db.fetch(user_id)? {
+            if let UserAgent::Client { ref mut class, .. } = user.user_agent {
+                *class = name.to_owned();
+                self.user_db.insert(user_id, &user)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Groups the accounts whose emails are the same once normalised, which can't be normalised without merging them.
+    pub fn email_collisions(&self) -> Vec<Vec<(UserKey, User)>> {
+        let mut users: HashMap<String, Vec<(UserKey, User)>> = HashMap::new();
+        self.user_db.for_each(|user_id: &UserKey, user| {
+            users.entry(util::normalise_email(&user.email)).or_default().push((*user_id, user));
+        });
+        users.into_values().filter(|x| x.len() > 1).collect()
+    }
+
+    /// Normalises the emails of accounts created before emails were normalised. Accounts whose emails would become the
+    /// same are left unchanged and reported, so that they can be merged by hand.
+    fn migrate_emails(&self) {
+        let collisions = self.email_collisions();
+        for group in collisions.iter() {
+            let emails: Vec<&str> = group.iter().map(|(_, user)| user.email.as_str()).collect();
+            log::warn!("Accounts need to be merged as their emails are the same once normalised: {}", emails.join(", "));
+        }
+
+        let mut unnormalised: Vec<(UserKey, String)> = Vec::new();
+        self.user_db.for_each(|user_id: &UserKey, user| {
+            let email: String = util::normalise_email(&user.email);
+            if email != user.email && !collisions.iter().flatten().any(|(x, _)| x == user_id) {
+                unnormalised.push((*user_id, email));
+            }
+        });
+        for (user_id, email) in unnormalised {
+            if let Err(e) = self.change_email(&user_id, &email) {
+                log::error!("Failed to normalise email of user {}: {}", user_id.to_string(), e);
+            }
+        }
+    }
+
+    /// Creates classes for pupils added before classes were introduced, from the class name they were given.
+    fn migrate_classes(&self) {
+        let mut members: Vec<UserKey> = Vec::new();
+        self.class_db.for_each_val(|class| members.extend(class.clients));
+
+        let mut unassigned: Vec<(org::OrgKey, UserKey, String)> = Vec::new();
+        self.user_db.for_each(|user_id: &UserKey, user| {
+            if let UserAgent::Client { org_id, class, .. } = user.user_agent {
+                if !class.trim().is_empty() && !members.contains(user_id) {
+                    unassigned.push((org_id, *user_id, class.trim().to_owned()));
+                }
+            }
+        });
+
+        for (org_id, user_id, name) in unassigned {
+            if let Err(e) = self.add_client_to_class_by_name(&org_id, &user_id, &name) {
+                log::error!("Failed to add client {} to class {}: {}", user_id.to_string(), name, e);
+            }
+        }
+    }
+
+    /// Lists the admins of an org, starting with the primary contact.
+    pub fn org_admins(&self, org_id: &org::OrgKey, org: &org::Org) -> Vec<UserKey> {
+        let mut admins: Vec<UserKey> = self.org_admins_db.fetch(org_id).ok().flatten().unwrap_or_default();
+        if let Some(primary) = org.admin {
+            admins.retain(|x| x != &primary);
+            admins.insert(0, primary);
+        }
+        admins
+    }
+
+    /// Whether a user is the only admin left in their org, in which case they shouldn't be removed.
+    pub fn is_last_org_admin(&self, user_id: &UserKey, user_agent: &UserAgent) -> bool {
+        match user_agent {
+            UserAgent::Organisation(org_id) => match self.org_db.fetch(org_id) {
+                Ok(Some(org)) => self.org_admins(org_id, &org) == [*user_id],
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Removes a user from the admins of an org, making the next admin the primary contact if needed.
+    /// Returns true if the org itself was changed and needs saving.
+    fn remove_org_admin_entry(&self, org_id: &org::OrgKey, org: &mut org::Org, user_id: &UserKey) -> bool {
+        let mut admins = self.org_admins(org_id, org);
+        admins.retain(|x| x != user_id);
+        if let Err(e) = self.org_admins_db.insert(org_id, &admins) {
+            log::error!("Failed to update admins for deleted org admin! {}", e);
+        }
+        if org.admin == Some(*user_id) {
+            org.admin = admins.first().copied();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The settings of an org, or the defaults if they have never been changed.
+    pub fn org_settings(&self, org_id: &org::OrgKey) -> org::OrgSettings {
+        self.org_settings_db.fetch(org_id).ok().flatten().unwrap_or_default()
+    }
+
+    pub fn org_files_path(&self, org_id: &org::OrgKey) -> String {
+        format!("{}/orgs/{}", &self.fs_root, org_id.to_string())
+    }
+
+    pub fn org_logo_path(&self, org_id: &org::OrgKey) -> String {
+        self.org_files_path(org_id) + "/logo.png"
+    }
+
+    /// Stores an uploaded logo as a PNG, scaled down to fit within `dir::ORG_LOGO_SIZE`.
+    pub fn set_org_logo(&self, org_id: &org::OrgKey, bytes: &[u8]) -> image::ImageResult<()> {
+        let logo = image::load_from_memory(bytes)?;
+        let logo = if logo.width() > dir::ORG_LOGO_SIZE || logo.height() > dir::ORG_LOGO_SIZE {
+            logo.thumbnail(dir::ORG_LOGO_SIZE, dir::ORG_LOGO_SIZE)
+        } else {
+            logo
+        };
+        std::fs::create_dir_all(self.org_files_path(org_id))?;
+        logo.save_with_format(self.org_logo_path(org_id), image::ImageFormat::Png)
+    }
+
+    pub fn trash_item_path(&self, trash_id: &trash::TrashKey) -> String {
+        self.fs_root.clone() + "/trash/" + &trash_id.to_string()
+    }
+
+    /// Moves a section and its assets into the trash, freeing up the pupil's slot for that section.
+    /// The section is only detached from the pupil once it is safely in the trash, and anything already moved is put
+    /// back if a later step fails, so the work is never left without either a section or a trash entry.
+    pub fn trash_section(&self, section_id: &section::SectionKey, deleted_by: UserKey) -> Result<(), trash::TrashError> {
+        let section = match self.section_db.fetch(section_id)? {
+            Some(section) => section,
+            None => return Ok(()),
+        };
+        let trash_id = trash::TrashKey::generate();
+        let path = self.section_path(section_id);
+        let trash_path = self.trash_item_path(&trash_id);
+        let moved: bool = std::path::Path::new(&path).exists();
+        if moved {
+            std::fs::create_dir_all(self.fs_root.clone() + "/trash")?;
+            std::fs::rename(&path, &trash_path)?;
+        }
+        let restore_files = || {
+            if moved {
+                if let Err(e) = std::fs::rename(&trash_path, &path) {
+                    log::error!("Failed to move section assets back out of the trash: {}", e);
+                }
+            }
+        };
+
+        let entry = trash::TrashEntry::new(
+            section.user_id,
+            deleted_by,
+            trash::TrashItem::Section {
+                section_id: *section_id,
+                section,
+            },
+        );
+        if let Err(e) = self.trash_db.insert(&trash_id, &entry) {
+            restore_files();
+            return Err(e.into());
+        }
+        if let Err(e) = self.detach_section(section_id) {
+            let _ = self.trash_db.remove_silent(&trash_id);
+            restore_files();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Moves an uploaded file of a section into the trash.
+    pub fn trash_asset(&self, section_id: &section::SectionKey, filename: &str, deleted_by: UserKey) -> Result<(), trash::TrashError> {
+        match self.section_db.fetch(section_id)? {
+            Some(section) => {
+                let filename: String = sanitize_filename::sanitize(filename);
+                let trash_id = trash::TrashKey::generate();
+                std::fs::create_dir_all(self.fs_root.clone() + "/trash")?;
+                let path = self.path_for_asset(section_id, &filename);
+                std::fs::rename(&path, self.trash_item_path(&trash_id))?;
+                let entry = trash::TrashEntry::new(
+                    section.user_id,
+                    deleted_by,
+                    trash::TrashItem::Asset {
+                        section_id: *section_id,
+                        filename,
+                    },
+                );
+                if let Err(e) = self.trash_db.insert(&trash_id, &entry) {
+                    if let Err(e) = std::fs::rename(self.trash_item_path(&trash_id), &path) {
+                        log::error!("Failed to move asset back out of the trash: {}", e);
+                    }
+                    return Err(e.into());
+                }
+                Ok(())
+            }
+            None => Err(trash::TrashError::NoSection),
+        }
+    }
+
+    /// Moves an item in the trash back to where it was deleted from.
+    pub fn restore_trash(&self, trash_id: &trash::TrashKey) -> Result<trash::TrashEntry, trash::TrashError> {
+        let entry = match self.trash_db.fetch(trash_id)? {
+            Some(entry) => entry,
+            None => return Err(trash::TrashError::NoEntry),
+        };
+        let trash_path = self.trash_item_path(trash_id);
+        match &entry.item {
+            trash::TrashItem::Asset { section_id, filename } => {
+                match self.section_db.fetch(section_id)? {
+                    Some(section) if section.state.is_completed() => return Err(trash::TrashError::SectionCompleted),
+                    Some(_) => {}
+                    None => return Err(trash::TrashError::NoSection),
+                }
+                std::fs::create_dir_all(self.section_path(section_id))?;
+
+                // Don't overwrite a file that has been uploaded with the same name since.
+                let path = std::path::Path::new(filename);
+                let mut restored_path = self.path_for_asset(section_id, filename);
+                let mut i: i32 = 0;
+                while std::path::Path::new(&restored_path).exists() {
+                    let restored_name = path.file_stem().and_then(|x| x.to_str()).unwrap_or("file").to_owned()
+                        + &i.to_string()
+                        + "."
+                        + path.extension().and_then(|x| x.to_str()).unwrap_or("");
+                    restored_path = self.path_for_asset(section_id, &restored_name);
+                    i += 1;
+                }
+                std::fs::rename(&trash_path, &restored_path)?;
+            }
+            trash::TrashItem::Section { section_id, section } => {
+                let mut client = match self.user_db.fetch(&section.user_id)? {
+                    Some(client) => client,
+                    None => return Err(trash::TrashError::NoEntry),
+                };
+                if let UserAgent::Client { org_id, award, sections, .. } = &mut client.user_agent {
+                    let org_id = *org_id;
+                    if award != &section.award {
+                        return Err(trash::TrashError::AwardChanged);
+                    }
+                    match sections.get_mut(section.section_index) {
+                        Some(slot @ None) => *slot = Some(*section_id),
+                        _ => return Err(trash::TrashError::SectionInUse),
+                    }
+                    if std::path::Path::new(&trash_path).exists() {
+                        std::fs::rename(&trash_path, self.section_path(section_id))?;
+                    }
+                    self.section_db.insert(section_id, section)?;
+                    self.user_db.insert(&section.user_id, &client)?;
+
+                    if section.outstanding {
+                        let _ = self.outstanding_sections_db.insert(section_id, &());
+                    }
+                    if let section::SectionState::InReview(_) = section.state {
+                        if let Ok(Some(mut org)) = self.org_db.fetch(&org_id) {
+                            org.unreviewed_sections.push(*section_id);
+                            if let Err(e) = self.org_db.insert(&org_id, &org) {
+                                log::error!("Failed to update org: {}", e);
+                            }
+                        }
+                    }
+                } else {
+                    return Err(trash::TrashError::NoEntry);
+                }
+            }
+        }
+        let _ = self.trash_db.remove_silent(trash_id);
+        Ok(entry)
+    }
+
+    /// Permanently deletes an item in the trash.
+    pub fn purge_trash(&self, trash_id: &trash::TrashKey) {
+        if let Ok(Some(trash::TrashEntry {
+            item: trash::TrashItem::Section { section_id, .. },
+            ..
+        })) = self.trash_db.fetch(trash_id)
+        {
+            self.delete_section_attempts(&section_id);
+        }
+        let path = self.trash_item_path(trash_id);
+        let p = std::path::Path::new(&path);
+        let result = if p.is_dir() {
+            std::fs::remove_dir_all(p)
+        } else if p.exists() {
+            std::fs::remove_file(p)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            log::error!("Failed to remove trash item {}: {}", &path, e);
+        }
+        if let Err(e) = self.trash_db.remove_silent(trash_id) {
+            log::error!("Failed to remove trash entry: {}", e);
+        }
+    }
+
+    pub fn purge_expired_trash(&self) {
+        let now = std::time::SystemTime::now();
+        let mut expired: Vec<trash::TrashKey> = Vec::new();
+        self.trash_db.for_each(|trash_id: &trash::TrashKey, entry| {
+            // Archived orgs are kept exactly as they were until they are restored or purged themselves.
+            if entry.is_expired(now) && !self.is_user_id_archived(&entry.user_id) {
+                expired.push(*trash_id);
+            }
+        });
+        for trash_id in expired.iter() {
+            self.purge_trash(trash_id);
+        }
+        if !expired.is_empty() {
+            log::trace!("Purged {} expired items from the trash", expired.len());
+        }
+    }
+
     pub fn nav_items_for_context(&self, ctx: Option<AuthContext>) -> Vec<(String, String)> {
         match ctx {
             Some(ctx) => self.nav_items_for_agent(&ctx.user_id, &ctx.user.user_agent),
@@ -484,6 +1868,24 @@ impl SharedData {
         format!("{}/sections/{}/", &self.fs_root, section_id.to_string())
     }
 
+    pub fn attempts_path(&self, section_id: &section::SectionKey) -> String {
+        format!("{}/attempts/{}/", &self.fs_root, section_id.to_string())
+    }
+
+    pub fn attempt_path(&self, section_id: &section::SectionKey, attempt: usize) -> String {
+        format!("{}/attempts/{}/{}", &self.fs_root, section_id.to_string(), attempt)
+    }
+
+    pub fn path_for_attempt_asset(&self, section_id: &section::SectionKey, attempt: usize, filename: &str) -> String {
+        format!(
+            "{}/attempts/{}/{}/{}",
+            &self.fs_root,
+            section_id.to_string(),
+            attempt,
+            filename
+        )
+    }
+
     pub fn path_for_asset(&self, section_id: &section::SectionKey, filename: &str) -> String {
         format!(
             "{}/sections/{}/{}",
@@ -493,6 +1895,481 @@ impl SharedData {
         )
     }
 
+    /// Finds the certificate issued to a pupil for an award. Revoked certificates are skipped, so that a corrected one
+    /// can be issued in their place.
+    pub fn certificate_for_user(
+        &self,
+        user_id: &UserKey,
+        award: &str,
+    ) -> Option<(certificate::CertificateKey, certificate::Certificate)> {
+        let mut result = None;
+        self.certificate_db.for_each(|certificate_id: &certificate::CertificateKey, certificate| {
+            if &certificate.user_id == user_id
+                && certificate.award == award
+                && !self.certificate_revocations_db.contains_key(certificate_id).unwrap_or(false)
+            {
+                result = Some((*certificate_id, certificate));
+            }
+        });
+        result
+    }
+
+    /// Whether a pupil has ever been issued a certificate for an award, including one that has since been revoked.
+    pub fn has_certificate(&self, user_id: &UserKey, award: &str) -> bool {
+        let mut result = false;
+        self.certificate_db.for_each(|_, certificate| {
+            if &certificate.user_id == user_id && certificate.award == award {
+                result = true;
+            }
+        });
+        result
+    }
+
+    /// Issues a certificate to the pupil if they have completed every section of their award and haven't been issued one
+    /// already. A revoked certificate still counts, so that revocation isn't undone the next time certificates are issued.
+    pub fn issue_certificate(&self, user_id: &UserKey) -> Result<Option<certificate::CertificateKey>, db::Error> {
+        self.issue_certificate_with(user_id, false)
+    }
+
+    /// Issues a corrected certificate to a pupil whose certificate has been revoked.
+    pub fn reissue_certificate(&self, user_id: &UserKey) -> Result<Option<certificate::CertificateKey>, db::Error> {
+        self.issue_certificate_with(user_id, true)
+    }
+
+    fn issue_certificate_with(&self, user_id: &UserKey, reissue: bool) -> Result<Option<certificate::CertificateKey>, db::Error> {
+        let user = match self.user_db.fetch(user_id)? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        if let UserAgent::Client { org_id, award, sections, .. } = &user.user_agent {
+            let issued: bool = if reissue {
+                self.certificate_for_user(user_id, award).is_some()
+            } else {
+                self.has_certificate(user_id, award)
+            };
+            if issued {
+                return Ok(None);
+            }
+            let award_info = match self.awards.get(award) {
+                Some(award_info) => award_info,
+                None => return Ok(None),
+            };
+
+            let mut activities: Vec<certificate::CertificateActivity> = Vec::new();
+            for (i, section_id) in sections.iter().enumerate() {
+                match section_id {
+                    Some(section_id) => match self.section_db.fetch(section_id)? {
+                        Some(section) if section.state.is_completed() => {
+                            let section_info = &award_info.sections[i];
+                            activities.push(certificate::CertificateActivity {
+                                section: section_info.name.clone(),
+                                activity: section_info
+                                    .activities
+                                    .get(&section.activity)
+                                    .map(|x| x.name.clone())
+                                    .unwrap_or(section.activity),
+                            });
+                        }
+                        _ => return Ok(None),
+                    },
+                    None => return Ok(None),
+                }
+            }
+
+            let org_name: String = match self.org_db.fetch(org_id)? {
+                Some(org) => org.name,
+                None => String::new(),
+            };
+
+            let certificate_id = certificate::CertificateKey::generate();
+            // Numbers are random, so on the rare clash with an existing certificate another is generated.
+            let mut number: String = certificate::Certificate::generate_number();
+            while !self.certificate_codes_db.insert_new(&number, &certificate_id)? {
+                number = certificate::Certificate::generate_number();
+            }
+            self.certificate_db.insert(
+                &certificate_id,
+                &certificate::Certificate {
+                    number,
+                    user_id: *user_id,
+                    name: user.name(),
+                    award: award.clone(),
+                    award_name: award_info.name.clone(),
+                    org_name,
+                    completed: std::time::SystemTime::now(),
+                    activities,
+                },
+            )?;
+            Ok(Some(certificate_id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Looks up a certificate by the number printed on it.
+    pub fn find_certificate(
+        &self,
+        number: &str,
+    ) -> Result<Option<(certificate::CertificateKey, certificate::Certificate)>, db::Error> {
+        let number: String = certificate::Certificate::normalise_number(number);
+        if let Some(certificate_id) = self.certificate_codes_db.fetch(&number)? {
+            if let Some(certificate) = self.certificate_db.fetch(&certificate_id)? {
+                return Ok(Some((certificate_id, certificate)));
+            }
+        }
+
+        // Certificates issued before the registry existed aren't indexed yet.
+        let mut result = None;
+        self.certificate_db.for_each(|certificate_id: &certificate::CertificateKey, certificate| {
+            if certificate.number == number {
+                result = Some((*certificate_id, certificate));
+            }
+        });
+        if let Some((certificate_id, _)) = &result {
+            self.certificate_codes_db.insert(&number, certificate_id)?;
+        }
+        Ok(result)
+    }
+
+    pub fn revoke_certificate(
+        &self,
+        certificate_id: &certificate::CertificateKey,
+        reason: &str,
+        revoked_by: &UserKey,
+    ) -> Result<(), db::Error> {
+        self.certificate_revocations_db.insert(
+            certificate_id,
+            &certificate::Revocation {
+                revoked: std::time::SystemTime::now(),
+                revoked_by: *revoked_by,
+                reason: reason.to_owned(),
+            },
+        )
+    }
+
+    pub fn credit_transactions(&self, org_id: &org::OrgKey) -> Result<Vec<credit::CreditTransaction>, db::Error> {
+        Ok(self.credit_ledger_db.fetch(org_id)?.unwrap_or_default())
+    }
+
+    /// Appends a transaction to the credit ledger of an org and updates the balance stored on the org.
+    /// The org itself isn't saved, so that callers can save it along with their own changes.
+    pub fn add_credit_transaction(
+        &self,
+        org_id: &org::OrgKey,
+        org: &mut org::Org,
+        transaction: credit::CreditTransaction,
+    ) -> Result<(), credit::CreditError> {
+        let mut transactions = self.credit_transactions(org_id)?;
+        let balance: i64 = credit::balance(&transactions) + transaction.amount;
+        if balance < 0 {
+            return Err(credit::CreditError::InsufficientCredits);
+        }
+        transactions.push(transaction);
+        self.credit_ledger_db.insert(org_id, &transactions)?;
+        org.credits = balance as u32;
+        Ok(())
+    }
+
+    /// Lists credit requests, newest first. If `org_id` is given only the requests of that org are included.
+    pub fn credit_requests(
+        &self,
+        org_id: Option<&org::OrgKey>,
+    ) -> Vec<(credit::CreditRequestKey, credit::CreditRequest)> {
+        let mut requests = Vec::new();
+        self.credit_requests_db.for_each(|request_id: &credit::CreditRequestKey, request| {
+            if org_id.is_none() || org_id == Some(&request.org_id) {
+                requests.push((*request_id, request));
+            }
+        });
+        requests.sort_by_key(|x| std::cmp::Reverse(x.1.requested));
+        requests
+    }
+
+    /// Approves or declines a pending credit request. Approved requests are added to the org's ledger as a purchase.
+    pub fn decide_credit_request(
+        &self,
+        request_id: &credit::CreditRequestKey,
+        approve: bool,
+        decided_by: &UserKey,
+        response: &str,
+    ) -> Result<credit::CreditRequest, credit::CreditError> {
+        let pending = self
+            .credit_requests_db
+            .fetch(request_id)?
+            .ok_or(credit::CreditError::NoRequest)?;
+        if pending.status != credit::CreditRequestStatus::Pending {
+            return Err(credit::CreditError::AlreadyDecided);
+        }
+        if approve && self.is_org_archived(&pending.org_id) {
+            return Err(credit::CreditError::OrgArchived);
+        }
+
+        let mut request = pending.clone();
+        request.status = if approve {
+            credit::CreditRequestStatus::Approved
+        } else {
+            credit::CreditRequestStatus::Declined
+        };
+        request.decided_by = Some(*decided_by);
+        request.decided = Some(std::time::SystemTime::now());
+        request.response = response.to_owned();
+        // The request is marked as decided before any credits are added, so if two owners approve it at the same
+        // time only one of them gets past here.
+        if !self.credit_requests_db.compare_and_swap(request_id, Some(&pending), &request)? {
+            return Err(credit::CreditError::AlreadyDecided);
+        }
+
+        if approve {
+            let mut org = match self.org_db.fetch(&request.org_id) {
+                Ok(Some(org)) => org,
+                Ok(None) => {
+                    self.credit_requests_db.insert(request_id, &pending)?;
+                    return Err(credit::CreditError::NoRequest);
+                }
+                Err(e) => {
+                    self.credit_requests_db.insert(request_id, &pending)?;
+                    return Err(e.into());
+                }
+            };
+            let mut reason: String = "Credit request".to_owned();
+            if !request.reference.is_empty() {
+                reason += &(" - ".to_owned() + &request.reference);
+            }
+            let transaction = credit::CreditTransaction::new(
+                credit::CreditTransactionKind::Purchase,
+                request.quantity as i64,
+                Some(*decided_by),
+                reason,
+            );
+            if let Err(e) = self.add_credit_transaction(&request.org_id, &mut org, transaction) {
+                // Put the request back so that it can be approved again.
+                self.credit_requests_db.insert(request_id, &pending)?;
+                return Err(e);
+            }
+            self.org_db.insert(&request.org_id, &org)?;
+        }
+        Ok(request)
+    }
+
+    /// Records the credits of orgs from before the ledger existed as an opening balance,
+    /// and makes sure the balance stored on each org matches its ledger.
+    fn reconcile_credits(&self) {
+        self.org_db.for_each(|org_id: &org::OrgKey, mut org| match self.credit_transactions(org_id) {
+            Ok(transactions) if transactions.is_empty() => {
+                if org.credits > 0 {
+                    let opening = credit::CreditTransaction::new(
+                        credit::CreditTransactionKind::Adjustment,
+                        org.credits as i64,
+                        None,
+                        "Opening balance".to_owned(),
+                    );
+                    if let Err(e) = self.credit_ledger_db.insert(org_id, &vec![opening]) {
+                        log::error!("Failed to record opening balance: {}", e);
+                    }
+                }
+            }
+            Ok(transactions) => {
+                let balance: u32 = credit::balance(&transactions).max(0) as u32;
+                if org.credits != balance {
+                    log::warn!("Credits of {} did not match its ledger, correcting.", &org.name);
+                    org.credits = balance;
+                    if let Err(e) = self.org_db.insert(org_id, &org) {
+                        log::error!("Failed to update org: {}", e);
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to read credit ledger: {}", e),
+        });
+    }
+
+    /// Moves a pupil and all of their work to another org. A credit is used by the new org and returned to the old one.
+    /// The pupil is removed from their class and any teacher assignments, and their sections awaiting review move with them.
+    pub fn transfer_client(
+        &self,
+        user_id: &UserKey,
+        to_org_id: &org::OrgKey,
+        actor: UserKey,
+    ) -> Result<(), transfer::TransferError> {
+        let mut user = self.user_db.fetch(user_id)?.ok_or(transfer::TransferError::NotClient)?;
+        let from_org_id = match &mut user.user_agent {
+            UserAgent::Client { org_id, class, .. } => {
+                if org_id == to_org_id {
+                    return Err(transfer::TransferError::SameOrg);
+                }
+                let from_org_id = *org_id;
+                *org_id = *to_org_id;
+                class.clear();
+                from_org_id
+            }
+            _ => return Err(transfer::TransferError::NotClient),
+        };
+        if self.is_org_archived(&from_org_id) || self.is_org_archived(to_org_id) {
+            return Err(transfer::TransferError::Archived);
+        }
+        let old_from_org = self.org_db.fetch(&from_org_id)?.ok_or(transfer::TransferError::NoOrg)?;
+        let old_to_org = self.org_db.fetch(to_org_id)?.ok_or(transfer::TransferError::NoOrg)?;
+        // Nothing changes if the new org doesn't have a credit to spare.
+        if credit::balance(&self.credit_transactions(to_org_id)?) < 1 {
+            return Err(credit::CreditError::InsufficientCredits.into());
+        }
+        let mut from_org = old_from_org.clone();
+        let mut to_org = old_to_org.clone();
+
+        from_org.clients.retain(|x| x != user_id);
+        if !to_org.clients.contains(user_id) {
+            to_org.clients.push(*user_id);
+        }
+        let (moved, kept): (Vec<section::SectionKey>, Vec<section::SectionKey>) =
+            from_org.unreviewed_sections.iter().partition(|section_id| {
+                matches!(self.section_db.fetch(section_id), Ok(Some(section)) if &section.user_id == user_id)
+            });
+        from_org.unreviewed_sections = kept;
+        to_org.unreviewed_sections.extend(moved);
+
+        // The pupil is moved between the orgs in one write, and the orgs are put back if the pupil can't be updated,
+        // so they are never left in both orgs or neither.
+        self.org_db.insert_all(&[(&from_org_id, &from_org), (to_org_id, &to_org)])?;
+        if let Err(e) = self.user_db.insert(user_id, &user) {
+            if let Err(e) = self.org_db.insert_all(&[(&from_org_id, &old_from_org), (to_org_id, &old_to_org)]) {
+                log::error!("Failed to restore orgs after a failed transfer! {}", e);
+            }
+            return Err(e.into());
+        }
+        self.remove_from_assignments(user_id);
+
+        // The credits are only moved once the pupil has been.
+        let mut transaction = credit::CreditTransaction::new(
+            credit::CreditTransactionKind::Consumption,
+            -1,
+            Some(actor),
+            user.name() + " transferred from " + &from_org.name,
+        );
+        transaction.user_id = Some(*user_id);
+        if let Err(e) = self.add_credit_transaction(to_org_id, &mut to_org, transaction) {
+            log::error!("Failed to charge credit for transferred client! {}", e);
+        }
+        let mut transaction = credit::CreditTransaction::new(
+            credit::CreditTransactionKind::Refund,
+            1,
+            Some(actor),
+            user.name() + " transferred to " + &to_org.name,
+        );
+        transaction.user_id = Some(*user_id);
+        if let Err(e) = self.add_credit_transaction(&from_org_id, &mut from_org, transaction) {
+            log::error!("Failed to record credit for transferred client! {}", e);
+        }
+        self.org_db.insert_all(&[(&from_org_id, &from_org), (to_org_id, &to_org)])?;
+
+        let record = transfer::Transfer::new(*user_id, user.name(), from_org_id, *to_org_id, actor);
+        self.transfer_db.insert(&transfer::TransferKey::generate(), &record)?;
+        Ok(())
+    }
+
+    /// Lists the transfers of a pupil between orgs, newest first.
+    pub fn client_transfers(&self, user_id: &UserKey) -> Vec<transfer::Transfer> {
+        let mut transfers: Vec<transfer::Transfer> = Vec::new();
+        self.transfer_db.for_each_val(|transfer| {
+            if &transfer.user_id == user_id {
+                transfers.push(transfer);
+            }
+        });
+        transfers.sort_by(|a, b| b.time.cmp(&a.time));
+        transfers
+    }
+
+    /// Lists the awards a pupil has been enrolled on, oldest first. The last one is their current award.
+    pub fn enrolments(&self, user_id: &UserKey, user: &User) -> Vec<enrolment::Enrolment> {
+        let mut enrolments: Vec<enrolment::Enrolment> = match self.enrolments_db.fetch(user_id) {
+            Ok(Some(enrolments)) => enrolments,
+            _ => Vec::new(),
+        };
+        // Pupils created before enrolments were recorded have no entry for their current award.
+        if let UserAgent::Client { award, .. } = &user.user_agent {
+            if !enrolments.iter().any(|x| x.is_active()) {
+                enrolments.push(enrolment::Enrolment::new(award.clone(), None));
+            }
+        }
+        enrolments
+    }
+
+    /// Ends the pupil's current enrolment and enrols them on a different award.
+    /// If `carry_sections` is set, sections which haven't been completed are moved to the new award when it offers the same activity.
+    /// All other sections are archived with the old enrolment.
+    pub fn change_award(
+        &self,
+        user_id: &UserKey,
+        new_award: &str,
+        carry_sections: bool,
+    ) -> Result<(), enrolment::EnrolmentError> {
+        let award_info = self.awards.get(new_award).ok_or(enrolment::EnrolmentError::InvalidAward)?;
+        let mut user = self.user_db.fetch(user_id)?.ok_or(enrolment::EnrolmentError::NotClient)?;
+        match &user.user_agent {
+            UserAgent::Client { award, .. } if award == new_award => return Err(enrolment::EnrolmentError::SameAward),
+            UserAgent::Client { .. } => {}
+            _ => return Err(enrolment::EnrolmentError::NotClient),
+        }
+
+        // Pupils moving on after finishing an award get their certificate first.
+        self.issue_certificate(user_id)?;
+
+        let mut enrolments = self.enrolments(user_id, &user);
+        let now = std::time::SystemTime::now();
+        if let UserAgent::Client { org_id, award, sections, .. } = &mut user.user_agent {
+            let mut archived: [Option<section::SectionKey>; 6] = Default::default();
+            let mut completed: usize = 0;
+            for (i, slot) in sections.iter_mut().enumerate() {
+                if let Some(section_id) = *slot {
+                    if let Some(mut section) = self.section_db.fetch(&section_id)? {
+                        if section.state.is_completed() {
+                            completed += 1;
+                        } else if carry_sections
+                            && matches!(award_info.sections.get(i), Some(x) if x.activities.contains_key(&section.activity))
+                        {
+                            section.award = new_award.to_owned();
+                            self.section_db.insert(&section_id, &section)?;
+                            continue;
+                        }
+                        archived[i] = Some(section_id);
+                        let _ = self.outstanding_sections_db.remove_silent(&section_id);
+                        let _ = self.section_drafts_db.remove_silent(&section_id);
+                    }
+                    *slot = None;
+                }
+            }
+
+            if let Some(mut org) = self.org_db.fetch(org_id)? {
+                let len = org.unreviewed_sections.len();
+                org.unreviewed_sections.retain(|x| !archived.contains(&Some(*x)));
+                if len != org.unreviewed_sections.len() {
+                    self.org_db.insert(org_id, &org)?;
+                }
+            }
+
+            if let Some(current) = enrolments.iter_mut().find(|x| x.is_active()) {
+                current.ended = Some(now);
+                current.status = if completed == archived.len() {
+                    enrolment::EnrolmentStatus::Completed
+                } else {
+                    enrolment::EnrolmentStatus::Withdrawn
+                };
+                current.sections = archived;
+            }
+            enrolments.push(enrolment::Enrolment::new(new_award.to_owned(), Some(now)));
+            *award = new_award.to_owned();
+        }
+
+        self.user_db.insert(user_id, &user)?;
+        self.enrolments_db.insert(user_id, &enrolments)?;
+        Ok(())
+    }
+
+    /// Returns a unique path in the temp directory for generated files such as archives.
+    pub fn temp_path(&self, extension: &str) -> std::io::Result<String> {
+        let dir: String = format!("{}/tmp", &self.fs_root);
+        std::fs::create_dir_all(&dir)?;
+        Ok(format!("{}/{}.{}", dir, uuid::Uuid::new_v4(), extension))
+    }
+
     pub fn send_email(
         &self,
         address: &str,
@@ -529,33 +2406,57 @@ impl SharedData {
 
     pub fn get_activity_stats(&self) -> Stats {
         let mut stats: Stats = Stats::new(&self.awards);
-        self.user_db.for_each_val(|user| {
+        self.user_db.for_each(|user_id: &UserKey, user| {
             if let UserAgent::Client { award, sections, .. } = user.user_agent {
-                if let Some(aw) = stats.awards.get_mut(&award) {
-                    aw.total += 1;
-                    let mut completed = 0;
-                    for section_id in sections.iter() {
-                        if let Some(section_id) = section_id {
-                            if let Ok(Some(section)) = self.section_db.fetch(section_id) {
-                                aw.sections[section.section_index].total += 1;
-                                aw.sections[section.section_index].increment(&section.activity, section.state.is_completed());
-                                if section.state.is_completed() {
-                                    completed += 1;
-                                }
-                            }
-                        }
-                    }
+                self.add_enrolment_stats(&mut stats, &award, &sections);
 
-                    // Completed the entire award.
-                    if completed == sections.len() {
-                        aw.completed += 1;
+                // Awards the pupil was previously enrolled on.
+                if let Ok(Some(enrolments)) = self.enrolments_db.fetch(user_id) {
+                    for enrolment in enrolments.iter().filter(|x| !x.is_active()) {
+                        self.add_enrolment_stats(&mut stats, &enrolment.award, &enrolment.sections);
                     }
                 }
             }
         });
 
+        // Pupils whose data was erased still count towards the statistics.
+        self.erased_clients_db.for_each_val(|erased| {
+            self.add_enrolment_stats(&mut stats, &erased.award, &erased.sections);
+            for enrolment in erased.enrolments.iter() {
+                self.add_enrolment_stats(&mut stats, &enrolment.award, &enrolment.sections);
+            }
+        });
+
         stats
     }
+
+    fn add_enrolment_stats(&self, stats: &mut Stats, award: &str, sections: &[Option<section::SectionKey>; 6]) {
+        if let Some(aw) = stats.awards.get_mut(award) {
+            aw.total += 1;
+            let mut completed = 0;
+            for section_id in sections.iter() {
+                if let Some(section_id) = section_id {
+                    if let Ok(Some(section)) = self.section_db.fetch(section_id) {
+                        aw.sections[section.section_index].total += 1;
+                        aw.sections[section.section_index].increment(&section.activity, section.state.is_completed());
+                        if let Ok(Some(attempts)) = self.section_attempts_db.fetch(section_id) {
+                            for attempt in attempts.iter() {
+                                aw.sections[section.section_index].increment_switch(&attempt.section.activity);
+                            }
+                        }
+                        if section.state.is_completed() {
+                            completed += 1;
+                        }
+                    }
+                }
+            }
+
+            // Completed the entire award.
+            if completed == sections.len() {
+                aw.completed += 1;
+            }
+        }
+    }
 }
 
 pub struct Stats {
@@ -593,6 +2494,8 @@ impl AwardPoint {
 
 pub struct SectionPoint {
     pub total: usize,
+    /// The number of times pupils have changed from one activity to another.
+    pub switches: usize,
     pub activities: HashMap<String, ActivityPoint>,
 }
 
@@ -600,6 +2503,7 @@ impl SectionPoint {
     pub fn new(section_info: &SectionInfo) -> Self {
         SectionPoint {
             total: 0,
+            switches: 0,
             activities: section_info.activities.iter().map(|(k, _)| (k.clone(), ActivityPoint::new())).collect(),
         }
     }
@@ -613,6 +2517,14 @@ impl SectionPoint {
         }
     }
 
+    /// Records a pupil switching away from the given activity.
+    pub fn increment_switch(&mut self, activity: &str) {
+        self.switches += 1;
+        if let Some(p) = self.activities.get_mut(activity) {
+            p.switched_away += 1;
+        }
+    }
+
     pub fn completions(&self) -> usize {
         self.activities.iter().map(|(_, v)| v.completed).sum()
     }
@@ -621,6 +2533,7 @@ impl SectionPoint {
 pub struct ActivityPoint {
     pub selected: usize,
     pub completed: usize,
+    pub switched_away: usize,
 }
 
 impl ActivityPoint {
@@ -628,6 +2541,7 @@ impl ActivityPoint {
         Self {
             selected: 0,
             completed: 0,
+            switched_away: 0,
         }
     }
 }
\ No newline at end of file
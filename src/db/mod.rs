@@ -99,6 +99,16 @@ impl<K: AsRef<[u8]> + ?Sized, V: Serialize + DeserializeOwned> Database<K, V> {
         }
     }
 
+    /// Inserts several values at once, so that either all of them are written or none are.
+    pub fn insert_all(&self, values: &[(&K, &V)]) -> Result<(), Error> {
+        let mut batch = sled::Batch::default();
+        for (key, value) in values.iter() {
+            let bytes = bincode::serialize(value).map_err(Error::SerializeError)?;
+            batch.insert(key.as_ref(), bytes);
+        }
+        self.db.apply_batch(batch).map_err(Error::DbError)
+    }
+
     pub fn insert_raw(&self, key_bytes: &sled::IVec, value: &V) -> Result<(), Error> {
         match bincode::serialize(value) {
             Ok(bytes) => {
@@ -132,6 +142,26 @@ impl<K: AsRef<[u8]> + ?Sized, V: Serialize + DeserializeOwned> Database<K, V> {
         }
     }
 
+    /// Replaces `old` with `new` only if the stored value is still `old`, returning false if it has been changed since it
+    /// was fetched. `None` means there was no value. Unlike a `WriteGuard` this is atomic, so it can be used to make sure
+    /// only one request acts on a value. Values are compared serialised, so `V` shouldn't contain a `HashMap`.
+    pub fn compare_and_swap(&self, key: &K, old: Option<&V>, new: &V) -> Result<bool, Error> {
+        let old_bytes = match old {
+            Some(old) => Some(bincode::serialize(old).map_err(Error::SerializeError)?),
+            None => None,
+        };
+        let new_bytes = bincode::serialize(new).map_err(Error::SerializeError)?;
+        match self.db.compare_and_swap(key, old_bytes, Some(new_bytes)) {
+            Ok(result) => Ok(result.is_ok()),
+            Err(e) => Err(Error::DbError(e)),
+        }
+    }
+
+    /// Inserts `value` only if there is no value for `key` yet, returning false if the key is already taken.
+    pub fn insert_new(&self, key: &K, value: &V) -> Result<bool, Error> {
+        self.compare_and_swap(key, None, value)
+    }
+
     pub fn write_lock<'db>(&'db self, key: &K) -> Result<Option<WriteGuard<'db, K, V>>, Error> {
         WriteGuard::lock(self, key)
     }
@@ -286,6 +316,22 @@ impl<K: AsRef<[u8]> + ?Sized, V: Serialize + DeserializeOwned> Database<K, V> {
         }
     }
 
+    /// Converts values stored in an older format to the current type.
+    /// Unlike `migrate` this leaves values that are already in the current format untouched, so it is safe to run on every startup.
+    pub fn upgrade<F, O: DeserializeOwned>(&self, f: F)
+    where
+        F: Fn(O) -> V,
+    {
+        for (key, bytes) in self.db.iter().flatten() {
+            if bincode::deserialize::<V>(&bytes).is_ok() {
+                continue;
+            }
+            if let Ok(old) = bincode::deserialize::<O>(&bytes) {
+                let _ = self.insert_raw(&key, &f(old));
+            }
+        }
+    }
+
     pub fn migrate<F, OwnedKey, O: DeserializeOwned>(&self, f: F)
     where
         K: ToOwned<Owned = OwnedKey>,
@@ -9,6 +9,7 @@ pub const AUTH_COOKIE: &'static str = "Auth";
 pub const LOGIN_REDIRECT_COOKIE: &'static str = "LoginRedirect";
 pub const ASSIGN_ADMIN_LINK_TIMEOUT_SECS: u64 = 60 * 60 * 24 * 5;
 pub const CHANE_PASSWORD_LINK_TIMEOUT_SECS: u64 = 60 * 60 * 24 * 5;
+pub const CHANGE_EMAIL_LINK_TIMEOUT_SECS: u64 = 60 * 60 * 24;
 
 pub const EXTENDED_APP_NAME: &'static str = "Senior Duke Portal";
 pub const APP_NAME: &'static str = "Senior Duke";
@@ -17,6 +18,9 @@ pub const USER_ROOT_PATH: &'static str = "/user";
 
 pub const LINK_BASE_PATH: &'static str = "/link";
 pub const CHANGE_PASSWORD_PATH: &'static str = "/user/change_password";
+pub const CONFIRM_EMAIL_PATH: &'static str = "/user/confirm_email";
+pub const EDIT_PROFILE_PATH: &'static str = "/edit";
+pub const EXPORT_USER_DATA_PATH: &'static str = "/export";
 
 pub fn user_path(user_id: user::UserKey) -> String {
     USER_ROOT_PATH.to_owned() + "/" + &user_id.to_string()
@@ -55,15 +59,36 @@ pub const DOWNLOAD_LOG_PATH: &'static str = "/admin/log";
 pub const ACCOUNTS_PATH: &'static str = "/admin/accounts";
 pub const ACCOUNTS_TITLE: &'static str = "User Accounts";
 pub const ADD_ADMIN_PATH: &'static str = "/admin/add_admin";
+pub const CERTIFICATES_PATH: &'static str = "/admin/certificates";
+pub const CERTIFICATES_TITLE: &'static str = "Certificates";
+pub const CREDIT_REQUESTS_PATH: &'static str = "/admin/credit_requests";
+pub const CREDIT_REQUESTS_TITLE: &'static str = "Credit Requests";
+pub const ORG_SETTINGS_PATH: &'static str = "/settings";
+pub const ORG_SETTINGS_TITLE: &'static str = "Settings";
+pub const ORG_LOGO_PATH: &'static str = "/logo";
+pub const ORG_ADMINS_PAGE: &'static str = "/admins";
+pub const ORG_ADMINS_TITLE: &'static str = "Administrators";
 
 pub const ADD_ORG_PATH: &'static str = "/add_org";
-pub const DELETE_ORG_PATH: &'static str = "/del_org";
+pub const ARCHIVE_ORG_PATH: &'static str = "/archive_org";
+pub const RESTORE_ORG_PATH: &'static str = "/restore_org";
+pub const ORG_ARCHIVE_RETENTION_DAYS: u64 = 365;
+pub const MAX_ORG_ARCHIVE_RETENTION_DAYS: u64 = 3650;
 pub const ADD_CREDITS_PATH: &'static str = "/add_credits";
+pub const CREDITS_PATH: &'static str = "/credits";
+pub const CREDITS_TITLE: &'static str = "Credit Statement";
 pub const ASSIGN_ADMIN_PATH: &'static str = "/assign_admin";
 
 pub const CLIENTS_PAGE: &'static str = "/clients";
 pub const CLIENTS_TITLE: &'static str = "Pupils";
 pub const ADD_CLIENT_PATH: &'static str = "/add_client";
+pub const IMPORT_CLIENTS_PATH: &'static str = "/import/pupils";
+pub const IMPORT_CLIENTS_TITLE: &'static str = "Import Pupils";
+pub const IMPORT_ASSOCIATES_PATH: &'static str = "/import/teachers";
+pub const IMPORT_ASSOCIATES_TITLE: &'static str = "Import Teachers";
+pub const IMPORT_ROSTER_PATH: &'static str = "/import/roster";
+pub const IMPORT_ROSTER_TITLE: &'static str = "Sync Roster";
+pub const IMPORTS_PATH: &'static str = "/imports";
 
 pub const SECTION_ROOT: &'static str = "/section";
 pub const SELECT_ACTIVITY_PATH: &'static str = "/select_activity";
@@ -71,11 +96,28 @@ pub const SELECT_ACTIVITY_PATH: &'static str = "/select_activity";
 pub const ASSOCIATES_PAGE: &'static str = "/associates";
 pub const ASSOCIATES_TITLE: &'static str = "Teachers";
 
+pub fn associate_path(org_id: org::OrgKey, user_id: user::UserKey) -> String {
+    org_path(org_id) + ASSOCIATES_PAGE + "/" + &user_id.to_string()
+}
+
 pub const UNREVIEWED_SECTIONS_PAGE: &'static str = "/unreviewed";
 pub const UNREVIEWED_SECTIONS_TITLE: &'static str = "Unreviewed Sections";
 
 pub const ADD_ASSOCIATE_PATH: &'static str = "/add_associate";
 
+// Class
+pub const CLASSES_PAGE: &'static str = "/classes";
+pub const CLASSES_TITLE: &'static str = "Classes";
+pub const CLASS_ROOT_PATH: &'static str = "/class";
+pub const MOVE_CLIENT_PATH: &'static str = "/move_client";
+
+pub const ROLLOVER_PAGE: &'static str = "/rollover";
+pub const ROLLOVER_TITLE: &'static str = "Year Rollover";
+
+pub fn class_path(org_id: org::OrgKey, class_id: class::ClassKey) -> String {
+    org_path(org_id) + CLASS_ROOT_PATH + "/" + &class_id.to_string()
+}
+
 // Client
 pub const CLIENT_ROOT_PATH: &'static str = "/client";
 
@@ -83,9 +125,35 @@ pub fn client_path(org_id: org::OrgKey, user_id: user::UserKey) -> String {
     org_path(org_id) + CLIENT_ROOT_PATH + "/" + &user_id.to_string()
 }
 
+pub fn import_path(org_id: org::OrgKey, import_id: import::ImportKey) -> String {
+    org_path(org_id) + IMPORTS_PATH + "/" + &import_id.to_string()
+}
+
+pub const DOWNLOAD_PATH: &'static str = "/download";
+pub const PORTFOLIO_PATH: &'static str = "/portfolio";
+pub const AWARDS_PATH: &'static str = "/awards";
+pub const AWARDS_TITLE: &'static str = "Awards";
+pub const TRANSFER_PATH: &'static str = "/transfer";
+pub const TRANSFER_TITLE: &'static str = "Transfer";
+pub const TRASH_PATH: &'static str = "/trash";
+pub const TRASH_TITLE: &'static str = "Deleted Items";
+pub const TRASH_RETENTION_DAYS: u64 = 30;
+
+pub const VERIFY_PATH: &'static str = "/verify";
+pub const VERIFY_TITLE: &'static str = "Verify a Certificate";
+
 pub const SECTIONS_TITLE: &'static str = "Sections";
 
 pub const NOTIFICATION_INTERVAL_DAYS: u64 = 3;
+pub const MAX_NOTIFICATION_INTERVAL_DAYS: u64 = 30;
+pub const DEFAULT_TIMEZONE: &str = "Europe/London";
+pub const EMAIL_LOCAL_PART: util::EmailLocalPart = util::EmailLocalPart::Lowercase;
+/// Shown in place of the name of a user whose data has been erased.
+pub const ERASED_NAME: &str = "Erased User";
+/// The largest logo (in pixels) that is stored. Bigger images are scaled down on upload.
+pub const ORG_LOGO_SIZE: u32 = 400;
+pub const MAX_ORG_LOGO_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_IMPORT_BYTES: usize = 1024 * 1024;
 
 pub const HELP_PAGE: &'static str = "/help";
 pub const HELP_TITLE: &'static str = "Help";
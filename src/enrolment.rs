@@ -0,0 +1,78 @@
+use crate::{db, section::SectionKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EnrolmentStatus {
+    Active,
+    Completed,
+    Withdrawn,
+}
+
+impl fmt::Display for EnrolmentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnrolmentStatus::Active => write!(f, "In Progress"),
+            EnrolmentStatus::Completed => write!(f, "Completed"),
+            EnrolmentStatus::Withdrawn => write!(f, "Withdrawn"),
+        }
+    }
+}
+
+/// A period in which a pupil was working towards an award.
+/// While an enrolment is active the pupil's sections live in their `UserAgent::Client`; once it ends
+/// the sections that weren't carried over to the next award are kept here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enrolment {
+    pub award: String,
+    /// Not known for pupils who were enrolled before enrolments were recorded.
+    pub started: Option<SystemTime>,
+    pub ended: Option<SystemTime>,
+    pub status: EnrolmentStatus,
+    pub sections: [Option<SectionKey>; 6],
+}
+
+impl Enrolment {
+    pub fn new(award: String, started: Option<SystemTime>) -> Self {
+        Self {
+            award,
+            started,
+            ended: None,
+            status: EnrolmentStatus::Active,
+            sections: Default::default(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == EnrolmentStatus::Active
+    }
+}
+
+/// The enrolments of each pupil, oldest first.
+pub type EnrolmentDb = db::Database<UserKey, Vec<Enrolment>>;
+
+#[derive(Debug)]
+pub enum EnrolmentError {
+    NotClient,
+    InvalidAward,
+    SameAward,
+    DbError(db::Error),
+}
+
+impl fmt::Display for EnrolmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnrolmentError::NotClient => write!(f, "Only pupils can be enrolled on an award."),
+            EnrolmentError::InvalidAward => write!(f, "This award does not exist."),
+            EnrolmentError::SameAward => write!(f, "The pupil is already enrolled on this award."),
+            EnrolmentError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<db::Error> for EnrolmentError {
+    fn from(e: db::Error) -> Self {
+        EnrolmentError::DbError(e)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{db, enrolment::Enrolment, section::SectionKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// What is left of a pupil whose data has been erased: the anonymised sections they worked on, so that the award and
+/// activity statistics still count them. Nothing here identifies the pupil.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErasedClient {
+    pub award: String,
+    pub sections: [Option<SectionKey>; 6],
+    /// Awards the pupil was enrolled on before their last one.
+    pub enrolments: Vec<Enrolment>,
+    pub erased: SystemTime,
+}
+
+impl ErasedClient {
+    pub fn new(award: String, sections: [Option<SectionKey>; 6], enrolments: Vec<Enrolment>) -> Self {
+        Self {
+            award,
+            sections,
+            enrolments,
+            erased: SystemTime::now(),
+        }
+    }
+}
+
+/// Erased pupils, keyed by the id their account had. The id is kept only because their sections still refer to it.
+pub type ErasedClientDb = db::Database<UserKey, ErasedClient>;
@@ -0,0 +1,521 @@
+use crate::{data::SharedData, link, section, trash, user, util::format_time};
+use serde_json::json;
+use std::fs::File;
+use std::io::{Seek, Write};
+use zip::{result::ZipResult, write::FileOptions, CompressionMethod, ZipWriter};
+
+pub const SUMMARY_FILENAME: &str = "summary.html";
+pub const SUBJECT_ACCESS_FILENAME: &str = "data.json";
+
+/// Photos are scaled down to fit within this many pixels before being embedded in a portfolio.
+const MAX_IMAGE_SIZE: u32 = 1200;
+
+/// An uploaded image, scaled down and re-encoded as a JPEG so that it can be embedded in a document.
+pub struct EmbeddedImage {
+    pub width: u32,
+    pub height: u32,
+    pub jpeg: Vec<u8>,
+}
+
+impl EmbeddedImage {
+    pub fn load(path: &str) -> Option<Self> {
+        let image = image::open(path).ok()?;
+        let image = if image.width() > MAX_IMAGE_SIZE || image.height() > MAX_IMAGE_SIZE {
+            image.thumbnail(MAX_IMAGE_SIZE, MAX_IMAGE_SIZE)
+        } else {
+            image
+        };
+        let rgb = image.to_rgb8();
+        let mut jpeg: Vec<u8> = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 80)
+            .encode_image(&rgb)
+            .ok()?;
+        Some(Self {
+            width: rgb.width(),
+            height: rgb.height(),
+            jpeg,
+        })
+    }
+
+    pub fn data_url(&self) -> String {
+        "data:image/jpeg;base64,".to_owned() + &base64::encode(&self.jpeg)
+    }
+}
+
+/// Loads the uploaded images of a section which can be embedded in a portfolio, in name order.
+pub fn section_images(data: &SharedData, section_id: &section::SectionKey) -> Vec<(String, EmbeddedImage)> {
+    asset_names(data, section_id)
+        .into_iter()
+        .filter(|name| section::AssetKind::from_filename(name) == section::AssetKind::Image)
+        .filter_map(|name| {
+            EmbeddedImage::load(&data.path_for_asset(section_id, &name)).map(|image| (name, image))
+        })
+        .collect()
+}
+
+/// Lists the names of the uploaded assets of a section.
+pub fn asset_names(data: &SharedData, section_id: &section::SectionKey) -> Vec<String> {
+    list_files(&data.section_path(section_id))
+}
+
+/// Lists the names of the files in a directory, sorted by name.
+pub fn list_files(path: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if let Ok(paths) = std::fs::read_dir(path) {
+        for entry in paths.flatten() {
+            if entry.path().is_file() {
+                if let Ok(name) = entry.file_name().into_string() {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+/// Renders the description of an activity from the same template as the section page, so documents show what was asked.
+pub fn activity_description(data: &SharedData, activity: &section::Activity) -> String {
+    data.handlebars
+        .render(&activity.activity_url, &())
+        .unwrap_or_else(|e| format!("Failed to render: {}", e))
+}
+
+/// Makes HTML rendered from the site's templates work in a document opened offline. Server-relative images are
+/// embedded from the static folder, and server-relative links are removed as they would lead nowhere.
+fn offline_html(html: &str) -> String {
+    let mut out: String = String::new();
+    let mut rest: &str = html;
+    while let Some((start, attribute)) = ["src=\"/", "href=\"/"]
+        .iter()
+        .filter_map(|attribute| rest.find(attribute).map(|i| (i, *attribute)))
+        .min()
+    {
+        let value_start: usize = start + attribute.len() - 1;
+        let value_end: usize = match rest[value_start..].find('"') {
+            Some(i) => value_start + i,
+            None => break,
+        };
+        out += &rest[..start];
+        if attribute.starts_with("src") {
+            out += &format!("src=\"{}\"", static_data_url(&rest[value_start..value_end]).unwrap_or_default());
+        }
+        rest = &rest[value_end + 1..];
+    }
+    out + rest
+}
+
+/// Loads an image served from the static folder as a data URL.
+fn static_data_url(url: &str) -> Option<String> {
+    let path = std::path::Path::new(url.trim_start_matches('/'));
+    if path.components().any(|x| !matches!(x, std::path::Component::Normal(_))) {
+        return None;
+    }
+    let mime = mime_guess::from_path(path).first()?;
+    if mime.type_() != mime::IMAGE {
+        return None;
+    }
+    let bytes = std::fs::read(std::path::Path::new("static").join(path)).ok()?;
+    Some(format!("data:{};base64,{}", mime, base64::encode(&bytes)))
+}
+
+/// The folder name used for a section inside a pupil archive, e.g. `1 - Creative Skills - Video Editing`.
+pub fn section_folder_name(data: &SharedData, section: &section::Section) -> String {
+    let mut name: String = (section.section_index + 1).to_string();
+    if let Some(award) = data.awards.get(&section.award) {
+        if let Some(section_info) = award.sections.get(section.section_index) {
+            name += &(" - ".to_owned() + &section_info.name);
+        }
+    }
+    if let Some(activity) = section.get_activity(data) {
+        name += &(" - ".to_owned() + &activity.name);
+    }
+    sanitize_filename::sanitize(name)
+}
+
+/// Renders the part of the summary document describing a single section.
+/// If `embed_images` is set, uploaded photos are included in the document itself rather than only listed.
+pub fn section_summary(
+    data: &SharedData,
+    section_id: &section::SectionKey,
+    section: &section::Section,
+    embed_images: bool,
+) -> String {
+    let section_name: String = data
+        .awards
+        .get(&section.award)
+        .and_then(|award| award.sections.get(section.section_index))
+        .map(|x| x.name.clone())
+        .unwrap_or_default();
+
+    let (activity_name, description, answers): (String, String, Vec<_>) = match section.get_activity(data) {
+        Some(activity) => (
+            activity.name.clone(),
+            offline_html(&activity_description(data, activity)),
+            section
+                .answers(activity)
+                .into_iter()
+                .map(|(title, answer)| json!({ "title": title, "answer": answer }))
+                .collect(),
+        ),
+        None => (section.activity.clone(), String::new(), Vec::new()),
+    };
+
+    let submitted: String = section.state.time().map(format_time).unwrap_or_default();
+
+    let mut images: String = String::new();
+    if embed_images {
+        for (_, image) in section_images(data, section_id) {
+            images += &data
+                .handlebars
+                .render("sections/image_asset", &json!({ "asset_url": image.data_url() }))
+                .unwrap_or_default();
+        }
+    }
+
+    data.handlebars
+        .render(
+            "sections/summary_section",
+            &json!({
+                "section_name": section_name,
+                "activity_name": activity_name,
+                "description": description,
+                "state": section.state.to_string(),
+                "submitted": submitted,
+                "outstanding": section.outstanding,
+                "plan": &section.plan,
+                "reflection": &section.reflection,
+                "answers": answers,
+                "files": asset_names(data, section_id),
+                "images": images,
+            }),
+        )
+        .unwrap_or_else(|e| format!("Failed to render: {}", e))
+}
+
+/// Renders the complete summary document for the given sections of a pupil.
+pub fn summary_document(
+    data: &SharedData,
+    user: &user::User,
+    sections: &[(section::SectionKey, section::Section)],
+    embed_images: bool,
+) -> String {
+    let (award, org): (String, String) = match &user.user_agent {
+        user::UserAgent::Client { org_id, award, .. } => (
+            data.awards
+                .get(award)
+                .map(|x| x.name.clone())
+                .unwrap_or_else(|| award.clone()),
+            match data.org_db.fetch(org_id) {
+                Ok(Some(org)) => org.name,
+                _ => String::new(),
+            },
+        ),
+        _ => (String::new(), String::new()),
+    };
+
+    let mut body: String = String::new();
+    for (section_id, section) in sections.iter() {
+        body += &section_summary(data, section_id, section, embed_images);
+    }
+
+    data.handlebars
+        .render(
+            "export/summary",
+            &json!({
+                "name": user.name(),
+                "award": award,
+                "org": org,
+                "generated": format_time(std::time::SystemTime::now()),
+                "sections": body,
+            }),
+        )
+        .unwrap_or_else(|e| format!("Failed to render: {}", e))
+}
+
+/// Copies the uploaded assets of a section into the archive under `folder`.
+fn write_assets<W: Write + Seek>(
+    data: &SharedData,
+    zip: &mut ZipWriter<W>,
+    section_id: &section::SectionKey,
+    folder: &str,
+) -> ZipResult<()> {
+    write_files(zip, &data.section_path(section_id), folder)
+}
+
+/// Copies every file in the directory at `path` into the archive under `folder`.
+fn write_files<W: Write + Seek>(zip: &mut ZipWriter<W>, path: &str, folder: &str) -> ZipResult<()> {
+    // Evidence is mostly photos and videos which are already compressed.
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for name in list_files(path) {
+        let mut file = File::open(std::path::Path::new(path).join(&name))?;
+        zip.start_file(folder.to_owned() + &name, options)?;
+        std::io::copy(&mut file, zip)?;
+    }
+    Ok(())
+}
+
+fn write_summary<W: Write + Seek>(zip: &mut ZipWriter<W>, summary: &str) -> ZipResult<()> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(SUMMARY_FILENAME, options)?;
+    zip.write_all(summary.as_bytes())?;
+    Ok(())
+}
+
+/// Fetches every section the pupil has started, in section order.
+pub fn client_sections(
+    data: &SharedData,
+    user: &user::User,
+) -> Vec<(section::SectionKey, section::Section)> {
+    let mut result = Vec::new();
+    if let user::UserAgent::Client { sections, .. } = &user.user_agent {
+        for section_id in sections.iter().flatten() {
+            if let Ok(Some(section)) = data.section_db.fetch(section_id) {
+                result.push((*section_id, section));
+            }
+        }
+    }
+    result
+}
+
+/// Writes a zip archive at `path` containing the evidence of a single section and its summary.
+pub fn write_section_zip(
+    data: &SharedData,
+    path: &str,
+    user: &user::User,
+    section_id: section::SectionKey,
+    section: section::Section,
+) -> ZipResult<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let sections = [(section_id, section)];
+    write_summary(&mut zip, &summary_document(data, user, &sections, false))?;
+    write_assets(data, &mut zip, &section_id, "files/")?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Writes a zip archive at `path` containing the evidence for all of a pupil's sections,
+/// with one folder per section and a summary document covering them all.
+pub fn write_client_zip(data: &SharedData, path: &str, user: &user::User) -> ZipResult<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let sections = client_sections(data, user);
+    write_summary(&mut zip, &summary_document(data, user, &sections, false))?;
+    for (section_id, section) in sections.iter() {
+        let folder: String = section_folder_name(data, section) + "/";
+        write_assets(data, &mut zip, section_id, &folder)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Fetches every section a user has started, including those kept with an enrolment that has ended.
+fn user_sections(
+    data: &SharedData,
+    user_id: &user::UserKey,
+    user: &user::User,
+) -> Vec<(section::SectionKey, section::Section)> {
+    let mut result = client_sections(data, user);
+    for enrolment in data.enrolments(user_id, user) {
+        for section_id in enrolment.sections.iter().flatten() {
+            if !result.iter().any(|(x, _)| x == section_id) {
+                if let Ok(Some(section)) = data.section_db.fetch(section_id) {
+                    result.push((*section_id, section));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The folder for a section in a subject access export. The award is included, as the pupil may have done the same
+/// activity for more than one award.
+fn award_section_folder(data: &SharedData, section: &section::Section) -> String {
+    let award: String = data
+        .awards
+        .get(&section.award)
+        .map(|x| x.name.clone())
+        .unwrap_or_else(|| section.award.clone());
+    "sections/".to_owned() + &sanitize_filename::sanitize(award) + "/" + &section_folder_name(data, section) + "/"
+}
+
+/// Copies the files of the previous attempts at a section into the archive under `folder`.
+fn write_attempts<W: Write + Seek>(
+    data: &SharedData,
+    zip: &mut ZipWriter<W>,
+    section_id: &section::SectionKey,
+    folder: &str,
+) -> ZipResult<()> {
+    let attempts: usize = data.section_attempts_db.fetch(section_id).ok().flatten().map(|x| x.len()).unwrap_or(0);
+    for n in 0..attempts {
+        let attempt_folder: String = folder.to_owned() + "previous attempts/" + &(n + 1).to_string() + "/";
+        write_files(zip, &data.attempt_path(section_id, n), &attempt_folder)?;
+    }
+    Ok(())
+}
+
+fn describe_link(entry: &link::LinkEntry) -> String {
+    match &entry.link {
+        link::Link::CreateUser(_) => "Create account".to_owned(),
+        link::Link::ChangePassword(_) => "Reset password".to_owned(),
+        link::Link::ChangeEmail(_, email) => "Change email to ".to_owned() + email,
+    }
+}
+
+/// Writes a zip archive at `path` containing everything held about a user, to answer a subject access request.
+/// `data.json` has the records themselves, the summary describes them in a readable form and the uploaded files of
+/// each section, including previous attempts, are kept in a folder per award and section. Work in the trash is included
+/// too, as it is still held until the trash is emptied.
+/// Passwords and the tokens of sessions and links are left out, as they would let anyone reading the archive log in.
+pub fn write_subject_access_zip(
+    data: &SharedData,
+    path: &str,
+    user_id: user::UserKey,
+    user: &user::User,
+) -> ZipResult<()> {
+    let login = data.login_db.db().fetch(&user.email).ok().flatten();
+    let sessions = data.auth_manager.user_sessions(&user_id);
+    let links = data.link_manager.user_links(&user_id);
+    let enrolments = match &user.user_agent {
+        user::UserAgent::Client { .. } => data.enrolments(&user_id, user),
+        _ => Vec::new(),
+    };
+    let transfers = data.client_transfers(&user_id);
+    let archived = data.archived_clients_db.fetch(&user_id).ok().flatten();
+
+    let mut certificates: Vec<_> = Vec::new();
+    data.certificate_db.for_each(|certificate_id, certificate: crate::certificate::Certificate| {
+        if certificate.user_id == user_id {
+            let revocation = data.certificate_revocations_db.fetch(certificate_id).ok().flatten();
+            certificates.push(json!({ "certificate": certificate, "revocation": revocation }));
+        }
+    });
+
+    let mut trashed: Vec<(trash::TrashKey, trash::TrashEntry)> = Vec::new();
+    data.trash_db.for_each(|trash_id: &trash::TrashKey, entry: trash::TrashEntry| {
+        if entry.user_id == user_id {
+            trashed.push((*trash_id, entry));
+        }
+    });
+    trashed.sort_by_key(|x| x.1.deleted);
+    let trash_records: Vec<_> = trashed
+        .iter()
+        .map(|(trash_id, entry)| {
+            let attempts = match &entry.item {
+                trash::TrashItem::Section { section_id, .. } => {
+                    data.section_attempts_db.fetch(section_id).ok().flatten().unwrap_or_default()
+                }
+                trash::TrashItem::Asset { .. } => Vec::new(),
+            };
+            json!({
+                "entry": entry,
+                "files": list_files(&data.trash_item_path(trash_id)),
+                "attempts": attempts,
+            })
+        })
+        .collect();
+
+    let sections = user_sections(data, &user_id, user);
+    let mut section_records: Vec<_> = Vec::new();
+    let mut section_summaries: String = String::new();
+    for (section_id, section) in sections.iter() {
+        let attempts = data.section_attempts_db.fetch(section_id).ok().flatten().unwrap_or_default();
+        let attempt_files: Vec<Vec<String>> = (0..attempts.len())
+            .map(|n| list_files(&data.attempt_path(section_id, n)))
+            .collect();
+        section_records.push(json!({
+            "section_id": section_id,
+            "section": section,
+            "draft": data.section_drafts_db.fetch(section_id).ok().flatten(),
+            "attempts": attempts,
+            "files": asset_names(data, section_id),
+            "attempt_files": attempt_files,
+        }));
+        section_summaries += &section_summary(data, section_id, section, false);
+    }
+
+    let records = json!({
+        "generated": std::time::SystemTime::now(),
+        "user_id": user_id,
+        "user": user,
+        "login": login.as_ref().map(|x| json!({ "username": &user.email, "default_password": x.default_password })),
+        "sessions": sessions,
+        "links": links,
+        "enrolments": enrolments,
+        "sections": section_records,
+        "certificates": certificates,
+        "transfers": transfers,
+        "archived": archived,
+        "trash": trash_records,
+    });
+
+    let summary: String = data
+        .handlebars
+        .render(
+            "export/subject_access",
+            &json!({
+                "name": user.name(),
+                "email": &user.email,
+                "account_type": user.user_agent.agent_string(),
+                "external_id": &user.external_id,
+                "notifications": user.notifications,
+                "default_password": login.as_ref().map(|x| x.default_password),
+                "generated": format_time(std::time::SystemTime::now()),
+                "sessions": sessions.iter().map(|x| format_time(x.expiry())).collect::<Vec<String>>(),
+                "links": links
+                    .iter()
+                    .map(|x| json!({ "description": describe_link(x), "expiry": format_time(x.expiry) }))
+                    .collect::<Vec<_>>(),
+                "enrolments": enrolments
+                    .iter()
+                    .map(|x| json!({
+                        "award": data.awards.get(&x.award).map(|a| a.name.clone()).unwrap_or_else(|| x.award.clone()),
+                        "status": x.status.to_string(),
+                    }))
+                    .collect::<Vec<_>>(),
+                "certificates": certificates.len(),
+                "transfers": transfers.len(),
+                "archived": archived.is_some(),
+                "trash": trashed.len(),
+                "sections": section_summaries,
+            }),
+        )
+        .unwrap_or_else(|e| format!("Failed to render: {}", e));
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(SUBJECT_ACCESS_FILENAME, options)?;
+    zip.write_all(serde_json::to_string_pretty(&records).unwrap_or_default().as_bytes())?;
+    write_summary(&mut zip, &summary)?;
+    let mut folders: Vec<String> = Vec::new();
+    for (section_id, section) in sections.iter() {
+        let mut folder: String = award_section_folder(data, section);
+        // Zip files can hold the same path twice, and one would overwrite the other when extracted.
+        if folders.contains(&folder) {
+            folder = folder.trim_end_matches('/').to_owned() + " (" + &section_id.to_string() + ")/";
+        }
+        folders.push(folder.clone());
+        write_assets(data, &mut zip, section_id, &folder)?;
+        write_attempts(data, &mut zip, section_id, &folder)?;
+    }
+    for (n, (trash_id, entry)) in trashed.iter().enumerate() {
+        let folder: String = "trash/".to_owned() + &(n + 1).to_string();
+        let trash_path: String = data.trash_item_path(trash_id);
+        match &entry.item {
+            trash::TrashItem::Section { section_id, section } => {
+                let folder: String = folder + " - " + &section_folder_name(data, section) + "/";
+                write_files(&mut zip, &trash_path, &folder)?;
+                write_attempts(data, &mut zip, section_id, &folder)?;
+            }
+            trash::TrashItem::Asset { filename, .. } => {
+                if std::path::Path::new(&trash_path).is_file() {
+                    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+                    let mut file = File::open(&trash_path)?;
+                    zip.start_file(folder + " - " + filename, options)?;
+                    std::io::copy(&mut file, &mut zip)?;
+                }
+            }
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::{db, dir, org::OrgKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::SystemTime;
+
+/// A row of an uploaded CSV file, with the columns named by the header row.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// The line of the file the row is on, for reporting problems.
+    pub line: u64,
+    fields: HashMap<String, String>,
+}
+
+impl Record {
+    /// The trimmed value of a column, or an empty string if the file doesn't have the column.
+    pub fn get(&self, column: &str) -> String {
+        self.fields
+            .get(&normalise_header(column))
+            .map(|x| x.trim().to_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn has(&self, column: &str) -> bool {
+        self.fields.contains_key(&normalise_header(column))
+    }
+}
+
+/// Header names are compared ignoring case, spaces and punctuation, so `First Name` matches `firstname`.
+fn normalise_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|x| x.is_alphanumeric())
+        .flat_map(|x| x.to_lowercase())
+        .collect()
+}
+
+/// Reads a CSV file with a header row. Blank rows are skipped.
+pub fn read_records(bytes: &[u8]) -> Result<Vec<Record>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+    let headers: Vec<String> = reader.headers()?.iter().map(normalise_header).collect();
+    let mut records: Vec<Record> = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(|x| x.trim().is_empty()) {
+            continue;
+        }
+        records.push(Record {
+            line: record.position().map(|x| x.line()).unwrap_or_default(),
+            fields: headers.iter().cloned().zip(record.iter().map(|x| x.to_owned())).collect(),
+        });
+    }
+    Ok(records)
+}
+
+/// A pupil to be created from a row of an import.
+#[derive(Debug, Clone)]
+pub struct ClientRow {
+    pub forename: String,
+    pub surname: String,
+    pub email: String,
+    pub class: String,
+    /// The id of the award, which may have been given by name in the file.
+    pub award: String,
+    pub external_id: String,
+}
+
+impl ClientRow {
+    pub const COLUMNS: [&'static str; 6] = ["forename", "surname", "email", "class", "award", "external id"];
+
+    pub fn name(&self) -> String {
+        self.forename.clone() + " " + &self.surname
+    }
+}
+
+/// A teacher to be created from a row of an import.
+#[derive(Debug, Clone)]
+pub struct AssociateRow {
+    pub forename: String,
+    pub surname: String,
+    pub email: String,
+}
+
+impl AssociateRow {
+    pub const COLUMNS: [&'static str; 3] = ["forename", "surname", "email"];
+
+    pub fn name(&self) -> String {
+        self.forename.clone() + " " + &self.surname
+    }
+}
+
+/// A row of an import once it has been checked. Rows with a problem are skipped when the import is run.
+#[derive(Debug, Clone)]
+pub struct CheckedRow<T> {
+    pub line: u64,
+    pub row: T,
+    pub problem: Option<String>,
+}
+
+/// A change to an existing pupil, found when syncing a roster.
+#[derive(Debug, Clone)]
+pub struct RosterUpdate {
+    pub line: u64,
+    pub user_id: UserKey,
+    pub email: String,
+    pub forename: String,
+    pub surname: String,
+    /// The name of the class to move the pupil to, if the roster has a class column.
+    pub class: Option<String>,
+    pub external_id: Option<String>,
+    /// A description of each change, for review.
+    pub changes: Vec<String>,
+    pub problem: Option<String>,
+}
+
+/// The differences between an uploaded roster and the pupils of an org.
+#[derive(Debug, Clone)]
+pub struct RosterDiff {
+    /// Rows that don't match a pupil of the org.
+    pub adds: Vec<CheckedRow<ClientRow>>,
+    pub updates: Vec<RosterUpdate>,
+    /// The number of pupils in the roster that are already up to date.
+    pub unchanged: usize,
+    /// Pupils of the org who aren't in the roster, with their names.
+    pub removals: Vec<(UserKey, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ImportKind {
+    Clients,
+    Associates,
+    /// A pupil roster synced with the pupils of the org, which can also update and archive pupils.
+    Roster,
+}
+
+impl ImportKind {
+    /// The path of the upload page, relative to the org.
+    pub fn path(&self) -> &'static str {
+        match self {
+            ImportKind::Clients => dir::IMPORT_CLIENTS_PATH,
+            ImportKind::Associates => dir::IMPORT_ASSOCIATES_PATH,
+            ImportKind::Roster => dir::IMPORT_ROSTER_PATH,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ImportKind::Clients => dir::IMPORT_CLIENTS_TITLE,
+            ImportKind::Associates => dir::IMPORT_ASSOCIATES_TITLE,
+            ImportKind::Roster => dir::IMPORT_ROSTER_TITLE,
+        }
+    }
+
+    /// The page of the org that lists the users created by the import.
+    pub fn list_page(&self) -> &'static str {
+        match self {
+            ImportKind::Clients | ImportKind::Roster => dir::CLIENTS_PAGE,
+            ImportKind::Associates => dir::ASSOCIATES_PAGE,
+        }
+    }
+}
+
+impl fmt::Display for ImportKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportKind::Clients => write!(f, "Pupil"),
+            ImportKind::Associates => write!(f, "Teacher"),
+            ImportKind::Roster => write!(f, "Roster"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ImportOutcome {
+    Created,
+    Updated,
+    Archived,
+    /// The row had a problem when it was checked, so nothing was attempted.
+    Skipped,
+    /// The row was valid but creating the account failed.
+    Failed,
+}
+
+impl fmt::Display for ImportOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportOutcome::Created => write!(f, "Created"),
+            ImportOutcome::Updated => write!(f, "Updated"),
+            ImportOutcome::Archived => write!(f, "Archived"),
+            ImportOutcome::Skipped => write!(f, "Skipped"),
+            ImportOutcome::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportResult {
+    pub line: u64,
+    pub name: String,
+    pub email: String,
+    pub outcome: ImportOutcome,
+    pub user_id: Option<UserKey>,
+    pub message: String,
+}
+
+/// The results of running an import, kept so they can be downloaded afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Import {
+    pub org_id: OrgKey,
+    pub kind: ImportKind,
+    pub time: SystemTime,
+    pub actor: UserKey,
+    pub results: Vec<ImportResult>,
+}
+
+impl Import {
+    pub fn count(&self, outcome: ImportOutcome) -> usize {
+        self.results.iter().filter(|x| x.outcome == outcome).count()
+    }
+
+    /// The number of rows with each outcome, such as "3 created, 1 skipped".
+    pub fn summary(&self) -> String {
+        let counts: Vec<String> = [
+            ImportOutcome::Created,
+            ImportOutcome::Updated,
+            ImportOutcome::Archived,
+            ImportOutcome::Skipped,
+            ImportOutcome::Failed,
+        ]
+        .iter()
+        .map(|outcome| (self.count(*outcome), outcome))
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, outcome)| count.to_string() + " " + &outcome.to_string().to_lowercase())
+        .collect();
+        if counts.is_empty() {
+            "no changes".to_owned()
+        } else {
+            counts.join(", ")
+        }
+    }
+}
+
+define_uuid_key!(ImportKey);
+
+pub type ImportDb = db::Database<ImportKey, Import>;
+
+pub fn write_results(results: &[ImportResult]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["Line", "Name", "Email", "Result", "Details"])?;
+    for result in results.iter() {
+        writer.write_record([
+            result.line.to_string(),
+            result.name.clone(),
+            result.email.clone(),
+            result.outcome.to_string(),
+            result.message.clone(),
+        ])?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
@@ -8,6 +8,8 @@ use std::time::{Duration, SystemTime};
 pub enum Link {
     CreateUser(UserAgent),
     ChangePassword(UserKey),
+    /// Confirms that the user can receive email at a new address before it becomes their username.
+    ChangeEmail(UserKey, String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,6 +74,31 @@ impl LinkManager {
         }
     }
 
+    /// Lists the links that act on a user's account, without their tokens.
+    pub fn user_links(&self, user_id: &UserKey) -> Vec<LinkEntry> {
+        let mut links: Vec<LinkEntry> = Vec::new();
+        self.db().for_each_val(|entry| {
+            let is_user: bool = match &entry.link {
+                Link::CreateUser(_) => false,
+                Link::ChangePassword(id) => id == user_id,
+                Link::ChangeEmail(id, _) => id == user_id,
+            };
+            if is_user && entry.expiry > SystemTime::now() {
+                links.push(entry);
+            }
+        });
+        links
+    }
+
+    pub fn destroy_user_links(&self, user_id: &UserKey) {
+        let user_id: UserKey = *user_id;
+        self.db().retain(true, move |v| match v.link {
+            Link::CreateUser(_) => true,
+            Link::ChangePassword(id) => id != user_id,
+            Link::ChangeEmail(id, _) => id != user_id,
+        });
+    }
+
     pub fn clear_expired_links(&self) {
         self.db().retain(false, |v| {
             if v.expiry > SystemTime::now() {
@@ -1,4 +1,4 @@
-use crate::{db, db::Database, user::UserKey};
+use crate::{credit, db, db::Database, user::UserKey};
 use std::fmt;
 use std::path::Path;
 
@@ -15,6 +15,8 @@ pub struct LoginEntry {
 pub enum AuthError {
     NoUser,
     IncorrectPassword,
+    /// The user belongs to an organisation that has been archived.
+    OrgArchived,
     DbError(db::Error),
 }
 
@@ -23,6 +25,7 @@ impl fmt::Display for AuthError {
         match self {
             AuthError::NoUser => write!(f, "No matching username found!"),
             AuthError::IncorrectPassword => write!(f, "Incorrect password for username!"),
+            AuthError::OrgArchived => write!(f, "This organisation has been archived."),
             AuthError::DbError(e) => e.fmt(f),
         }
     }
@@ -30,9 +33,11 @@ impl fmt::Display for AuthError {
 
 #[derive(Debug)]
 pub enum LoginEntryError {
-    NotUnique,
     UsernameExists,
+    NoEntry,
     //PasswordInvalid(String),
+    /// The credit for a new client couldn't be taken from their organisation.
+    CreditError(credit::CreditError),
     DbError(db::Error),
 }
 
@@ -42,11 +47,9 @@ impl fmt::Display for LoginEntryError {
             LoginEntryError::UsernameExists => {
                 write!(f, "An account with the given username already exists!")
             }
-            LoginEntryError::NotUnique => write!(
-                f,
-                "An account with similar characteristics exists where it should not!"
-            ),
+            LoginEntryError::NoEntry => write!(f, "No account with the given username exists!"),
             //LoginEntryError::PasswordInvalid(ref s) => write!(f, "Password invalid: {}!", s),
+            LoginEntryError::CreditError(e) => e.fmt(f),
             LoginEntryError::DbError(e) => e.fmt(f),
         }
     }
@@ -93,6 +96,28 @@ impl LoginDb {
         }
     }
 
+    /// Moves a login entry to a new username, failing if the new username is taken.
+    /// The entry is moved in a single transaction, so it is never under both usernames or neither.
+    pub fn change_username(&self, username: &str, new_username: &str) -> Result<(), LoginEntryError> {
+        let result = self.db().raw_db().transaction(|tx| {
+            if tx.get(new_username)?.is_some() {
+                return sled::transaction::abort(LoginEntryError::UsernameExists);
+            }
+            match tx.remove(username)? {
+                Some(entry) => {
+                    tx.insert(new_username, entry)?;
+                    Ok(())
+                }
+                None => sled::transaction::abort(LoginEntryError::NoEntry),
+            }
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(sled::transaction::TransactionError::Storage(e)) => Err(LoginEntryError::DbError(db::Error::DbError(e))),
+        }
+    }
+
     pub fn change_password(
         &self,
         username: &str,
@@ -16,14 +16,25 @@ pub mod dir;
 pub mod form;
 
 pub mod data;
+pub mod export;
 pub mod page;
 
 pub mod auth;
+pub mod certificate;
+mod class;
+pub mod credit;
+pub mod enrolment;
+pub mod erasure;
+pub mod import;
 pub mod link;
 pub mod login;
 pub mod notifications;
 pub mod org;
+pub mod portfolio;
+pub mod rollover;
 pub mod section;
+pub mod trash;
+pub mod transfer;
 pub mod user;
 
 use data::SharedData;
@@ -78,7 +89,8 @@ async fn main() -> std::io::Result<()> {
         email: "ncbmknight@gmail.com".to_owned(),
         user_agent: user::UserAgent::Owner,
         notifications: false,
-    }, "fj!ao83yfipu]9y3", false);
+        external_id: None,
+    }, "fj!ao83yfipu]9y3", false, None);
 
     let _ = data.register_user(&user::User {
         forename: "Dawn".to_owned(),
@@ -86,10 +98,13 @@ async fn main() -> std::io::Result<()> {
         email: "dawn@juniorduke.com".to_owned(),
         user_agent: user::UserAgent::Owner,
         notifications: false,
-    }, "fms83lF!SL5l", false);
+        external_id: None,
+    }, "fms83lF!SL5l", false, None);
 
     // Spawn notification process using the actix runtime
     actix_web::rt::spawn(notifications::user_notification_process(data.clone()));
+    actix_web::rt::spawn(trash::trash_purge_process(data.clone()));
+    actix_web::rt::spawn(org::archive_purge_process(data.clone()));
 
     let mut https_builder = HttpServer::new(move || {
         // User
@@ -98,6 +113,11 @@ async fn main() -> std::io::Result<()> {
             .service(page::user::user_get)
             .service(page::user::delete_user_post)
             .service(page::user::enable_notifications_get)
+            .service(page::user::confirm_email_get)
+            .service(page::user::edit_profile_get)
+            .service(page::user::edit_profile_post)
+            .service(page::user::change_email_post)
+            .service(page::user::export_user_data_get)
             // Login
             .service(page::login::login_get)
             .service(page::login::login_post)
@@ -112,18 +132,59 @@ async fn main() -> std::io::Result<()> {
             .service(page::orgs::org_get)
             .service(page::orgs::orgs_get)
             .service(page::orgs::add_org_post)
-            .service(page::orgs::delete_org_post)
+            .service(page::orgs::archive_org_post)
+            .service(page::orgs::restore_org_post)
             .service(page::orgs::assign_admin_post)
             .service(page::orgs::add_credits_post)
+            .service(page::credits::credits_get)
+            .service(page::credits::credits_csv_get)
+            .service(page::credits::credit_request_post)
+            .service(page::credits::credit_requests_get)
+            .service(page::credits::approve_credit_request_post)
+            .service(page::credits::decline_credit_request_post)
+            .service(page::settings::settings_get)
+            .service(page::settings::settings_post)
+            .service(page::settings::logo_get)
+            .service(page::org_admins::org_admins_get)
+            .service(page::org_admins::invite_org_admin_post)
+            .service(page::org_admins::primary_org_admin_post)
+            .service(page::classes::classes_get)
+            .service(page::classes::add_class_post)
+            .service(page::classes::class_get)
+            .service(page::classes::class_post)
+            .service(page::classes::delete_class_post)
+            .service(page::classes::move_client_post)
             // Clients
             .service(page::clients::clients_get)
             .service(page::clients::add_client_get)
             .service(page::clients::add_client_post)
             .service(page::clients::client_dashboard_get)
+            .service(page::clients::client_download_get)
+            .service(page::clients::client_portfolio_get)
+            .service(page::enrolment::client_awards_get)
+            .service(page::enrolment::client_awards_post)
             // Associates
             .service(page::associates::associates_get)
             .service(page::associates::add_associate_get)
             .service(page::associates::add_associate_post)
+            .service(page::associates::associate_get)
+            .service(page::associates::caseload_post)
+            .service(page::transfer::transfer_get)
+            .service(page::transfer::transfer_post)
+            .service(page::rollover::rollover_get)
+            .service(page::rollover::rollover_post)
+            .service(page::rollover::restore_client_post)
+            .service(page::import::import_clients_get)
+            .service(page::import::import_clients_post)
+            .service(page::import::confirm_import_clients_post)
+            .service(page::import::import_associates_get)
+            .service(page::import::import_associates_post)
+            .service(page::import::confirm_import_associates_post)
+            .service(page::import::import_roster_get)
+            .service(page::import::import_roster_post)
+            .service(page::import::confirm_import_roster_post)
+            .service(page::import::import_results_get)
+            .service(page::import::import_results_csv_get)
             // Sections
             .service(page::section::section_get)
             .service(page::section::section_id_get)
@@ -133,6 +194,19 @@ async fn main() -> std::io::Result<()> {
             .service(page::section::set_outstanding_post)
             .service(page::section::delete_section_get)
             .service(page::section::asset_get)
+            .service(page::section::section_download_get)
+            .service(page::section::draft_post)
+            .service(page::section::change_activity_get)
+            .service(page::section::change_activity_post)
+            .service(page::section::attempts_get)
+            .service(page::section::attempt_asset_get)
+            .service(page::trash::client_trash_get)
+            .service(page::trash::restore_trash_post)
+            .service(page::certificate::certificate_get)
+            .service(page::certificate::verify_get)
+            .service(page::certificate::verify_code_get)
+            .service(page::certificate::certificates_get)
+            .service(page::certificate::revoke_certificate_post)
             //Admin
             .service(page::admin::accounts_get)
             .service(page::admin::add_admin_get)
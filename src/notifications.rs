@@ -18,7 +18,8 @@ pub async fn user_notification_process(data: Arc<SharedData>) {
 
                 for user_id in org.associates.iter() {
                     if let Ok(Some(user)) = data.user_db.fetch(user_id) {
-                        if user.notifications && unreviewed_count > 0 {
+                        // Teachers of archived orgs can't log in to review sections.
+                        if user.notifications && unreviewed_count > 0 && !data.is_user_archived(&user.user_agent) {
                             // Send email
                             if data.send_email(
                                 &user.email,
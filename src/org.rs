@@ -1,6 +1,9 @@
+use crate::data::SharedData;
 use crate::section::SectionKey;
 use crate::user::UserKey;
+use async_std::task;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -8,6 +11,7 @@ use crate::{db, define_uuid_key, dir};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Org {
+    /// The primary contact for the org. Every admin, including this one, is listed in the `OrgAdminsDb`.
     pub admin: Option<UserKey>,
     pub name: String,
     pub associates: Vec<UserKey>,
@@ -38,3 +42,121 @@ impl Org {
 define_uuid_key!(OrgKey);
 
 pub type OrgDb = db::Database<OrgKey, Org>;
+
+/// The admins of each org. Orgs from before multiple admins were supported only have `Org::admin`.
+pub type OrgAdminsDb = db::Database<OrgKey, Vec<UserKey>>;
+
+/// Details that an organisation can change from its settings page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgSettings {
+    pub has_logo: bool,
+    pub contact_name: String,
+    pub contact_email: String,
+    pub contact_phone: String,
+    /// The IANA name of the timezone used to show times to the organisation.
+    pub timezone: String,
+    /// The award selected when adding a new pupil.
+    pub default_award: Option<String>,
+    /// If set, teachers can only see the pupils in their classes and those assigned to them.
+    pub restrict_associates: bool,
+}
+
+impl Default for OrgSettings {
+    fn default() -> Self {
+        Self {
+            has_logo: false,
+            contact_name: String::new(),
+            contact_email: String::new(),
+            contact_phone: String::new(),
+            timezone: dir::DEFAULT_TIMEZONE.to_owned(),
+            default_award: None,
+            restrict_associates: false,
+        }
+    }
+}
+
+impl OrgSettings {
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+}
+
+pub type OrgSettingsDb = db::Database<OrgKey, OrgSettings>;
+
+/// Something that happened to an org, kept so that admins can see how the org has changed over time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgEvent {
+    pub time: SystemTime,
+    pub actor: Option<UserKey>,
+    pub description: String,
+}
+
+impl OrgEvent {
+    pub fn new(actor: Option<UserKey>, description: String) -> Self {
+        Self {
+            time: SystemTime::now(),
+            actor,
+            description,
+        }
+    }
+}
+
+/// The history of each org, oldest first.
+pub type OrgHistoryDb = db::Database<OrgKey, Vec<OrgEvent>>;
+
+/// An org that has been archived. Its users can't log in and its data can't be changed until it is restored.
+/// It is deleted permanently once `purge_after` has passed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedOrg {
+    pub archived: SystemTime,
+    pub archived_by: UserKey,
+    pub purge_after: SystemTime,
+}
+
+impl ArchivedOrg {
+    pub fn new(archived_by: UserKey, retention: Duration) -> Self {
+        let archived = SystemTime::now();
+        Self {
+            archived,
+            archived_by,
+            purge_after: archived + retention,
+        }
+    }
+}
+
+pub type OrgArchiveDb = db::Database<OrgKey, ArchivedOrg>;
+
+pub async fn archive_purge_process(data: Arc<SharedData>) {
+    log::trace!("Starting archive purge process...");
+    loop {
+        let removed: Vec<String> = data.purge_archived_orgs();
+        if !removed.is_empty() {
+            for line in removed.iter() {
+                log::warn!("Purged archived organisation: {}", line);
+            }
+            let content: String = removed.join("<br>");
+            let mut addresses: Vec<String> = Vec::new();
+            data.user_db.for_each_val(|user| {
+                if user.user_agent.privilege().is_root() {
+                    addresses.push(user.email);
+                }
+            });
+            for address in addresses.iter() {
+                if data
+                    .send_email(
+                        address,
+                        "Senior Duke - Archived Organisations Deleted",
+                        "Archived Organisations Deleted",
+                        "The following organisations reached the end of their retention period and have been permanently deleted:",
+                        &content,
+                    )
+                    .is_none()
+                {
+                    log::error!("Failed to send purge report to {}", address);
+                }
+            }
+        }
+        // Archived orgs only need to be removed at some point after their retention period ends.
+        task::sleep(Duration::from_secs(60 * 60 * 24)).await;
+    }
+}
@@ -9,6 +9,7 @@ use serde_json::json;
 
 use crate::data::SharedData;
 
+use crate::credit;
 use crate::dir;
 
 use crate::page;
@@ -54,7 +55,7 @@ pub async fn accounts_get(data: web::Data<Arc<SharedData>>, req: HttpRequest, qu
                             let search_span: String = search.to_lowercase();
                             if name.contains(&search_span) {
                                 unordered_users.push((*user_id, user));
-                            } else if user.email.contains(&search_span) {
+                            } else if user.email.to_lowercase().contains(&search_span) {
                                 unordered_users.push((*user_id, user));
                             }
                         });
@@ -124,6 +125,24 @@ pub async fn accounts_get(data: web::Data<Arc<SharedData>>, req: HttpRequest, qu
                     })).unwrap();
                 }
 
+                // Accounts with the same email once normalised have to be merged by hand.
+                let collisions: Vec<_> = data
+                    .email_collisions()
+                    .iter()
+                    .map(|group| {
+                        group
+                            .iter()
+                            .map(|(user_id, user)| {
+                                json!({
+                                    "user_url": dir::user_path(*user_id),
+                                    "name": user.name(),
+                                    "email": user.email,
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+
                 let content = data
                     .handlebars
                     .render(
@@ -135,7 +154,8 @@ pub async fn accounts_get(data: web::Data<Arc<SharedData>>, req: HttpRequest, qu
                             "display_user_table": query.search.is_some() || view_all,
                             "delete_user_url": dir::DELETE_USER_PATH,
                             "not_viewing_all": !view_all || query.search.is_some(),
-                            "can_add_admin": ctx.user.user_agent.can_add_admin()
+                            "can_add_admin": ctx.user.user_agent.can_add_admin(),
+                            "collisions": collisions,
                         }),
                     )
                     .unwrap();
@@ -246,11 +266,12 @@ pub async fn add_admin_post(
                         surname: form.surname.clone(),
                         notifications: true,
                         user_agent: user::UserAgent::Admin,
+                        external_id: None,
                     };
 
                     let password: String = util::gen_password(8);
 
-                    match data.register_user(&user, &password, true)  {
+                    match data.register_user(&user, &password, true, Some(ctx.user_id))  {
                         Ok(user_id) => {
                             if let Ok(link_token) = data.link_manager.create_link(link::Link::ChangePassword(user_id), std::time::Duration::from_secs(dir::CHANE_PASSWORD_LINK_TIMEOUT_SECS)) {
                                 // send email.
@@ -397,7 +418,22 @@ pub async fn delete_data_post(
                             info += "<b>organisations</b>";
                         } else {
                             if let Some(_) = form.delete_credits {
-                                data.org_db.for_each_write(|mut org| { org.credits = 0; });
+                                data.org_db.for_each(|org_id, mut org| {
+                                    if org.credits > 0 {
+                                        let transaction = credit::CreditTransaction::new(
+                                            credit::CreditTransactionKind::Adjustment,
+                                            -(org.credits as i64),
+                                            Some(ctx.user_id),
+                                            "All credits deleted by an administrator".to_owned(),
+                                        );
+                                        match data.add_credit_transaction(org_id, &mut org, transaction) {
+                                            Ok(()) => {
+                                                let _ = data.org_db.insert(org_id, &org);
+                                            }
+                                            Err(e) => log::error!("Failed to delete credits: {}", e),
+                                        }
+                                    }
+                                });
                                 info += "<b>credits</b>"
                             }
                             if let Some(_) = form.delete_pupils {
@@ -408,7 +444,7 @@ pub async fn delete_data_post(
                                     }
                                 });
                                 for user_id in users {
-                                    data.delete_user(&user_id);
+                                    data.delete_user(&user_id, Some(ctx.user_id));
                                 }
                                 if !info.is_empty() {
                                     info += ", <b>pupils</b>";
@@ -484,6 +520,8 @@ pub async fn admin_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> Ht
                             "memory": memory,
                             "log_url": dir::DOWNLOAD_LOG_PATH,
                             "delete_url": dir::DELETE_PATH,
+                            "certificates_url": dir::CERTIFICATES_PATH,
+                            "credit_requests_url": dir::CREDIT_REQUESTS_PATH,
                         }),
                     )
                     .unwrap();
@@ -7,7 +7,6 @@ use serde_json::json;
 use crate::data::SharedData;
 
 use crate::dir;
-use crate::link;
 use crate::login;
 use crate::org;
 use crate::page;
@@ -31,6 +30,7 @@ pub async fn associates_get(
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
                             let mut rows: String = String::new();
+                            let can_assign: bool = ctx.user.user_agent.can_manage_classes(&org_id);
 
                             for user_id in org.associates.iter() {
                                 match data.user_db.fetch(user_id) {
@@ -58,6 +58,9 @@ pub async fn associates_get(
                                                     "email": user.email,
                                                     "user_id": user_id,
                                                     "delete_user_hidden": delete_user_hidden,
+                                                    "can_assign": can_assign,
+                                                    "assign_url": dir::associate_path(org_id, *user_id),
+                                                    "assigned": data.assigned_clients(user_id, &org_id).len(),
                                                 })).unwrap();
                                             }
                                         }
@@ -77,8 +80,10 @@ pub async fn associates_get(
                             let content = data.handlebars.render("associate/associates_list", &json!({
                                 "hide_add_associate": hide_add_associate,
                                 "add_associate_url": dir::org_path(org_id) + dir::ADD_ASSOCIATE_PATH,
+                                "import_url": dir::org_path(org_id) + dir::IMPORT_ASSOCIATES_PATH,
                                 "delete_user_url": dir::DELETE_USER_PATH.to_owned(),
                                 "associate_rows": rows,
+                                "can_assign": can_assign,
                             })).unwrap();
 
                             let header: String = page::path_header(
@@ -248,6 +253,9 @@ pub async fn add_associate_post(
         match data.authenticate_context_from_request(&req, true) {
             Ok(Some(ctx)) => {
                 if ctx.user.user_agent.can_add_associate(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
                             if !util::is_string_server_valid(&form.forename) {
@@ -278,41 +286,14 @@ pub async fn add_associate_post(
                                     surname: form.surname.clone(),
                                     notifications: true,
                                     user_agent: user::UserAgent::Associate(org_id),
+                                    external_id: None,
                                 };
 
                                 let password: String = util::gen_password(8);
 
-                                match data.register_user(&user, &password, true)  {
+                                match data.register_user(&user, &password, true, Some(ctx.user_id))  {
                                     Ok(user_id) => {
-                                        if let Ok(link_token) = data.link_manager.create_link(link::Link::ChangePassword(user_id), std::time::Duration::from_secs(dir::CHANE_PASSWORD_LINK_TIMEOUT_SECS)) {
-                                            // send email.
-                                            let link: String = dir::make_absolute_url(&("/user/change_password/".to_string() + &link_token.to_string()));
-                                            let addr: String = form.email.clone();
-                                            let subtitle: String = data
-                                            .handlebars
-                                            .render(
-                                                "email/account_created",
-                                                &json!({
-                                                    "name": user.name(),
-                                                    "account_type": "teacher",
-                                                    "org_name": &org.name,
-                                                    "username": &user.email,
-                                                    "password": &password,
-                                                    "link": link,
-                                                }),
-                                            )
-                                            .unwrap();
-                    
-                                            if data.send_email(
-                                                &addr, 
-                                                "Senior Duke - Welcome & Password Info", 
-                                                "Senior Duke - Welcome & Password Info",
-                                                &subtitle, 
-                                                ""
-                                            ).is_none() {
-                                                log::error!("Failed to send email!");
-                                            }
-                                        }
+                                        page::send_welcome_email(&data, &user, user_id, "teacher", &org.name, &password);
 
                                         let mut attrs: String = String::new();
 
@@ -373,3 +354,193 @@ pub async fn add_associate_post(
         HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
     }
 }
+
+#[get("/org/{org}/associates/{user}")]
+pub async fn associate_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(associate_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_manage_classes(&org_id) {
+                        match (data.org_db.fetch(&org_id), data.user_db.fetch(&associate_id)) {
+                            (Ok(Some(org)), Ok(Some(associate)))
+                                if associate.user_agent == user::UserAgent::Associate(org_id) =>
+                            {
+                                let classes: Vec<_> = data
+                                    .org_classes(&org_id)
+                                    .iter()
+                                    .filter(|(_, class)| class.associates.contains(&associate_id))
+                                    .map(|(class_id, class)| {
+                                        json!({
+                                            "class_url": dir::class_path(org_id, *class_id),
+                                            "title": class.title(),
+                                            "pupils": class.clients.len(),
+                                        })
+                                    })
+                                    .collect();
+
+                                let caseload: Vec<user::UserKey> =
+                                    data.caseload_db.fetch(&associate_id).ok().flatten().unwrap_or_default();
+                                let assign_url: String = dir::associate_path(org_id, associate_id) + "/caseload";
+
+                                let mut rows: String = String::new();
+                                let mut pupils: Vec<_> = Vec::new();
+                                for user_id in org.clients.iter() {
+                                    if let Ok(Some(user)) = data.user_db.fetch(user_id) {
+                                        if let user::UserAgent::Client { ref class, .. } = user.user_agent {
+                                            if caseload.contains(user_id) {
+                                                rows += &data
+                                                    .handlebars
+                                                    .render(
+                                                        "associate/caseload_row",
+                                                        &json!({
+                                                            "client_url": dir::client_path(org_id, *user_id),
+                                                            "name": user.name(),
+                                                            "class": class,
+                                                            "user_id": user_id,
+                                                            "assign_url": &assign_url,
+                                                        }),
+                                                    )
+                                                    .unwrap();
+                                            } else {
+                                                pupils.push(json!({
+                                                    "user_id": user_id,
+                                                    "name": user.name(),
+                                                    "class": class,
+                                                }));
+                                            }
+                                        }
+                                    }
+                                }
+
+                                let content = data
+                                    .handlebars
+                                    .render(
+                                        "associate/associate_page",
+                                        &json!({
+                                            "back_url": dir::org_path(org_id) + dir::ASSOCIATES_PAGE,
+                                            "name": associate.name(),
+                                            "restricted": data.org_settings(&org_id).restrict_associates,
+                                            "classes": classes,
+                                            "rows": rows,
+                                            "empty": rows.is_empty(),
+                                            "pupils": pupils,
+                                            "assign_url": assign_url,
+                                        }),
+                                    )
+                                    .unwrap();
+
+                                let header: String = page::path_header(
+                                    &data,
+                                    &ctx.user.user_agent.privilege(),
+                                    &[
+                                        (
+                                            dir::ORGS_PAGE.to_owned(),
+                                            dir::ORGS_TITLE.to_owned(),
+                                            Privilege::RootLevel,
+                                        ),
+                                        (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+                                    ],
+                                );
+
+                                let nav = page::org_nav(
+                                    &ctx,
+                                    &data,
+                                    org_id,
+                                    &org,
+                                    dir::org_path(org_id) + dir::ASSOCIATES_PAGE,
+                                );
+
+                                let org_page = data
+                                    .handlebars
+                                    .render(
+                                        "org/org_root",
+                                        &json!({
+                                            "header": header,
+                                            "org_nav": nav,
+                                            "body": content,
+                                        }),
+                                    )
+                                    .unwrap();
+
+                                let body = page::render_page(
+                                    Some(ctx),
+                                    &data,
+                                    dir::APP_NAME.to_owned() + " | " + &associate.name() + " - " + &org.name,
+                                    dir::EXTENDED_APP_NAME.to_owned(),
+                                    org_page,
+                                )
+                                .unwrap();
+
+                                HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+                            }
+                            (Ok(Some(_)), _) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("No matching teacher")),
+                            _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from("Failed to fetch org!")),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CaseloadForm {
+    user_id: user::UserKey,
+    /// Present to remove the pupil from the caseload rather than add them.
+    remove: Option<String>,
+}
+
+#[post("/org/{org}/associates/{user}/caseload")]
+pub async fn caseload_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: web::Form<CaseloadForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(associate_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_manage_classes(&org_id) {
+                        if data.is_org_archived(&org_id) {
+                            return page::archived_org_page(Some(ctx), &data);
+                        }
+                        match data.set_caseload_assignment(&org_id, &associate_id, &form.user_id, form.remove.is_none()) {
+                            Ok(()) => HttpResponse::SeeOther()
+                                .header(http::header::LOCATION, dir::associate_path(org_id, associate_id))
+                                .body(""),
+                            Err(e) => page::error_page(Some(ctx), &data, "Could not assign pupil", &e.to_string()),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -0,0 +1,304 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::data::SharedData;
+
+use crate::certificate;
+use crate::dir;
+use crate::page;
+use crate::util;
+
+#[get("/certificate/{certificate}")]
+pub async fn certificate_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    certificate_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(certificate_id) = certificate::CertificateKey::from_str(&certificate_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.certificate_db.fetch(&certificate_id) {
+                Ok(Some(certificate)) => match data.user_db.fetch(&certificate.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &certificate.user_id, &user)
+                            || ctx.user_id == certificate.user_id
+                        {
+                            let icon_path: String = match data.awards.get(&certificate.award) {
+                                Some(award) => "static".to_owned() + &award.image_url,
+                                None => String::new(),
+                            };
+                            let filename: String = certificate.filename();
+                            match web::block(move || certificate.render_pdf(&icon_path)).await {
+                                Ok(pdf) => HttpResponse::Ok()
+                                    .content_type("application/pdf")
+                                    .set(ContentDisposition {
+                                        disposition: DispositionType::Attachment,
+                                        parameters: vec![DispositionParam::Filename(filename)],
+                                    })
+                                    .body(pdf),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Failed to create certificate: {}", e))),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching certificate")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid certificate_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerifyQuery {
+    pub code: Option<String>,
+}
+
+fn verify_page(data: &SharedData, req: &HttpRequest, code: &str, result: String) -> HttpResponse {
+    match data.authenticate_context_from_request(req, true) {
+        Ok(ctx) => {
+            let body: String = data
+                .handlebars
+                .render(
+                    "certificate/verify",
+                    &json!({
+                        "verify_url": dir::VERIFY_PATH,
+                        "code": code,
+                        "result": result,
+                    }),
+                )
+                .unwrap();
+
+            let body = page::render_page(
+                ctx,
+                data,
+                dir::APP_NAME.to_owned() + " | " + dir::VERIFY_TITLE,
+                dir::EXTENDED_APP_NAME.to_owned(),
+                body,
+            )
+            .unwrap();
+
+            HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+        }
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+/// The verification form. Submitting it redirects to the page for the code entered.
+#[get("/verify")]
+pub async fn verify_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    query: web::Query<VerifyQuery>,
+) -> HttpResponse {
+    match &query.code {
+        Some(code) if !code.trim().is_empty() => HttpResponse::SeeOther()
+            .header(
+                http::header::LOCATION,
+                dir::VERIFY_PATH.to_owned() + "/" + &certificate::Certificate::normalise_number(code),
+            )
+            .body(""),
+        _ => verify_page(&data, &req, "", String::new()),
+    }
+}
+
+/// Publicly confirms whether a certificate is genuine. Only the holder's name, award and issue date are shown.
+#[get("/verify/{code}")]
+pub async fn verify_code_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    code: web::Path<String>,
+) -> HttpResponse {
+    let number: String = certificate::Certificate::normalise_number(&code);
+    let result: String = match data.find_certificate(&number) {
+        Ok(Some((certificate_id, certificate))) => {
+            let revocation = match data.certificate_revocations_db.fetch(&certificate_id) {
+                Ok(revocation) => revocation,
+                Err(e) => {
+                    return HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e)))
+                }
+            };
+            let issued: chrono::DateTime<chrono::offset::Local> = certificate.completed.into();
+            data.handlebars
+                .render(
+                    "certificate/verify_result",
+                    &json!({
+                        "found": true,
+                        "number": certificate.number,
+                        "name": certificate.name,
+                        "award": certificate.award_name,
+                        "issued": issued.format("%d %B %Y").to_string(),
+                        // The reason is only shown to owners, as it may say more about the pupil than should be public.
+                        "revoked": revocation.map(|x| util::format_time(x.revoked)),
+                    }),
+                )
+                .unwrap()
+        }
+        Ok(None) => data
+            .handlebars
+            .render(
+                "certificate/verify_result",
+                &json!({
+                    "found": false,
+                    "number": number,
+                }),
+            )
+            .unwrap(),
+        Err(e) => {
+            return HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e)))
+        }
+    };
+    verify_page(&data, &req, &number, result)
+}
+
+#[get("/admin/certificates")]
+pub async fn certificates_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> HttpResponse {
+    match data.authenticate_context_from_request(&req, true) {
+        Ok(Some(ctx)) => {
+            if ctx.user.user_agent.can_revoke_certificates() {
+                let mut certificates: Vec<(certificate::CertificateKey, certificate::Certificate)> = Vec::new();
+                data.certificate_db.for_each(|certificate_id: &certificate::CertificateKey, certificate| {
+                    certificates.push((*certificate_id, certificate));
+                });
+                certificates.sort_by_key(|x| std::cmp::Reverse(x.1.completed));
+
+                let mut rows: String = String::new();
+                for (certificate_id, certificate) in certificates.iter() {
+                    let revocation = data.certificate_revocations_db.fetch(certificate_id).ok().flatten();
+                    rows += &data
+                        .handlebars
+                        .render(
+                            "certificate/certificate_row",
+                            &json!({
+                                "number": certificate.number,
+                                "name": certificate.name,
+                                "org_name": certificate.org_name,
+                                "award": certificate.award_name,
+                                "issued": util::format_time(certificate.completed),
+                                "verify_url": certificate.verify_path(),
+                                "revoke_url": "/certificate/".to_owned() + &certificate_id.to_string() + "/revoke",
+                                "revoked": revocation.as_ref().map(|x| util::format_time(x.revoked)),
+                                "reason": revocation.map(|x| x.reason),
+                            }),
+                        )
+                        .unwrap();
+                }
+
+                let body: String = data
+                    .handlebars
+                    .render(
+                        "certificate/certificate_list",
+                        &json!({
+                            "back_url": dir::ADMIN_PATH,
+                            "verify_url": dir::make_absolute_url(dir::VERIFY_PATH),
+                            "rows": rows,
+                            "empty": certificates.is_empty(),
+                        }),
+                    )
+                    .unwrap();
+
+                let body = page::render_page(
+                    Some(ctx),
+                    &data,
+                    dir::APP_NAME.to_owned() + " | " + dir::CERTIFICATES_TITLE,
+                    dir::EXTENDED_APP_NAME.to_owned(),
+                    body,
+                )
+                .unwrap();
+
+                HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+            } else {
+                page::not_authorized_page(Some(ctx), &data)
+            }
+        }
+        Ok(None) => page::redirect_to_login(&req),
+
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeForm {
+    pub reason: String,
+    /// Set to issue a new certificate straight away, for when the revoked one had a mistake in it.
+    pub reissue: Option<String>,
+}
+
+#[post("/certificate/{certificate}/revoke")]
+pub async fn revoke_certificate_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    certificate_path: web::Path<String>,
+    form: web::Form<RevokeForm>,
+) -> HttpResponse {
+    if let Ok(certificate_id) = certificate::CertificateKey::from_str(&certificate_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_revoke_certificates() {
+                    let reason: &str = form.reason.trim();
+                    if reason.is_empty() {
+                        return page::error_page(
+                            Some(ctx),
+                            &data,
+                            "Could not revoke certificate",
+                            "Please give a reason for revoking the certificate.",
+                        );
+                    }
+                    match data.certificate_db.fetch(&certificate_id) {
+                        Ok(Some(certificate)) if data.is_user_id_archived(&certificate.user_id) => {
+                            page::archived_org_page(Some(ctx), &data)
+                        }
+                        Ok(Some(certificate)) => match data.revoke_certificate(&certificate_id, reason, &ctx.user_id) {
+                            Ok(()) => {
+                                if form.reissue.is_some() {
+                                    if let Err(e) = data.reissue_certificate(&certificate.user_id) {
+                                        log::error!("Failed to reissue certificate! {}", e);
+                                    }
+                                }
+                                HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::CERTIFICATES_PATH)
+                                    .body("")
+                            }
+                            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from(format!("Error: {}", e))),
+                        },
+                        Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                            .set_body(Body::from("No matching certificate")),
+                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from(format!("Error: {}", e))),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid certificate_id"))
+    }
+}
@@ -0,0 +1,568 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::auth::AuthContext;
+use crate::data::SharedData;
+
+use crate::class;
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::section;
+use crate::user;
+use crate::util;
+
+use section::SectionState;
+use user::Privilege;
+
+/// Renders a bar showing how many of the pupils' sections are in each state, coloured as the sections are elsewhere.
+pub fn class_progress(data: &SharedData, clients: &[user::UserKey]) -> String {
+    let states: [SectionState; 4] = [
+        SectionState::Completed,
+        SectionState::InReview(std::time::UNIX_EPOCH),
+        SectionState::Rejected(String::new()),
+        SectionState::InProgress,
+    ];
+    let mut counts: [usize; 4] = [0; 4];
+    let mut not_started: usize = 0;
+
+    for user_id in clients.iter() {
+        if let Ok(Some(user)) = data.user_db.fetch(user_id) {
+            if let user::UserAgent::Client { sections, .. } = user.user_agent {
+                for section_id in sections.iter() {
+                    match section_id.map(|x| data.section_db.fetch(&x)) {
+                        Some(Ok(Some(section))) => {
+                            let idx = match section.state {
+                                SectionState::Completed => 0,
+                                SectionState::InReview(_) => 1,
+                                SectionState::Rejected(_) => 2,
+                                SectionState::InProgress => 3,
+                            };
+                            counts[idx] += 1;
+                        }
+                        _ => not_started += 1,
+                    }
+                }
+            }
+        }
+    }
+
+    let total: usize = counts.iter().sum::<usize>() + not_started;
+    let mut segments: Vec<_> = states
+        .iter()
+        .zip(counts.iter())
+        .filter(|(_, count)| **count > 0)
+        .map(|(state, count)| {
+            json!({
+                "label": state.to_string(),
+                "count": count,
+                "color": state.css_color(),
+                "width": *count as f64 * 100.0 / total as f64,
+            })
+        })
+        .collect();
+    if not_started > 0 {
+        segments.push(json!({
+            "label": "Not Started",
+            "count": not_started,
+            "color": "transparent",
+            "width": not_started as f64 * 100.0 / total as f64,
+        }));
+    }
+
+    data.handlebars
+        .render(
+            "class/class_progress",
+            &json!({
+                "segments": segments,
+                "empty": total == 0,
+            }),
+        )
+        .unwrap()
+}
+
+fn org_page(
+    data: &SharedData,
+    ctx: AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    title: &str,
+    content: String,
+) -> HttpResponse {
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+        ],
+    );
+
+    let nav = page::org_nav(
+        &ctx,
+        data,
+        org_id,
+        org,
+        dir::org_path(org_id) + dir::CLASSES_PAGE,
+    );
+
+    let org_page = data
+        .handlebars
+        .render(
+            "org/org_root",
+            &json!({
+                "header": header,
+                "org_nav": nav,
+                "body": content,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + title + " - " + &org.name,
+        dir::EXTENDED_APP_NAME.to_owned(),
+        org_page,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+fn teacher_names(data: &SharedData, associates: &[user::UserKey]) -> String {
+    associates
+        .iter()
+        .filter_map(|user_id| data.user_db.fetch(user_id).ok().flatten())
+        .map(|user| user.name())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[get("/org/{org}/classes")]
+pub async fn classes_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_view_org(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let classes = data.org_classes(&org_id);
+                            // Teachers restricted to their own pupils only see those pupils in each class.
+                            let restriction = data.associate_restriction(&ctx);
+                            let mut rows: String = String::new();
+                            for (class_id, class) in classes.iter() {
+                                let clients: Vec<user::UserKey> = class
+                                    .clients
+                                    .iter()
+                                    .filter(|x| restriction.as_ref().map_or(true, |r| r.contains(x)))
+                                    .copied()
+                                    .collect();
+                                rows += &data
+                                    .handlebars
+                                    .render(
+                                        "class/class_row",
+                                        &json!({
+                                            "class_url": dir::class_path(org_id, *class_id),
+                                            "name": &class.name,
+                                            "year_group": &class.year_group,
+                                            "teachers": teacher_names(&data, &class.associates),
+                                            "pupils": clients.len(),
+                                            "progress": class_progress(&data, &clients),
+                                        }),
+                                    )
+                                    .unwrap();
+                            }
+
+                            let content: String = data
+                                .handlebars
+                                .render(
+                                    "class/class_list",
+                                    &json!({
+                                        "rows": rows,
+                                        "empty": classes.is_empty(),
+                                        "can_manage": ctx.user.user_agent.can_manage_classes(&org_id),
+                                        "add_class_url": dir::org_path(org_id) + dir::CLASSES_PAGE,
+                                    }),
+                                )
+                                .unwrap();
+
+                            org_page(&data, ctx, org_id, &org, dir::CLASSES_TITLE, content)
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct AddClassForm {
+    name: String,
+    year_group: String,
+}
+
+#[post("/org/{org}/classes")]
+pub async fn add_class_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<AddClassForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_manage_classes(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    let name: &str = form.name.trim();
+                    let year_group: &str = form.year_group.trim();
+                    if !util::is_string_server_valid(name) || !util::is_optional_string_server_valid(year_group) {
+                        return page::error_page(
+                            Some(ctx),
+                            &data,
+                            "Could not add class",
+                            "Please enter a valid class name and year group.",
+                        );
+                    }
+                    match data.create_class(&org_id, name, year_group) {
+                        Ok(class_id) => HttpResponse::SeeOther()
+                            .header(http::header::LOCATION, dir::class_path(org_id, class_id))
+                            .body(""),
+                        Err(e) => page::error_page(Some(ctx), &data, "Could not add class", &e.to_string()),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[get("/org/{org}/class/{class}")]
+pub async fn class_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(class_id) = class::ClassKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_view_org(&org_id) {
+                        match (data.org_db.fetch(&org_id), data.class_db.fetch(&class_id)) {
+                            (Ok(Some(org)), Ok(Some(class))) if class.org_id == org_id => {
+                                let can_manage: bool = ctx.user.user_agent.can_manage_classes(&org_id);
+                                let classes = data.org_classes(&org_id);
+                                let restriction = data.associate_restriction(&ctx);
+                                let is_visible = |user_id: &user::UserKey| restriction.as_ref().map_or(true, |x| x.contains(user_id));
+                                let clients: Vec<user::UserKey> =
+                                    class.clients.iter().filter(|x| is_visible(x)).copied().collect();
+
+                                let mut rows: String = String::new();
+                                for user_id in clients.iter() {
+                                    if let Ok(Some(user)) = data.user_db.fetch(user_id) {
+                                        let move_options: Vec<_> = classes
+                                            .iter()
+                                            .filter(|(id, _)| id != &class_id)
+                                            .map(|(id, x)| {
+                                                json!({
+                                                    "class_id": id,
+                                                    "title": x.title(),
+                                                })
+                                            })
+                                            .collect();
+                                        rows += &data
+                                            .handlebars
+                                            .render(
+                                                "class/class_member_row",
+                                                &json!({
+                                                    "client_url": dir::client_path(org_id, *user_id),
+                                                    "name": user.name(),
+                                                    "email": user.email,
+                                                    "user_id": user_id,
+                                                    "can_manage": can_manage,
+                                                    "move_url": dir::org_path(org_id) + dir::MOVE_CLIENT_PATH,
+                                                    "move_options": move_options,
+                                                }),
+                                            )
+                                            .unwrap();
+                                    }
+                                }
+
+                                // Pupils who can be added to this class, including those in another class.
+                                let mut pupils: Vec<_> = Vec::new();
+                                for user_id in org.clients.iter().filter(|x| !class.clients.contains(x) && is_visible(x)) {
+                                    if let Ok(Some(user)) = data.user_db.fetch(user_id) {
+                                        if let user::UserAgent::Client { class: ref class_name, .. } = user.user_agent {
+                                            pupils.push(json!({
+                                                "user_id": user_id,
+                                                "name": user.name(),
+                                                "class": class_name,
+                                            }));
+                                        }
+                                    }
+                                }
+
+                                let teachers: Vec<_> = org
+                                    .associates
+                                    .iter()
+                                    .filter_map(|user_id| {
+                                        data.user_db.fetch(user_id).ok().flatten().map(|user| {
+                                            json!({
+                                                "user_id": user_id,
+                                                "name": user.name(),
+                                                "selected": class.associates.contains(user_id),
+                                            })
+                                        })
+                                    })
+                                    .collect();
+
+                                let content: String = data
+                                    .handlebars
+                                    .render(
+                                        "class/class_page",
+                                        &json!({
+                                            "back_url": dir::org_path(org_id) + dir::CLASSES_PAGE,
+                                            "title": class.title(),
+                                            "name": &class.name,
+                                            "year_group": &class.year_group,
+                                            "teacher_names": teacher_names(&data, &class.associates),
+                                            "progress": class_progress(&data, &clients),
+                                            "rows": rows,
+                                            "empty": clients.is_empty(),
+                                            "can_manage": can_manage,
+                                            "class_url": dir::class_path(org_id, class_id),
+                                            "delete_url": dir::class_path(org_id, class_id) + "/delete",
+                                            "move_url": dir::org_path(org_id) + dir::MOVE_CLIENT_PATH,
+                                            "class_id": class_id,
+                                            "teachers": teachers,
+                                            "pupils": pupils,
+                                        }),
+                                    )
+                                    .unwrap();
+
+                                org_page(&data, ctx, org_id, &org, &class.name, content)
+                            }
+                            (Ok(Some(_)), _) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("No matching class")),
+                            _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from("Failed to fetch org!")),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid class_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+/// Updates the name, year group and teachers of a class. The form is read as a list of pairs since
+/// each selected teacher is submitted as a separate `associate` field.
+#[post("/org/{org}/class/{class}")]
+pub async fn class_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: web::Form<Vec<(String, String)>>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(class_id) = class::ClassKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_manage_classes(&org_id) {
+                        if data.is_org_archived(&org_id) {
+                            return page::archived_org_page(Some(ctx), &data);
+                        }
+                        match (data.org_db.fetch(&org_id), data.class_db.fetch(&class_id)) {
+                            (Ok(Some(org)), Ok(Some(class))) if class.org_id == org_id => {
+                                let field = |name: &str| {
+                                    form.iter()
+                                        .find(|(k, _)| k == name)
+                                        .map(|(_, v)| v.trim().to_owned())
+                                        .unwrap_or_default()
+                                };
+                                let name: String = field("name");
+                                let year_group: String = field("year_group");
+                                if !util::is_string_server_valid(&name)
+                                    || !util::is_optional_string_server_valid(&year_group)
+                                {
+                                    return page::error_page(
+                                        Some(ctx),
+                                        &data,
+                                        "Could not update class",
+                                        "Please enter a valid class name and year group.",
+                                    );
+                                }
+                                let associates: Vec<user::UserKey> = form
+                                    .iter()
+                                    .filter(|(k, _)| k == "associate")
+                                    .filter_map(|(_, v)| user::UserKey::from_str(v).ok())
+                                    .filter(|x| org.associates.contains(x))
+                                    .collect();
+
+                                match data.update_class(&class_id, &name, &year_group, associates) {
+                                    Ok(()) => HttpResponse::SeeOther()
+                                        .header(http::header::LOCATION, dir::class_path(org_id, class_id))
+                                        .body(""),
+                                    Err(e) => page::error_page(Some(ctx), &data, "Could not update class", &e.to_string()),
+                                }
+                            }
+                            (Ok(Some(_)), _) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("No matching class")),
+                            _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from("Failed to fetch org!")),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid class_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[post("/org/{org}/class/{class}/delete")]
+pub async fn delete_class_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(class_id) = class::ClassKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_manage_classes(&org_id) {
+                        if data.is_org_archived(&org_id) {
+                            return page::archived_org_page(Some(ctx), &data);
+                        }
+                        match data.class_db.fetch(&class_id) {
+                            Ok(Some(class)) if class.org_id == org_id => match data.delete_class(&class_id) {
+                                Ok(()) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::org_path(org_id) + dir::CLASSES_PAGE)
+                                    .body(""),
+                                Err(e) => page::error_page(Some(ctx), &data, "Could not delete class", &e.to_string()),
+                            },
+                            Ok(_) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("No matching class")),
+                            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from(format!("Error: {}", e))),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid class_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct MoveClientForm {
+    user_id: user::UserKey,
+    /// Empty to remove the pupil from their class.
+    class_id: String,
+}
+
+#[post("/org/{org}/move_client")]
+pub async fn move_client_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<MoveClientForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_manage_classes(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    let class_id: Option<class::ClassKey> = if form.class_id.is_empty() {
+                        None
+                    } else {
+                        match class::ClassKey::from_str(&form.class_id) {
+                            Ok(class_id) => Some(class_id),
+                            Err(_) => {
+                                return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                    .set_body(Body::from("Invalid class_id"))
+                            }
+                        }
+                    };
+                    match data.move_client(&org_id, &form.user_id, class_id.as_ref()) {
+                        Ok(()) => {
+                            let mut r = HttpResponse::SeeOther();
+                            match req.headers().get("Referer") {
+                                Some(referer) => r.header(http::header::LOCATION, referer.clone()),
+                                None => r.header(http::header::LOCATION, dir::org_path(org_id) + dir::CLASSES_PAGE),
+                            };
+                            r.body("")
+                        }
+                        Err(e) => page::error_page(Some(ctx), &data, "Could not move pupil", &e.to_string()),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -1,3 +1,4 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
 use actix_web::{body::Body, http, web, HttpRequest, HttpResponse};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -6,11 +7,13 @@ use serde_json::json;
 
 use crate::data::SharedData;
 
+use crate::class;
 use crate::dir;
-use crate::link;
+use crate::export;
 use crate::login;
 use crate::org;
 use crate::page;
+use crate::portfolio;
 use crate::section;
 use crate::user;
 use crate::util;
@@ -19,11 +22,18 @@ use user::Privilege;
 
 use actix_web::{get, post};
 
+#[derive(serde::Deserialize)]
+pub struct ClientsQuery {
+    /// A class id, or "none" for pupils who aren't in a class.
+    class: Option<String>,
+}
+
 #[get("/org/{org}/clients")]
 pub async fn clients_get(
     data: web::Data<Arc<SharedData>>,
     req: HttpRequest,
     org_path_str: web::Path<String>,
+    query: web::Query<ClientsQuery>,
 ) -> HttpResponse {
     if let Ok(org_id) = org::OrgKey::from_str(&org_path_str) {
         match data.authenticate_context_from_request(&req, true) {
@@ -31,9 +41,11 @@ pub async fn clients_get(
                 if ctx.user.user_agent.can_view_org(&org_id) {
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
-                            let mut rows: String = String::new();
+                            let mut client_rows: Vec<(user::UserKey, String)> = Vec::new();
+                            let restriction = data.associate_restriction(&ctx);
+                            let is_visible = |user_id: &user::UserKey| restriction.as_ref().map_or(true, |x| x.contains(user_id));
 
-                            for user_id in org.clients.iter() {
+                            for user_id in org.clients.iter().filter(|x| is_visible(x)) {
                                 match data.user_db.fetch(user_id) {
                                     Ok(Some(user)) => {
                                         if let user::UserAgent::Client {
@@ -75,7 +87,7 @@ pub async fn clients_get(
 
                                                     let completed: bool = completed_count == sections.len();
 
-                                                    rows += &data.handlebars.render("client/client_row", &json!({
+                                                    client_rows.push((*user_id, data.handlebars.render("client/client_row", &json!({
                                                         "client_url": dir::client_path(org_id, *user_id),
                                                         "user_url": dir::user_path(*user_id),
                                                         "user_id": user_id,
@@ -86,7 +98,7 @@ pub async fn clients_get(
                                                         "section_styles": section_styles,
                                                         "unreviewed_sections": unreviewed.to_string(),
                                                         "completed": completed,
-                                                    })).unwrap();
+                                                    })).unwrap()));
                                                 } else {
                                                     log::error!("[clients_get] Error - no award for id: {}", award);
                                                 }
@@ -97,10 +109,63 @@ pub async fn clients_get(
                                 }
                             }
 
+                            // Pupils are grouped by class, with those not in a class listed last.
+                            let classes = data.org_classes(&org_id);
+                            let mut groups: Vec<(Option<class::ClassKey>, String, Vec<user::UserKey>)> = classes
+                                .iter()
+                                .map(|(class_id, class)| {
+                                    let members = class.clients.iter().filter(|x| is_visible(x)).copied().collect();
+                                    (Some(*class_id), class.title(), members)
+                                })
+                                .collect();
+                            let unassigned: Vec<user::UserKey> = org
+                                .clients
+                                .iter()
+                                .filter(|x| is_visible(x) && !classes.iter().any(|(_, class)| class.clients.contains(x)))
+                                .copied()
+                                .collect();
+                            groups.push((None, "No Class".to_owned(), unassigned));
+
+                            let selected_class: Option<&str> = query.class.as_deref().filter(|x| !x.is_empty());
+                            let mut rows: String = String::new();
+                            for (class_id, title, members) in groups.iter() {
+                                let group_key: String = class_id.map(|x| x.to_string()).unwrap_or_else(|| "none".to_owned());
+                                if members.is_empty() || matches!(selected_class, Some(x) if x != group_key) {
+                                    continue;
+                                }
+                                rows += &data.handlebars.render("client/client_group_row", &json!({
+                                    "title": title,
+                                    "class_url": class_id.map(|x| dir::class_path(org_id, x)),
+                                    "progress": page::classes::class_progress(&data, members),
+                                })).unwrap();
+                                for (_, row) in client_rows.iter().filter(|(user_id, _)| members.contains(user_id)) {
+                                    rows += row;
+                                }
+                            }
+
+                            let class_filters: Vec<_> = classes
+                                .iter()
+                                .map(|(class_id, class)| json!({
+                                    "value": class_id,
+                                    "title": class.title(),
+                                    "selected": selected_class == Some(&class_id.to_string()),
+                                }))
+                                .chain(std::iter::once(json!({
+                                    "value": "none",
+                                    "title": "No Class",
+                                    "selected": selected_class == Some("none"),
+                                })))
+                                .collect();
+
                             let add_client_button: String = {
                                 if org.credits > 0 {
                                     data.handlebars.render("client/add_client_button", &json!({
                                         "add_client_url": dir::org_path(org_id) + dir::ADD_CLIENT_PATH,
+                                        "import_url": if ctx.user.user_agent.can_import_users(&org_id) {
+                                            Some(dir::org_path(org_id) + dir::IMPORT_CLIENTS_PATH)
+                                        } else {
+                                            None
+                                        },
                                     })).unwrap()
                                 } else {
                                     String::new()
@@ -113,8 +178,15 @@ pub async fn clients_get(
                                     "client/client_list",
                                     &json!({
                                         "credits": org.credits,
+                                        "statement_url": dir::org_path(org_id) + dir::CREDITS_PATH,
                                         "add_client_button": add_client_button,
+                                        "roster_url": if ctx.user.user_agent.can_import_users(&org_id) {
+                                            Some(dir::org_path(org_id) + dir::IMPORT_ROSTER_PATH)
+                                        } else {
+                                            None
+                                        },
                                         "client_rows": rows,
+                                        "class_filters": class_filters,
                                         "delete_user_url": dir::DELETE_USER_PATH.to_owned(),
                                     }),
                                 )
@@ -193,17 +265,26 @@ pub fn add_client_page(
                 if ctx.user.user_agent.can_view_org(&org_id) {
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
+                            let default_award = data.org_settings(&org_id).default_award;
+                            let classes: Vec<_> = data.org_classes(&org_id).into_iter().map(|(_, class)| {
+                                json!({
+                                    "name": &class.name,
+                                    "title": class.title(),
+                                })
+                            }).collect();
                             let awards: Vec<_> =
                                 data.awards.iter().map(|(id, x)| { 
                                     json!({
                                         "title": x.name.clone(),
                                         "award": id,
+                                        "selected": default_award.as_ref() == Some(id),
                                     })
                                 }).collect();
 
                             let content = data.handlebars.render("client/add_client", &json!({
                                 "back_url": dir::org_path(org_id) + dir::CLIENTS_PAGE,
                                 "awards": awards,
+                                "classes": classes,
                                 "add_client_url": dir::org_path(org_id) + dir::ADD_CLIENT_PATH,
                                 "err_msg": err_msg,
                             })).unwrap();
@@ -298,6 +379,9 @@ pub async fn add_client_post(
         match data.authenticate_context_from_request(&req, true) {
             Ok(Some(ctx)) => {
                 if ctx.user.user_agent.can_view_org(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
                             if org.credits > 0 {
@@ -350,43 +434,15 @@ pub async fn add_client_post(
                                             award: form.award.clone(),
                                             sections: [None; 6],
                                         },
+                                        external_id: None,
                                     };
 
                                     let password: String = util::gen_password(8);
 
-                                    match data.register_user(&user, &password, true)  {
+                                    match data.register_user(&user, &password, true, Some(ctx.user_id))  {
                                         Ok(user_id) => {
-                                            if let Ok(link_token) = data.link_manager.create_link(link::Link::ChangePassword(user_id), std::time::Duration::from_secs(dir::CHANE_PASSWORD_LINK_TIMEOUT_SECS)) {
-                                                // send email.
-                                                let link: String = dir::make_absolute_url(&("/user/change_password/".to_string() + &link_token.to_string()));
-                                                let addr: String = form.email.clone();
-                                                
-                                                let subtitle: String = data
-                                                .handlebars
-                                                .render(
-                                                    "email/account_created",
-                                                    &json!({
-                                                        "name": user.name(),
-                                                        "account_type": "pupil",
-                                                        "org_name": &org.name,
-                                                        "username": &user.email,
-                                                        "password": &password,
-                                                        "link": link,
-                                                    }),
-                                                )
-                                            .unwrap();
+                                            page::send_welcome_email(&data, &user, user_id, "pupil", &org.name, &password);
 
-                                                if data.send_email(
-                                                    &addr, 
-                                                    "Senior Duke - Welcome & Password Info", 
-                                                    "Senior Duke - Welcome & Password Info",
-                                                    &subtitle, 
-                                                    ""
-                                                ).is_none() {
-                                                    log::error!("Failed to send email!");
-                                                }
-                                            }
-                                            
                                             let mut attrs: String = String::new();
 
                                             attrs += &data.handlebars.render("user/user_attribute", &json!({
@@ -426,11 +482,12 @@ pub async fn add_client_post(
 
                                         },
                                         Err(login::LoginEntryError::UsernameExists) =>  add_client_page(data, req, org_path_str, "This email is associated with another account!"),
+                                        Err(login::LoginEntryError::CreditError(_)) =>  add_client_page(data, req, org_path_str, "No more pupil credits remaining! More credits can be requested from the Credits page."),
                                         Err(e) =>  add_client_page(data, req, org_path_str, &format!("Something went wrong: ensure that the email is unique: {}", e)),
                                     }
                                 }
                             } else {
-                                add_client_page(data, req, org_path_str, "No more pupil credits remaining! Please contact support to purchase more.")
+                                add_client_page(data, req, org_path_str, "No more pupil credits remaining! More credits can be requested from the Credits page.")
                             }
                         }
                         _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
@@ -467,11 +524,12 @@ pub async fn client_dashboard_get(
                             ..
                         } = &user.user_agent
                         {
-                            if ctx.user.user_agent.can_view_user(&user.user_agent)
+                            if data.can_view_user(&ctx, &user_id, &user)
                                 || ctx.user_id == user_id
                             {
                                 match data.org_db.fetch(&org_id) {
                                     Ok(Some(org)) => {
+                                        let award_id: &str = award;
                                         if let Some(award) = data.awards.get(award) {
                                             let mut sections_body: String = String::new();
                                             let mut completed_count: usize = 0;
@@ -536,6 +594,13 @@ pub async fn client_dashboard_get(
 
                                             let completed = completed_count == award.sections.len();
 
+                                            let certificate_url: Option<String> = if completed {
+                                                data.certificate_for_user(&user_id, award_id)
+                                                    .map(|(certificate_id, _)| "/certificate/".to_owned() + &certificate_id.to_string())
+                                            } else {
+                                                None
+                                            };
+
                                             let body: String = data
                                                 .handlebars
                                                 .render(
@@ -545,6 +610,16 @@ pub async fn client_dashboard_get(
                                                         "award": &award.name,
                                                         "sections": sections_body,
                                                         "completed": completed,
+                                                        "download_url": dir::client_path(org_id, user_id) + dir::DOWNLOAD_PATH,
+                                                        "portfolio_url": dir::client_path(org_id, user_id) + dir::PORTFOLIO_PATH,
+                                                        "trash_url": dir::client_path(org_id, user_id) + dir::TRASH_PATH,
+                                                        "awards_url": dir::client_path(org_id, user_id) + dir::AWARDS_PATH,
+                                                        "transfer_url": if ctx.user.user_agent.can_transfer_clients() {
+                                                            Some(dir::client_path(org_id, user_id) + dir::TRANSFER_PATH)
+                                                        } else {
+                                                            None
+                                                        },
+                                                        "certificate_url": certificate_url,
                                                     }),
                                                 )
                                                 .unwrap();
@@ -641,3 +716,134 @@ pub async fn client_dashboard_get(
         HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
     }
 }
+
+#[get("/org/{org}/client/{user}/download")]
+pub async fn client_download_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => {
+                        if user.user_agent.is_client() && user.user_agent.org_id() == Some(org_id) {
+                            if data.can_view_user(&ctx, &user_id, &user)
+                                || ctx.user_id == user_id
+                            {
+                                match data.temp_path("zip") {
+                                    Ok(path) => {
+                                        let filename: String = sanitize_filename::sanitize(user.name() + " - Evidence.zip");
+                                        let zip_data: Arc<SharedData> = data.get_ref().clone();
+                                        let zip_path: String = path.clone();
+                                        match web::block(move || export::write_client_zip(&zip_data, &zip_path, &user)).await {
+                                            Ok(_) => page::temp_file_response(&req, path, filename).await,
+                                            Err(e) => page::temp_file_error(&path, e),
+                                        }
+                                    }
+                                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                        .set_body(Body::from(format!("Error: {}", e))),
+                                }
+                            } else {
+                                page::not_authorized_page(Some(ctx), &data)
+                            }
+                        } else {
+                            HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("User is not a client of this organisation!"))
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+/// A printable portfolio of a pupil's work, either as a PDF or a single HTML file with the photos embedded.
+#[get("/org/{org}/client/{user}/portfolio/{format}")]
+pub async fn client_portfolio_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            let is_pdf: bool = match (path.0).2.as_str() {
+                "pdf" => true,
+                "html" => false,
+                _ => return HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid format")),
+            };
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => {
+                        if user.user_agent.is_client() && user.user_agent.org_id() == Some(org_id) {
+                            if data.can_view_user(&ctx, &user_id, &user)
+                                || ctx.user_id == user_id
+                            {
+                                let name: String = user.name();
+                                let portfolio_data: Arc<SharedData> = data.get_ref().clone();
+                                let portfolio = web::block(move || {
+                                    if is_pdf {
+                                        portfolio::render_pdf(&portfolio_data, &user)
+                                    } else {
+                                        Ok(portfolio::render_html(&portfolio_data, &user).into_bytes())
+                                    }
+                                })
+                                .await;
+                                match portfolio {
+                                    Ok(portfolio) => {
+                                        let (content_type, extension) = if is_pdf {
+                                            ("application/pdf", "pdf")
+                                        } else {
+                                            ("text/html; charset=utf-8", "html")
+                                        };
+                                        HttpResponse::Ok()
+                                            .content_type(content_type)
+                                            .set(ContentDisposition {
+                                                disposition: DispositionType::Attachment,
+                                                parameters: vec![DispositionParam::Filename(sanitize_filename::sanitize(
+                                                    name + " - Portfolio." + extension,
+                                                ))],
+                                            })
+                                            .body(portfolio)
+                                    }
+                                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                        .set_body(Body::from(format!("Failed to create portfolio: {}", e))),
+                                }
+                            } else {
+                                page::not_authorized_page(Some(ctx), &data)
+                            }
+                        } else {
+                            HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("User is not a client of this organisation!"))
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -0,0 +1,493 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::data::SharedData;
+
+use crate::credit;
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+/// A transaction as shown on a statement, with the balance after it was made.
+struct StatementLine {
+    transaction: credit::CreditTransaction,
+    actor: String,
+    balance: i64,
+}
+
+/// Lists the transactions of an org, newest first.
+fn statement(data: &SharedData, transactions: Vec<credit::CreditTransaction>) -> Vec<StatementLine> {
+    let mut balance: i64 = 0;
+    let mut lines: Vec<StatementLine> = transactions
+        .into_iter()
+        .map(|transaction| {
+            balance += transaction.amount;
+            let actor: String = match &transaction.actor {
+                Some(actor) => match data.user_db.fetch(actor) {
+                    Ok(Some(user)) => user.name(),
+                    _ => "Deleted User".to_owned(),
+                },
+                None => "System".to_owned(),
+            };
+            StatementLine {
+                transaction,
+                actor,
+                balance,
+            }
+        })
+        .collect();
+    lines.reverse();
+    lines
+}
+
+fn format_amount(amount: i64) -> String {
+    if amount > 0 {
+        format!("+{}", amount)
+    } else {
+        amount.to_string()
+    }
+}
+
+fn user_name(data: &SharedData, user_id: &user::UserKey) -> String {
+    match data.user_db.fetch(user_id) {
+        Ok(Some(user)) => user.name(),
+        _ => "Deleted User".to_owned(),
+    }
+}
+
+/// Renders the rows of a credit request table. The org name and the approve/decline actions are only shown in the owners' queue.
+fn request_rows(
+    data: &SharedData,
+    requests: &[(credit::CreditRequestKey, credit::CreditRequest)],
+    queue: bool,
+) -> String {
+    let mut rows: String = String::new();
+    for (request_id, request) in requests.iter() {
+        let org_name: String = match data.org_db.fetch(&request.org_id) {
+            Ok(Some(org)) => org.name,
+            _ => "Deleted Organisation".to_owned(),
+        };
+        let tz = data.org_settings(&request.org_id).tz();
+        rows += &data
+            .handlebars
+            .render(
+                "credits/request_row",
+                &json!({
+                    "requested": util::format_time_tz(request.requested, &tz),
+                    "requested_by": user_name(data, &request.requested_by),
+                    "org_name": org_name,
+                    "org_url": dir::org_path(request.org_id) + dir::CREDITS_PATH,
+                    "quantity": request.quantity,
+                    "reference": &request.reference,
+                    "notes": &request.notes,
+                    "status": request.status.to_string(),
+                    "pending": request.status == credit::CreditRequestStatus::Pending,
+                    "approved": request.status == credit::CreditRequestStatus::Approved,
+                    "decided": request.decided.map(|x| util::format_time_tz(x, &tz)),
+                    "decided_by": request.decided_by.as_ref().map(|x| user_name(data, x)),
+                    "response": &request.response,
+                    "queue": queue,
+                    "request_url": "/credit_request/".to_owned() + &request_id.to_string(),
+                }),
+            )
+            .unwrap();
+    }
+    rows
+}
+
+/// Emails the user who made a credit request, and every owner, with the same message.
+fn notify_credit_request(data: &SharedData, request: &credit::CreditRequest, subject: &str, content: &str) {
+    let mut addresses: Vec<String> = Vec::new();
+    if let Ok(Some(user)) = data.user_db.fetch(&request.requested_by) {
+        addresses.push(user.email);
+    }
+    data.user_db.for_each_val(|user| {
+        if user.user_agent.can_approve_credit_requests() && !addresses.contains(&user.email) {
+            addresses.push(user.email);
+        }
+    });
+
+    let org_name: String = match data.org_db.fetch(&request.org_id) {
+        Ok(Some(org)) => org.name,
+        _ => String::new(),
+    };
+    let subtitle: String = org_name + " - " + &request.quantity.to_string() + " credits";
+    for address in addresses.iter() {
+        if data
+            .send_email(address, &("Senior Duke - ".to_owned() + subject), subject, &subtitle, content)
+            .is_none()
+        {
+            log::error!("Failed to send credit request email to {}", address);
+        }
+    }
+}
+
+fn write_csv(lines: &[StatementLine], tz: &chrono_tz::Tz) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["Date", "Type", "Details", "By", "Change", "Balance"])?;
+    for line in lines.iter() {
+        let time: chrono::DateTime<chrono::Utc> = line.transaction.time.into();
+        let time = time.with_timezone(tz);
+        writer.write_record([
+            time.format("%Y-%m-%d %H:%M").to_string(),
+            line.transaction.kind.to_string(),
+            line.transaction.reason.clone(),
+            line.actor.clone(),
+            format_amount(line.transaction.amount),
+            line.balance.to_string(),
+        ])?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+#[get("/org/{org}/credits")]
+pub async fn credits_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_view_org(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => match data.credit_transactions(&org_id) {
+                            Ok(transactions) => {
+                                let lines = statement(&data, transactions);
+                                let requests = data.credit_requests(Some(&org_id));
+                                let tz = data.org_settings(&org_id).tz();
+                                let mut rows: String = String::new();
+                                for line in lines.iter() {
+                                    rows += &data
+                                        .handlebars
+                                        .render(
+                                            "credits/statement_row",
+                                            &json!({
+                                                "time": util::format_time_tz(line.transaction.time, &tz),
+                                                "kind": line.transaction.kind.to_string(),
+                                                "reason": &line.transaction.reason,
+                                                "actor": &line.actor,
+                                                "amount": format_amount(line.transaction.amount),
+                                                "positive": line.transaction.amount > 0,
+                                                "balance": line.balance,
+                                            }),
+                                        )
+                                        .unwrap();
+                                }
+
+                                let content: String = data
+                                    .handlebars
+                                    .render(
+                                        "credits/statement",
+                                        &json!({
+                                            "credits": org.credits,
+                                            "rows": rows,
+                                            "empty": lines.is_empty(),
+                                            "csv_url": dir::org_path(org_id) + dir::CREDITS_PATH + "/csv",
+                                            "can_request": ctx.user.user_agent.can_request_credits(&org_id),
+                                            "request_url": dir::org_path(org_id) + dir::CREDITS_PATH + "/request",
+                                            "request_rows": request_rows(&data, &requests, false),
+                                            "requests_empty": requests.is_empty(),
+                                        }),
+                                    )
+                                    .unwrap();
+
+                                let header: String = page::path_header(
+                                    &data,
+                                    &ctx.user.user_agent.privilege(),
+                                    &[
+                                        (
+                                            dir::ORGS_PAGE.to_owned(),
+                                            dir::ORGS_TITLE.to_owned(),
+                                            Privilege::RootLevel,
+                                        ),
+                                        (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+                                    ],
+                                );
+
+                                let nav = page::org_nav(
+                                    &ctx,
+                                    &data,
+                                    org_id,
+                                    &org,
+                                    dir::org_path(org_id) + dir::CREDITS_PATH,
+                                );
+
+                                let org_page = data
+                                    .handlebars
+                                    .render(
+                                        "org/org_root",
+                                        &json!({
+                                            "header": header,
+                                            "org_nav": nav,
+                                            "body": content,
+                                        }),
+                                    )
+                                    .unwrap();
+
+                                let body = page::render_page(
+                                    Some(ctx),
+                                    &data,
+                                    dir::APP_NAME.to_owned() + " | " + dir::CREDITS_TITLE + " - " + &org.name,
+                                    dir::EXTENDED_APP_NAME.to_owned(),
+                                    org_page,
+                                )
+                                .unwrap();
+
+                                HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+                            }
+                            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from(format!("Error: {}", e))),
+                        },
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[get("/org/{org}/credits/csv")]
+pub async fn credits_csv_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_view_org(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => match data.credit_transactions(&org_id) {
+                            Ok(transactions) => match write_csv(&statement(&data, transactions), &data.org_settings(&org_id).tz()) {
+                                Ok(csv) => HttpResponse::Ok()
+                                    .content_type("text/csv; charset=utf-8")
+                                    .set(ContentDisposition {
+                                        disposition: DispositionType::Attachment,
+                                        parameters: vec![DispositionParam::Filename(sanitize_filename::sanitize(
+                                            org.name + " - Credit Statement.csv",
+                                        ))],
+                                    })
+                                    .body(csv),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Failed to create statement: {}", e))),
+                            },
+                            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from(format!("Error: {}", e))),
+                        },
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreditRequestForm {
+    pub quantity: u32,
+    pub reference: String,
+    pub notes: String,
+}
+
+#[post("/org/{org}/credits/request")]
+pub async fn credit_request_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<CreditRequestForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_request_credits(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    if form.quantity == 0 {
+                        return page::error_page(
+                            Some(ctx),
+                            &data,
+                            "Could not request credits",
+                            "Please request at least one credit.",
+                        );
+                    }
+                    let request = credit::CreditRequest::new(
+                        org_id,
+                        ctx.user_id,
+                        form.quantity,
+                        form.reference.trim().to_owned(),
+                        form.notes.trim().to_owned(),
+                    );
+                    match data.credit_requests_db.insert(&credit::CreditRequestKey::generate(), &request) {
+                        Ok(_) => {
+                            let content: String = ctx.user.name()
+                                + " has requested "
+                                + &request.quantity.to_string()
+                                + " pupil credits (reference: "
+                                + if request.reference.is_empty() { "none" } else { &request.reference }
+                                + "). The request will be reviewed by an administrator.";
+                            notify_credit_request(&data, &request, "Credit Request Received", &content);
+                            HttpResponse::SeeOther()
+                                .header(http::header::LOCATION, dir::org_path(org_id) + dir::CREDITS_PATH)
+                                .body("")
+                        }
+                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from(format!("Error: {}", e))),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[get("/admin/credit_requests")]
+pub async fn credit_requests_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> HttpResponse {
+    match data.authenticate_context_from_request(&req, true) {
+        Ok(Some(ctx)) => {
+            if ctx.user.user_agent.can_approve_credit_requests() {
+                // Pending requests are listed first, then the history of decided requests.
+                let (pending, decided): (Vec<_>, Vec<_>) = data
+                    .credit_requests(None)
+                    .into_iter()
+                    .partition(|(_, request)| request.status == credit::CreditRequestStatus::Pending);
+
+                let body: String = data
+                    .handlebars
+                    .render(
+                        "credits/request_queue",
+                        &json!({
+                            "back_url": dir::ADMIN_PATH,
+                            "pending_rows": request_rows(&data, &pending, true),
+                            "pending_empty": pending.is_empty(),
+                            "decided_rows": request_rows(&data, &decided, true),
+                            "decided_empty": decided.is_empty(),
+                        }),
+                    )
+                    .unwrap();
+
+                let body = page::render_page(
+                    Some(ctx),
+                    &data,
+                    dir::APP_NAME.to_owned() + " | " + dir::CREDIT_REQUESTS_TITLE,
+                    dir::EXTENDED_APP_NAME.to_owned(),
+                    body,
+                )
+                .unwrap();
+
+                HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+            } else {
+                page::not_authorized_page(Some(ctx), &data)
+            }
+        }
+        Ok(None) => page::redirect_to_login(&req),
+
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreditRequestDecisionForm {
+    pub response: String,
+}
+
+fn decide_credit_request(
+    data: &SharedData,
+    req: &HttpRequest,
+    request_path: &str,
+    approve: bool,
+    response: &str,
+) -> HttpResponse {
+    if let Ok(request_id) = credit::CreditRequestKey::from_str(request_path) {
+        match data.authenticate_context_from_request(req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_approve_credit_requests() {
+                    let response: &str = response.trim();
+                    match data.decide_credit_request(&request_id, approve, &ctx.user_id, response) {
+                        Ok(request) => {
+                            let (subject, mut content): (&str, String) = if approve {
+                                (
+                                    "Credit Request Approved",
+                                    request.quantity.to_string() + " pupil credits have been added to the organisation.",
+                                )
+                            } else {
+                                ("Credit Request Declined", "The request for pupil credits was declined.".to_owned())
+                            };
+                            if !response.is_empty() {
+                                content += &(" Message: ".to_owned() + response);
+                            }
+                            notify_credit_request(data, &request, subject, &content);
+                            HttpResponse::SeeOther()
+                                .header(http::header::LOCATION, dir::CREDIT_REQUESTS_PATH)
+                                .body("")
+                        }
+                        Err(e) => page::error_page(Some(ctx), data, "Could not update credit request", &e.to_string()),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid request_id"))
+    }
+}
+
+#[post("/credit_request/{request}/approve")]
+pub async fn approve_credit_request_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    request_path: web::Path<String>,
+    form: web::Form<CreditRequestDecisionForm>,
+) -> HttpResponse {
+    decide_credit_request(&data, &req, &request_path, true, &form.response)
+}
+
+#[post("/credit_request/{request}/decline")]
+pub async fn decline_credit_request_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    request_path: web::Path<String>,
+    form: web::Form<CreditRequestDecisionForm>,
+) -> HttpResponse {
+    decide_credit_request(&data, &req, &request_path, false, &form.response)
+}
@@ -0,0 +1,254 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::section;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+fn completed_sections(data: &SharedData, sections: &[Option<section::SectionKey>; 6]) -> usize {
+    sections
+        .iter()
+        .flatten()
+        .filter(|section_id| match data.section_db.fetch(section_id) {
+            Ok(Some(section)) => section.state.is_completed(),
+            _ => false,
+        })
+        .count()
+}
+
+fn enrolments_page(
+    data: &SharedData,
+    ctx: crate::auth::AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    user_id: user::UserKey,
+    user: &user::User,
+) -> HttpResponse {
+    let (current_award, current_sections) = match &user.user_agent {
+        user::UserAgent::Client { award, sections, .. } => (award.clone(), *sections),
+        _ => {
+            return HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from("User is not a client!"))
+        }
+    };
+
+    let mut rows: String = String::new();
+    for enrolment in data.enrolments(&user_id, user).iter().rev() {
+        let sections = if enrolment.is_active() {
+            &current_sections
+        } else {
+            &enrolment.sections
+        };
+        let certificate_url: Option<String> = data
+            .certificate_for_user(&user_id, &enrolment.award)
+            .map(|(certificate_id, _)| "/certificate/".to_owned() + &certificate_id.to_string());
+        rows += &data
+            .handlebars
+            .render(
+                "enrolment/enrolment_row",
+                &json!({
+                    "award": data.awards.get(&enrolment.award).map(|x| x.name.clone()).unwrap_or_else(|| enrolment.award.clone()),
+                    "started": enrolment.started.map(util::format_time).unwrap_or_else(|| "Unknown".to_owned()),
+                    "ended": enrolment.ended.map(util::format_time).unwrap_or_default(),
+                    "status": enrolment.status.to_string(),
+                    "completed": completed_sections(data, sections),
+                    "total": sections.len(),
+                    "certificate_url": certificate_url,
+                }),
+            )
+            .unwrap();
+    }
+
+    let awards: Vec<_> = data
+        .awards
+        .iter()
+        .filter(|(id, _)| **id != current_award)
+        .map(|(id, x)| {
+            json!({
+                "title": x.name.clone(),
+                "award": id,
+            })
+        })
+        .collect();
+
+    let body: String = data
+        .handlebars
+        .render(
+            "enrolment/enrolment_list",
+            &json!({
+                "back_url": dir::client_path(org_id, user_id),
+                "name": user.name(),
+                "rows": rows,
+                "can_change": data.can_view_user(&ctx, &user_id, user),
+                "change_url": dir::client_path(org_id, user_id) + dir::AWARDS_PATH,
+                "awards": awards,
+            }),
+        )
+        .unwrap();
+
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (
+                dir::org_path(org_id),
+                org.name.clone(),
+                Privilege::OrgLevel,
+            ),
+            (
+                dir::client_path(org_id, user_id),
+                user.name(),
+                Privilege::ClientLevel,
+            ),
+            (
+                dir::client_path(org_id, user_id) + dir::AWARDS_PATH,
+                dir::AWARDS_TITLE.to_owned(),
+                Privilege::ClientLevel,
+            ),
+        ],
+    );
+
+    let header_properties: String = {
+        if ctx.user.user_agent.privilege() == Privilege::ClientLevel {
+            "hidden".to_owned()
+        } else {
+            String::new()
+        }
+    };
+
+    let root: String = data
+        .handlebars
+        .render(
+            "client/client_root",
+            &json!({
+                "header": header,
+                "body": body,
+                "header_properties": header_properties,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + dir::AWARDS_TITLE,
+        dir::EXTENDED_APP_NAME.to_owned(),
+        root,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+#[get("/org/{org}/client/{user}/awards")]
+pub async fn client_awards_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => {
+                        if user.user_agent.org_id() != Some(org_id) {
+                            return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("User is not a client of this organisation!"));
+                        }
+                        if data.can_view_user(&ctx, &user_id, &user) || ctx.user_id == user_id {
+                            match data.org_db.fetch(&org_id) {
+                                Ok(Some(org)) => enrolments_page(&data, ctx, org_id, &org, user_id, &user),
+                                _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from("Failed to fetch org!")),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangeAwardForm {
+    pub award: String,
+    pub carry_sections: Option<String>,
+}
+
+#[post("/org/{org}/client/{user}/awards")]
+pub async fn client_awards_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: web::Form<ChangeAwardForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => {
+                        if user.user_agent.org_id() != Some(org_id) {
+                            return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("User is not a client of this organisation!"));
+                        }
+                        // Pupils can see their previous awards but can't change award themselves.
+                        if data.can_view_user(&ctx, &user_id, &user) {
+                            if data.is_user_archived(&user.user_agent) {
+                                return page::archived_org_page(Some(ctx), &data);
+                            }
+                            match data.change_award(&user_id, &form.award, form.carry_sections.is_some()) {
+                                Ok(()) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::client_path(org_id, user_id))
+                                    .body(""),
+                                Err(e) => page::error_page(Some(ctx), &data, "Could not change award", &e.to_string()),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -0,0 +1,791 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use futures::{StreamExt, TryStreamExt};
+use serde_json::json;
+
+use crate::auth::AuthContext;
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::import;
+use crate::login;
+use crate::org;
+use crate::page;
+use crate::user;
+use crate::util;
+
+use import::ImportKind;
+use user::Privilege;
+
+/// Wraps the content of an import page in the org page.
+fn import_page(
+    data: &SharedData,
+    ctx: AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    kind: ImportKind,
+    title: &str,
+    content: String,
+) -> HttpResponse {
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+        ],
+    );
+
+    let nav = page::org_nav(&ctx, data, org_id, org, dir::org_path(org_id) + kind.list_page());
+
+    let org_page = data
+        .handlebars
+        .render(
+            "org/org_root",
+            &json!({
+                "header": header,
+                "org_nav": nav,
+                "body": content,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + title + " - " + &org.name,
+        dir::EXTENDED_APP_NAME.to_owned(),
+        org_page,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+fn upload_page(
+    data: &SharedData,
+    ctx: AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    kind: ImportKind,
+    err_msg: &str,
+) -> HttpResponse {
+    let (intro, columns) = match kind {
+        ImportKind::Clients => (
+            "Add a whole cohort of pupils at once from a CSV file, such as one exported from a spreadsheet. \
+            The class and award columns can be left blank, in which case the pupil is not put in a class \
+            and is given the default award of the organisation.",
+            import::ClientRow::COLUMNS.join(", "),
+        ),
+        ImportKind::Associates => (
+            "Add all of your staff at once from a CSV file, such as one exported from a spreadsheet. \
+            Each teacher will receive an email with their password.",
+            import::AssociateRow::COLUMNS.join(", "),
+        ),
+        ImportKind::Roster => (
+            "Keep your pupils up to date with a roster exported from your school's management information system. \
+            Pupils are matched by their external id, or by email if they don't have one yet. \
+            New pupils are added, and the names and classes of existing pupils are updated. \
+            Pupils who aren't in the roster can be archived.",
+            import::ClientRow::COLUMNS.join(", "),
+        ),
+    };
+    let content: String = data
+        .handlebars
+        .render(
+            "import/upload",
+            &json!({
+                "back_url": dir::org_path(org_id) + kind.list_page(),
+                "upload_url": dir::org_path(org_id) + kind.path(),
+                "title": kind.title(),
+                "intro": intro,
+                "columns": columns,
+                "err_msg": err_msg,
+            }),
+        )
+        .unwrap();
+    import_page(data, ctx, org_id, org, kind, kind.title(), content)
+}
+
+/// Reads the uploaded file from the form, as text.
+async fn read_upload(payload: &mut Multipart) -> Result<String, &'static str> {
+    let mut file: Option<Vec<u8>> = None;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let is_file: bool = field
+            .content_disposition()
+            .map(|x| x.get_name() == Some("file"))
+            .unwrap_or(false);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = field.next().await {
+            if let Ok(chunk) = chunk {
+                if buffer.len() + chunk.len() > dir::MAX_IMPORT_BYTES {
+                    return Err("The file is too large. Please upload a file smaller than 1MB.");
+                }
+                buffer.extend_from_slice(&chunk);
+            }
+        }
+        if is_file {
+            file = Some(buffer);
+        }
+    }
+    match file {
+        Some(bytes) if !bytes.is_empty() => {
+            String::from_utf8(bytes).map_err(|_| "The file could not be read. Please save it as a CSV (UTF-8) file.")
+        }
+        _ => Err("Please choose a file to upload."),
+    }
+}
+
+async fn upload_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    kind: ImportKind,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_import_users(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => upload_page(&data, ctx, org_id, &org, kind, ""),
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+fn client_preview(data: &SharedData, org_id: org::OrgKey, org: &org::Org, records: &[import::Record], csv: &str) -> String {
+    let classes = data.org_classes(&org_id);
+    let checked = data.check_client_import(&org_id, org, records);
+    let valid: usize = checked.iter().filter(|x| x.problem.is_none()).count();
+    let rows: Vec<_> = checked
+        .iter()
+        .map(|x| {
+            json!({
+                "line": x.line,
+                "name": x.row.name(),
+                "email": x.row.email,
+                "class": x.row.class,
+                "new_class": !x.row.class.is_empty()
+                    && !classes.iter().any(|(_, class)| class.name == x.row.class),
+                "award": data.awards.get(&x.row.award).map(|award| award.name.clone()),
+                "problem": x.problem,
+            })
+        })
+        .collect();
+
+    data.handlebars
+        .render(
+            "import/client_preview",
+            &json!({
+                "back_url": dir::org_path(org_id) + dir::IMPORT_CLIENTS_PATH,
+                "confirm_url": dir::org_path(org_id) + dir::IMPORT_CLIENTS_PATH + "/confirm",
+                "rows": rows,
+                "valid": valid,
+                "invalid": checked.len() - valid,
+                "credits": org.credits,
+                "csv": csv,
+            }),
+        )
+        .unwrap()
+}
+
+fn associate_preview(data: &SharedData, org_id: org::OrgKey, records: &[import::Record], csv: &str) -> String {
+    let checked = data.check_associate_import(records);
+    let valid: usize = checked.iter().filter(|x| x.problem.is_none()).count();
+    let rows: Vec<_> = checked
+        .iter()
+        .map(|x| {
+            json!({
+                "line": x.line,
+                "name": x.row.name(),
+                "email": x.row.email,
+                "problem": x.problem,
+            })
+        })
+        .collect();
+
+    data.handlebars
+        .render(
+            "import/associate_preview",
+            &json!({
+                "back_url": dir::org_path(org_id) + dir::IMPORT_ASSOCIATES_PATH,
+                "confirm_url": dir::org_path(org_id) + dir::IMPORT_ASSOCIATES_PATH + "/confirm",
+                "rows": rows,
+                "valid": valid,
+                "invalid": checked.len() - valid,
+                "csv": csv,
+            }),
+        )
+        .unwrap()
+}
+
+fn roster_preview(data: &SharedData, org_id: org::OrgKey, org: &org::Org, records: &[import::Record], csv: &str) -> String {
+    let diff = data.roster_diff(&org_id, org, records);
+    let valid_adds: usize = diff.adds.iter().filter(|x| x.problem.is_none()).count();
+    let adds: Vec<_> = diff
+        .adds
+        .iter()
+        .map(|x| {
+            json!({
+                "line": x.line,
+                "name": x.row.name(),
+                "email": x.row.email,
+                "class": x.row.class,
+                "problem": x.problem,
+            })
+        })
+        .collect();
+    let updates: Vec<_> = diff
+        .updates
+        .iter()
+        .map(|x| {
+            json!({
+                "line": x.line,
+                "name": x.forename.clone() + " " + &x.surname,
+                "client_url": dir::client_path(org_id, x.user_id),
+                "changes": x.changes,
+                "problem": x.problem,
+            })
+        })
+        .collect();
+    let removals: Vec<_> = diff
+        .removals
+        .iter()
+        .map(|(user_id, name)| {
+            json!({
+                "name": name,
+                "client_url": dir::client_path(org_id, *user_id),
+            })
+        })
+        .collect();
+
+    data.handlebars
+        .render(
+            "import/roster_preview",
+            &json!({
+                "back_url": dir::org_path(org_id) + dir::IMPORT_ROSTER_PATH,
+                "confirm_url": dir::org_path(org_id) + dir::IMPORT_ROSTER_PATH + "/confirm",
+                "adds": adds,
+                "valid_adds": valid_adds,
+                "updates": updates,
+                "unchanged": diff.unchanged,
+                "removals": removals,
+                "credits": org.credits,
+                "csv": csv,
+            }),
+        )
+        .unwrap()
+}
+
+/// Checks an uploaded file and shows what would be created, without changing anything.
+/// The file is passed on to the confirm step in the page, and is checked again then.
+async fn upload_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    mut payload: Multipart,
+    org_path: web::Path<String>,
+    kind: ImportKind,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_import_users(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let csv: String = match read_upload(&mut payload).await {
+                                Ok(csv) => csv,
+                                Err(msg) => return upload_page(&data, ctx, org_id, &org, kind, msg),
+                            };
+                            let records = match import::read_records(csv.as_bytes()) {
+                                Ok(records) if !records.is_empty() => records,
+                                Ok(_) => return upload_page(&data, ctx, org_id, &org, kind, "The file doesn't contain any rows."),
+                                Err(e) => {
+                                    return upload_page(&data, ctx, org_id, &org, kind, &format!("The file could not be read: {}", e))
+                                }
+                            };
+
+                            let content: String = match kind {
+                                ImportKind::Clients => client_preview(&data, org_id, &org, &records, &csv),
+                                ImportKind::Associates => associate_preview(&data, org_id, &records, &csv),
+                                ImportKind::Roster => roster_preview(&data, org_id, &org, &records, &csv),
+                            };
+                            import_page(&data, ctx, org_id, &org, kind, kind.title(), content)
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+/// Creates the account for a row that passed its checks and emails them their password.
+/// Rows with a problem are recorded as skipped.
+fn create_account(
+    data: &SharedData,
+    ctx: &AuthContext,
+    org: &org::Org,
+    line: u64,
+    problem: Option<String>,
+    user: user::User,
+    account_type: &str,
+) -> import::ImportResult {
+    let mut result = import::ImportResult {
+        line,
+        name: user.name(),
+        email: user.email.clone(),
+        outcome: import::ImportOutcome::Skipped,
+        user_id: None,
+        message: String::new(),
+    };
+    if let Some(problem) = problem {
+        result.message = problem;
+        return result;
+    }
+
+    let password: String = util::gen_password(8);
+    match data.register_user(&user, &password, true, Some(ctx.user_id)) {
+        Ok(user_id) => {
+            page::send_welcome_email(data, &user, user_id, account_type, &org.name, &password);
+            result.outcome = import::ImportOutcome::Created;
+            result.user_id = Some(user_id);
+        }
+        Err(login::LoginEntryError::UsernameExists) => {
+            result.outcome = import::ImportOutcome::Failed;
+            result.message = "Email is associated with another account".to_owned();
+        }
+        Err(e) => {
+            result.outcome = import::ImportOutcome::Failed;
+            result.message = e.to_string();
+        }
+    }
+    result
+}
+
+fn create_client(
+    data: &SharedData,
+    ctx: &AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    checked: import::CheckedRow<import::ClientRow>,
+) -> import::ImportResult {
+    let user = user::User {
+        email: checked.row.email,
+        forename: checked.row.forename,
+        surname: checked.row.surname,
+        notifications: true,
+        user_agent: user::UserAgent::Client {
+            org_id,
+            class: checked.row.class,
+            award: checked.row.award,
+            sections: [None; 6],
+        },
+        external_id: Some(checked.row.external_id).filter(|x| !x.is_empty()),
+    };
+    create_account(data, ctx, org, checked.line, checked.problem, user, "pupil")
+}
+
+/// Applies the changes found in a roster, archiving the pupils who aren't in it if `archive_missing` is set.
+fn sync_roster(
+    data: &SharedData,
+    ctx: &AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    records: &[import::Record],
+    archive_missing: bool,
+) -> Vec<import::ImportResult> {
+    let diff = data.roster_diff(&org_id, org, records);
+    let mut results: Vec<import::ImportResult> = diff
+        .adds
+        .into_iter()
+        .map(|checked| create_client(data, ctx, org_id, org, checked))
+        .collect();
+
+    for update in diff.updates.into_iter() {
+        let mut result = import::ImportResult {
+            line: update.line,
+            name: update.forename.clone() + " " + &update.surname,
+            email: update.email.clone(),
+            outcome: import::ImportOutcome::Skipped,
+            user_id: Some(update.user_id),
+            message: update.changes.join("; "),
+        };
+        if let Some(problem) = &update.problem {
+            result.message = problem.clone();
+        } else {
+            match data.apply_roster_update(&org_id, &update) {
+                Ok(()) => result.outcome = import::ImportOutcome::Updated,
+                Err(e) => {
+                    result.outcome = import::ImportOutcome::Failed;
+                    result.message = e.to_string();
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    if archive_missing {
+        for (user_id, name) in diff.removals.into_iter() {
+            let mut result = import::ImportResult {
+                line: 0,
+                name,
+                email: data.user_db.fetch(&user_id).ok().flatten().map(|x| x.email).unwrap_or_default(),
+                outcome: import::ImportOutcome::Archived,
+                user_id: Some(user_id),
+                message: "Not in the roster".to_owned(),
+            };
+            if let Err(e) = data.archive_client(&user_id, ctx.user_id) {
+                result.outcome = import::ImportOutcome::Failed;
+                result.message = e.to_string();
+            }
+            results.push(result);
+        }
+    }
+    results
+}
+
+fn run_import(
+    data: &SharedData,
+    ctx: &AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    records: &[import::Record],
+    kind: ImportKind,
+    archive_missing: bool,
+) -> Vec<import::ImportResult> {
+    match kind {
+        ImportKind::Clients => data
+            .check_client_import(&org_id, org, records)
+            .into_iter()
+            .map(|checked| create_client(data, ctx, org_id, org, checked))
+            .collect(),
+        ImportKind::Associates => data
+            .check_associate_import(records)
+            .into_iter()
+            .map(|checked| {
+                let user = user::User {
+                    email: checked.row.email,
+                    forename: checked.row.forename,
+                    surname: checked.row.surname,
+                    notifications: true,
+                    user_agent: user::UserAgent::Associate(org_id),
+                    external_id: None,
+                };
+                create_account(data, ctx, org, checked.line, checked.problem, user, "teacher")
+            })
+            .collect(),
+        ImportKind::Roster => sync_roster(data, ctx, org_id, org, records, archive_missing),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmImportForm {
+    csv: String,
+    /// Whether pupils missing from a roster are archived.
+    archive_missing: Option<String>,
+}
+
+async fn confirm_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<ConfirmImportForm>,
+    kind: ImportKind,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_import_users(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let records = match import::read_records(form.csv.as_bytes()) {
+                                Ok(records) => records,
+                                Err(e) => {
+                                    return upload_page(&data, ctx, org_id, &org, kind, &format!("The file could not be read: {}", e))
+                                }
+                            };
+
+                            let import = import::Import {
+                                org_id,
+                                kind,
+                                time: std::time::SystemTime::now(),
+                                actor: ctx.user_id,
+                                results: run_import(&data, &ctx, org_id, &org, &records, kind, form.archive_missing.is_some()),
+                            };
+                            let description: String = match kind {
+                                ImportKind::Clients => "Imported pupils from CSV: ".to_owned(),
+                                ImportKind::Associates => "Imported teachers from CSV: ".to_owned(),
+                                ImportKind::Roster => "Synced pupils with a roster: ".to_owned(),
+                            } + &import.summary();
+                            let event = org::OrgEvent::new(Some(ctx.user_id), description);
+                            if let Err(e) = data.add_org_event(&org_id, event) {
+                                log::error!("Failed to record org event: {}", e);
+                            }
+
+                            let import_id = import::ImportKey::generate();
+                            match data.import_db.insert(&import_id, &import) {
+                                Ok(()) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::import_path(org_id, import_id))
+                                    .body(""),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e))),
+                            }
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[get("/org/{org}/import/pupils")]
+pub async fn import_clients_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_get(data, req, org_path, ImportKind::Clients).await
+}
+
+#[post("/org/{org}/import/pupils")]
+pub async fn import_clients_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    payload: Multipart,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_post(data, req, payload, org_path, ImportKind::Clients).await
+}
+
+#[post("/org/{org}/import/pupils/confirm")]
+pub async fn confirm_import_clients_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<ConfirmImportForm>,
+) -> HttpResponse {
+    confirm_post(data, req, org_path, form, ImportKind::Clients).await
+}
+
+#[get("/org/{org}/import/roster")]
+pub async fn import_roster_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_get(data, req, org_path, ImportKind::Roster).await
+}
+
+#[post("/org/{org}/import/roster")]
+pub async fn import_roster_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    payload: Multipart,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_post(data, req, payload, org_path, ImportKind::Roster).await
+}
+
+#[post("/org/{org}/import/roster/confirm")]
+pub async fn confirm_import_roster_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<ConfirmImportForm>,
+) -> HttpResponse {
+    confirm_post(data, req, org_path, form, ImportKind::Roster).await
+}
+
+#[get("/org/{org}/import/teachers")]
+pub async fn import_associates_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_get(data, req, org_path, ImportKind::Associates).await
+}
+
+#[post("/org/{org}/import/teachers")]
+pub async fn import_associates_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    payload: Multipart,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    upload_post(data, req, payload, org_path, ImportKind::Associates).await
+}
+
+#[post("/org/{org}/import/teachers/confirm")]
+pub async fn confirm_import_associates_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<ConfirmImportForm>,
+) -> HttpResponse {
+    confirm_post(data, req, org_path, form, ImportKind::Associates).await
+}
+
+/// Fetches an import of the org, if the user can see its results.
+fn fetch_import(
+    data: &SharedData,
+    ctx: &AuthContext,
+    org_id: &org::OrgKey,
+    import_id: &import::ImportKey,
+) -> Option<import::Import> {
+    if !ctx.user.user_agent.can_import_users(org_id) {
+        return None;
+    }
+    match data.import_db.fetch(import_id) {
+        Ok(Some(import)) if import.org_id == *org_id => Some(import),
+        _ => None,
+    }
+}
+
+#[get("/org/{org}/imports/{import}")]
+pub async fn import_results_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let (Ok(org_id), Ok(import_id)) = (
+        org::OrgKey::from_str(&(path.0).0),
+        import::ImportKey::from_str(&(path.0).1),
+    ) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match fetch_import(&data, &ctx, &org_id, &import_id) {
+                Some(import) => match data.org_db.fetch(&org_id) {
+                    Ok(Some(org)) => {
+                        let rows: Vec<_> = import
+                            .results
+                            .iter()
+                            .map(|x| {
+                                json!({
+                                    "line": x.line,
+                                    "name": x.name,
+                                    "email": x.email,
+                                    "outcome": x.outcome.to_string(),
+                                    "message": x.message,
+                                    "user_url": x.user_id.map(|user_id| match import.kind {
+                                        ImportKind::Clients | ImportKind::Roster => dir::client_path(org_id, user_id),
+                                        ImportKind::Associates => dir::associate_path(org_id, user_id),
+                                    }),
+                                })
+                            })
+                            .collect();
+
+                        let title: String = match import.kind {
+                            ImportKind::Roster => "Roster Sync".to_owned(),
+                            _ => import.kind.to_string() + " Import",
+                        };
+                        let content: String = data
+                            .handlebars
+                            .render(
+                                "import/results",
+                                &json!({
+                                    "back_url": dir::org_path(org_id) + import.kind.list_page(),
+                                    "csv_url": dir::import_path(org_id, import_id) + "/csv",
+                                    "title": title,
+                                    "time": util::format_time_tz(import.time, &data.org_settings(&org_id).tz()),
+                                    "summary": import.summary(),
+                                    "rows": rows,
+                                }),
+                            )
+                            .unwrap();
+                        import_page(&data, ctx, org_id, &org, import.kind, &title, content)
+                    }
+                    _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from("Failed to fetch org!")),
+                },
+                None => page::not_authorized_page(Some(ctx), &data),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id or import_id"))
+    }
+}
+
+#[get("/org/{org}/imports/{import}/csv")]
+pub async fn import_results_csv_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let (Ok(org_id), Ok(import_id)) = (
+        org::OrgKey::from_str(&(path.0).0),
+        import::ImportKey::from_str(&(path.0).1),
+    ) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match fetch_import(&data, &ctx, &org_id, &import_id) {
+                Some(import) => match import::write_results(&import.results) {
+                    Ok(csv) => HttpResponse::Ok()
+                        .content_type("text/csv; charset=utf-8")
+                        .set(ContentDisposition {
+                            disposition: DispositionType::Attachment,
+                            parameters: vec![DispositionParam::Filename(
+                                import.kind.to_string() + " Import Results.csv",
+                            )],
+                        })
+                        .body(csv),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Failed to create results file: {}", e))),
+                },
+                None => page::not_authorized_page(Some(ctx), &data),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id or import_id"))
+    }
+}
@@ -99,6 +99,11 @@ pub async fn login_post(
                         &data,
                         "Incorrect username and password combination".to_owned(),
                     ),
+                    Err(login::AuthError::OrgArchived) => login_template(
+                        old_ctx,
+                        &data,
+                        "Your organisation's account has been closed. Please contact your organisation if you think this is a mistake.".to_owned(),
+                    ),
                     Err(login::AuthError::DbError(e)) => {
                         HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
                             .set_body(Body::from(format!("Error: {}", e)))
@@ -386,9 +391,10 @@ pub async fn create_account_post(
                                     email: form.email.clone(),
                                     notifications: true,
                                     user_agent,
+                                    external_id: None,
                                 };
 
-                                match data.register_user(&user, &form.password, false) {
+                                match data.register_user(&user, &form.password, false, None) {
                                     Ok(_) => {
                                         // Got user - remove link.
                                         let _ = data.link_manager.db().remove_silent(&token);
@@ -481,7 +487,7 @@ pub fn reset_password_post(
 ) -> HttpResponse {
     match data.authenticate_context_from_request(&req, false) {
         Ok(ctx) => {
-            if let Ok(Some(login_entry)) = data.login_db.db().fetch(&form.username) {
+            if let Ok(Some(login_entry)) = data.login_db.db().fetch(&data.login_key(&form.username)) {
                 if let Ok(link_token) = data.link_manager.create_link(
                     link::Link::ChangePassword(login_entry.user_id),
                     std::time::Duration::from_secs(dir::CHANE_PASSWORD_LINK_TIMEOUT_SECS),
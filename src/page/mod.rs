@@ -9,13 +9,25 @@ pub mod user;
 pub mod details;
 pub mod admin;
 pub mod stats;
+pub mod trash;
+pub mod certificate;
+pub mod credits;
+pub mod enrolment;
+pub mod settings;
+pub mod org_admins;
+pub mod classes;
+pub mod transfer;
+pub mod rollover;
+pub mod import;
 
 use std::sync::Arc;
 
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
 use actix_web::{body::Body, cookie::Cookie, get, http, web, HttpRequest, HttpResponse};
 use serde_json::json;
 
-use crate::{auth::AuthContext, data::SharedData, dir, org, user::Privilege};
+use crate::{auth::AuthContext, data::SharedData, dir, link, org, user::Privilege};
 
 use std::collections::HashMap;
 
@@ -93,6 +105,19 @@ pub fn org_nav(
 
     let mut org_nav: String = String::new();
 
+    if data.org_settings(&org_id).has_logo {
+        org_nav += &data
+            .handlebars
+            .render(
+                "org/org_logo",
+                &json!({
+                    "logo_url": dir::org_path(org_id) + dir::ORG_LOGO_PATH,
+                    "name": &org.name,
+                }),
+            )
+            .unwrap();
+    }
+
     for (url, title) in org_items {
         let nav_class: &str = {
             if url == path {
@@ -155,6 +180,16 @@ pub fn not_authorized_page(ctx: Option<AuthContext>, data: &SharedData) -> HttpR
     error_page(ctx, data, "Not Authorised", "You are not authorised to access this page")
 }
 
+/// Shown when changing the data of an archived org, which is kept read-only until the org is restored.
+pub fn archived_org_page(ctx: Option<AuthContext>, data: &SharedData) -> HttpResponse {
+    error_page(
+        ctx,
+        data,
+        "Organisation Archived",
+        "This organisation has been archived, so its data can't be changed. Restore the organisation to make changes.",
+    )
+}
+
 pub fn error_page(ctx: Option<AuthContext>, data: &SharedData, title: &str, text: &str) -> HttpResponse {
     let page_body: String = data
         .handlebars
@@ -176,6 +211,51 @@ pub fn error_page(ctx: Option<AuthContext>, data: &SharedData, title: &str, text
     HttpResponse::new(http::StatusCode::UNAUTHORIZED).set_body(Body::from(body))
 }
 
+/// Emails a new user their username and password, with a link to change the password.
+pub fn send_welcome_email(
+    data: &SharedData,
+    user: &crate::user::User,
+    user_id: crate::user::UserKey,
+    account_type: &str,
+    org_name: &str,
+    password: &str,
+) {
+    if let Ok(link_token) = data.link_manager.create_link(
+        link::Link::ChangePassword(user_id),
+        std::time::Duration::from_secs(dir::CHANE_PASSWORD_LINK_TIMEOUT_SECS),
+    ) {
+        let link: String = dir::make_absolute_url(&("/user/change_password/".to_string() + &link_token.to_string()));
+
+        let subtitle: String = data
+            .handlebars
+            .render(
+                "email/account_created",
+                &json!({
+                    "name": user.name(),
+                    "account_type": account_type,
+                    "org_name": org_name,
+                    "username": &user.email,
+                    "password": password,
+                    "link": link,
+                }),
+            )
+            .unwrap();
+
+        if data
+            .send_email(
+                &user.email,
+                "Senior Duke - Welcome & Password Info",
+                "Senior Duke - Welcome & Password Info",
+                &subtitle,
+                "",
+            )
+            .is_none()
+        {
+            log::error!("Failed to send email!");
+        }
+    }
+}
+
 pub fn redirect_to_login(req: &HttpRequest) -> HttpResponse {
     let mut r = HttpResponse::SeeOther();
     r.cookie(Cookie::new(
@@ -186,6 +266,55 @@ pub fn redirect_to_login(req: &HttpRequest) -> HttpResponse {
     r.body("")
 }
 
+/// Finalises a response for a file opened with `NamedFile`, which takes care of the content type,
+/// range requests and cache validation.
+pub fn file_response(
+    req: &HttpRequest,
+    file: NamedFile,
+    disposition: DispositionType,
+    filename: String,
+) -> HttpResponse {
+    match file
+        .set_content_disposition(ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .into_response(req)
+    {
+        Ok(r) => r,
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+/// Serves a generated file (such as an archive) as an attachment, then removes it from the temp directory.
+pub async fn temp_file_response(req: &HttpRequest, path: String, filename: String) -> HttpResponse {
+    let open_path: String = path.clone();
+    match web::block(move || NamedFile::open(open_path)).await {
+        Ok(file) => {
+            // The open handle keeps the contents available while they are streamed.
+            // Where the platform doesn't allow this the temp directory is cleared on startup instead.
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::warn!("Failed to remove temp file {}: {}", &path, e);
+            }
+            file_response(req, file, DispositionType::Attachment, filename)
+        }
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+/// The response for when a generated file couldn't be written. Whatever was written of it is removed from the temp directory.
+pub fn temp_file_error(path: &str, e: impl std::fmt::Display) -> HttpResponse {
+    if std::path::Path::new(path).exists() {
+        if let Err(e) = std::fs::remove_file(path) {
+            log::warn!("Failed to remove temp file {}: {}", path, e);
+        }
+    }
+    HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+        .set_body(Body::from(format!("Failed to create archive: {}", e)))
+}
+
 #[get("/")]
 pub async fn root_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> HttpResponse {
     match data.authenticate_context_from_request(&req, true) {
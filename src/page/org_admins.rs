@@ -0,0 +1,254 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::link;
+use crate::org;
+use crate::page;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+#[get("/org/{org}/admins")]
+pub async fn org_admins_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_manage_org_admins(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let admins = data.org_admins(&org_id, &org);
+                            let mut rows: String = String::new();
+                            for user_id in admins.iter() {
+                                if let Ok(Some(user)) = data.user_db.fetch(user_id) {
+                                    rows += &data
+                                        .handlebars
+                                        .render(
+                                            "org/org_admin_row",
+                                            &json!({
+                                                "user_url": dir::user_path(*user_id),
+                                                "name": user.name(),
+                                                "email": user.email,
+                                                "user_id": user_id,
+                                                "primary": org.admin == Some(*user_id),
+                                                // The last admin can't be removed, so the org is never left without one.
+                                                "can_delete": admins.len() > 1
+                                                    && ctx.user.user_agent.can_delete_user(&user.user_agent),
+                                            }),
+                                        )
+                                        .unwrap();
+                                }
+                            }
+
+                            let content: String = data
+                                .handlebars
+                                .render(
+                                    "org/org_admin_list",
+                                    &json!({
+                                        "rows": rows,
+                                        "empty": admins.is_empty(),
+                                        "invite_url": dir::org_path(org_id) + dir::ORG_ADMINS_PAGE + "/invite",
+                                        "primary_url": dir::org_path(org_id) + dir::ORG_ADMINS_PAGE + "/primary",
+                                        "delete_user_url": dir::DELETE_USER_PATH,
+                                    }),
+                                )
+                                .unwrap();
+
+                            let header: String = page::path_header(
+                                &data,
+                                &ctx.user.user_agent.privilege(),
+                                &[
+                                    (
+                                        dir::ORGS_PAGE.to_owned(),
+                                        dir::ORGS_TITLE.to_owned(),
+                                        Privilege::RootLevel,
+                                    ),
+                                    (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+                                ],
+                            );
+
+                            let nav = page::org_nav(
+                                &ctx,
+                                &data,
+                                org_id,
+                                &org,
+                                dir::org_path(org_id) + dir::ORG_ADMINS_PAGE,
+                            );
+
+                            let org_page = data
+                                .handlebars
+                                .render(
+                                    "org/org_root",
+                                    &json!({
+                                        "header": header,
+                                        "org_nav": nav,
+                                        "body": content,
+                                    }),
+                                )
+                                .unwrap();
+
+                            let body = page::render_page(
+                                Some(ctx),
+                                &data,
+                                dir::APP_NAME.to_owned() + " | " + dir::ORG_ADMINS_TITLE + " - " + &org.name,
+                                dir::EXTENDED_APP_NAME.to_owned(),
+                                org_page,
+                            )
+                            .unwrap();
+
+                            HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct InviteOrgAdminForm {
+    email: String,
+}
+
+#[post("/org/{org}/admins/invite")]
+pub async fn invite_org_admin_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<InviteOrgAdminForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_manage_org_admins(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    let addr: String = form.email.trim().to_owned();
+                    if !util::is_email_valid(&addr) {
+                        return page::error_page(
+                            Some(ctx),
+                            &data,
+                            "Could not invite administrator",
+                            "Please enter a valid email address.",
+                        );
+                    }
+                    match data.link_manager.create_link(
+                        link::Link::CreateUser(user::UserAgent::Organisation(org_id)),
+                        Duration::from_secs(dir::ASSIGN_ADMIN_LINK_TIMEOUT_SECS),
+                    ) {
+                        Ok(link_token) => {
+                            let link: String = dir::make_absolute_url(
+                                &("/user/create_account/".to_string() + &link_token.to_string()),
+                            );
+
+                            let subtitle = "You have been invited to create a Senior Duke organisation administrator account. <br><a href=\"".to_owned()
+                                + &link
+                                + "\">"
+                                + "Click here</a> to create your organisation administrator account.";
+
+                            if data
+                                .send_email(
+                                    &addr,
+                                    "Senior Duke - Create Your Account",
+                                    "Create Organisation Administrator Account",
+                                    &subtitle,
+                                    "",
+                                )
+                                .is_none()
+                            {
+                                log::error!("Failed to send email!");
+                            }
+
+                            HttpResponse::SeeOther()
+                                .header(http::header::LOCATION, dir::org_path(org_id) + dir::ORG_ADMINS_PAGE)
+                                .body("")
+                        }
+                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from(format!("Error: {}", e))),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PrimaryOrgAdminForm {
+    user_id: user::UserKey,
+}
+
+#[post("/org/{org}/admins/primary")]
+pub async fn primary_org_admin_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<PrimaryOrgAdminForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_manage_org_admins(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(mut org)) => {
+                            if !data.org_admins(&org_id, &org).contains(&form.user_id) {
+                                return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                    .set_body(Body::from("User is not an administrator of this organisation"));
+                            }
+                            org.admin = Some(form.user_id);
+                            match data.org_db.insert(&org_id, &org) {
+                                Ok(()) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::org_path(org_id) + dir::ORG_ADMINS_PAGE)
+                                    .body(""),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e))),
+                            }
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
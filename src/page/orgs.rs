@@ -7,6 +7,7 @@ use serde_json::json;
 
 use crate::data::SharedData;
 
+use crate::credit;
 use crate::dir;
 use crate::link;
 use crate::org;
@@ -68,11 +69,21 @@ pub async fn orgs_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> Htt
                                 "org_id": org_id,
                                 "admin_email": admin,
                                 "admin_url": admin_url,
+                                "other_admins": data.org_admins(org_id, &org).len().saturating_sub(1),
+                                "admins_url": dir::org_path(*org_id) + dir::ORG_ADMINS_PAGE,
                                 "name": org.name,
                                 "unreviewed_sections": org.unreviewed_sections.len(),
                                 "teachers": org.associates.len(),
                                 "pupils": org.clients.len(),
                                 "credits": org.credits,
+                                "statement_url": dir::org_path(*org_id) + dir::CREDITS_PATH,
+                                "restore_org_url": dir::RESTORE_ORG_PATH,
+                                "purge_date": data
+                                    .org_archive_db
+                                    .fetch(org_id)
+                                    .ok()
+                                    .flatten()
+                                    .map(|archived| util::format_time(archived.purge_after)),
                             }),
                         )
                         .unwrap();
@@ -86,7 +97,9 @@ pub async fn orgs_get(data: web::Data<Arc<SharedData>>, req: HttpRequest) -> Htt
                             "org_rows": rows,
                             "add_org_url": dir::ADD_ORG_PATH,
                             "assign_admin_url": dir::ASSIGN_ADMIN_PATH,
-                            "delete_org_url": dir::DELETE_ORG_PATH,
+                            "archive_org_url": dir::ARCHIVE_ORG_PATH,
+                            "retention_days": dir::ORG_ARCHIVE_RETENTION_DAYS,
+                            "max_retention_days": dir::MAX_ORG_ARCHIVE_RETENTION_DAYS,
                             "add_credits_url": dir::ADD_CREDITS_PATH,
                         }),
                     )
@@ -155,20 +168,65 @@ pub async fn add_org_post(
 }
 
 #[derive(serde::Deserialize)]
-pub struct DeleteOrgForm {
+pub struct ArchiveOrgForm {
     org_id: org::OrgKey,
+    /// How long to keep the data of the org before it is deleted permanently.
+    retention_days: u64,
 }
 
-#[post("/del_org")]
-pub async fn delete_org_post(
+#[post("/archive_org")]
+pub async fn archive_org_post(
     data: web::Data<Arc<SharedData>>,
     req: HttpRequest,
-    form: web::Form<DeleteOrgForm>,
+    form: web::Form<ArchiveOrgForm>,
 ) -> HttpResponse {
     match data.authenticate_context_from_request(&req, true) {
         Ok(Some(ctx)) => {
             if ctx.user.user_agent.can_delete_orgs() {
-                match data.delete_org(&form.org_id) {
+                if form.retention_days == 0 || form.retention_days > dir::MAX_ORG_ARCHIVE_RETENTION_DAYS {
+                    return page::error_page(
+                        Some(ctx),
+                        &data,
+                        "Could not archive organisation",
+                        "Please choose how many days to keep the organisation's data for.",
+                    );
+                }
+                let retention = Duration::from_secs(form.retention_days * 24 * 60 * 60);
+                match data.archive_org(&form.org_id, ctx.user_id, retention) {
+                    Ok(_) => {
+                        let mut r = HttpResponse::SeeOther();
+                        r.header(http::header::LOCATION, dir::ORGS_PAGE);
+                        r.body("")
+                    }
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                }
+            } else {
+                page::not_authorized_page(Some(ctx), &data)
+            }
+        }
+        Ok(None) => page::redirect_to_login(&req),
+
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RestoreOrgForm {
+    org_id: org::OrgKey,
+}
+
+#[post("/restore_org")]
+pub async fn restore_org_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    form: web::Form<RestoreOrgForm>,
+) -> HttpResponse {
+    match data.authenticate_context_from_request(&req, true) {
+        Ok(Some(ctx)) => {
+            if ctx.user.user_agent.can_delete_orgs() {
+                match data.restore_org(&form.org_id) {
                     Ok(_) => {
                         let mut r = HttpResponse::SeeOther();
                         r.header(http::header::LOCATION, dir::ORGS_PAGE);
@@ -203,6 +261,9 @@ pub async fn assign_admin_post(
     match data.authenticate_context_from_request(&req, true) {
         Ok(Some(ctx)) => {
             if ctx.user.user_agent.can_view_orgs() {
+                if data.is_org_archived(&form.org_id) {
+                    return page::archived_org_page(Some(ctx), &data);
+                }
                 match data.link_manager.create_link(
                     link::Link::CreateUser(user::UserAgent::Organisation(form.org_id)),
                     Duration::from_secs(dir::ASSIGN_ADMIN_LINK_TIMEOUT_SECS),
@@ -249,8 +310,10 @@ pub async fn assign_admin_post(
 
 #[derive(serde::Deserialize)]
 pub struct AddCreditsForm {
-    credits_count: u32,
+    credits_count: i64,
     org_id: org::OrgKey,
+    kind: credit::CreditTransactionKind,
+    reason: String,
 }
 
 #[post("/add_credits")]
@@ -262,9 +325,30 @@ pub async fn add_credits_post(
     match data.authenticate_context_from_request(&req, true) {
         Ok(Some(ctx)) => {
             if ctx.user.user_agent.can_view_orgs() {
+                if data.is_org_archived(&form.org_id) {
+                    return page::archived_org_page(Some(ctx), &data);
+                }
                 match data.org_db.fetch(&form.org_id) {
                     Ok(Some(mut org)) => {
-                        org.credits += form.credits_count;
+                        // Expired credits are entered as the number of credits that have expired.
+                        let amount: i64 = match form.kind {
+                            credit::CreditTransactionKind::Purchase => form.credits_count.abs(),
+                            credit::CreditTransactionKind::Adjustment => form.credits_count,
+                            credit::CreditTransactionKind::Expiry => -form.credits_count.abs(),
+                            _ => {
+                                return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                    .set_body(Body::from("Invalid transaction type!"))
+                            }
+                        };
+                        let transaction = credit::CreditTransaction::new(
+                            form.kind,
+                            amount,
+                            Some(ctx.user_id),
+                            form.reason.trim().to_owned(),
+                        );
+                        if let Err(e) = data.add_credit_transaction(&form.org_id, &mut org, transaction) {
+                            return page::error_page(Some(ctx), &data, "Could not change credits", &e.to_string());
+                        }
 
                         match data.org_db.insert(&form.org_id, &org) {
                             Ok(_) => {
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::auth::AuthContext;
+use crate::data::SharedData;
+
+use crate::class;
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::rollover;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+/// The classes of an org, followed by the pupils who aren't in a class, with the key used for each in the form.
+fn groups(data: &SharedData, org_id: &org::OrgKey, org: &org::Org) -> Vec<(Option<class::ClassKey>, String, Vec<user::UserKey>)> {
+    let classes = data.org_classes(org_id);
+    let unassigned: Vec<user::UserKey> = org
+        .clients
+        .iter()
+        .filter(|x| !classes.iter().any(|(_, class)| class.clients.contains(x)))
+        .copied()
+        .collect();
+    classes
+        .into_iter()
+        .map(|(class_id, class)| (Some(class_id), class.title(), class.clients))
+        .chain(std::iter::once((None, "No Class".to_owned(), unassigned)))
+        .filter(|(_, _, members)| !members.is_empty())
+        .collect()
+}
+
+fn group_key(class_id: &Option<class::ClassKey>) -> String {
+    class_id.map(|x| x.to_string()).unwrap_or_else(|| "none".to_owned())
+}
+
+fn user_name(data: &SharedData, user_id: &Option<user::UserKey>) -> String {
+    match user_id.map(|x| data.user_db.fetch(&x)) {
+        Some(Ok(Some(user))) => user.name(),
+        Some(_) => "Deleted user".to_owned(),
+        None => "System".to_owned(),
+    }
+}
+
+fn rollover_page(data: &SharedData, ctx: AuthContext, org_id: org::OrgKey, org: &org::Org) -> HttpResponse {
+    let tz = data.org_settings(&org_id).tz();
+    let classes = data.org_classes(&org_id);
+
+    let groups: Vec<_> = groups(data, &org_id, org)
+        .iter()
+        .map(|(class_id, title, members)| {
+            let mut completed_count: usize = 0;
+            let pupils: Vec<_> = members
+                .iter()
+                .filter_map(|user_id| {
+                    let user = data.user_db.fetch(user_id).ok().flatten()?;
+                    if let user::UserAgent::Client { sections, .. } = &user.user_agent {
+                        let completed_sections: usize = sections
+                            .iter()
+                            .flatten()
+                            .filter(|section_id| {
+                                matches!(data.section_db.fetch(section_id), Ok(Some(section)) if section.state.is_completed())
+                            })
+                            .count();
+                        let completed: bool = data.is_client_completed(sections);
+                        if completed {
+                            completed_count += 1;
+                        }
+                        Some(json!({
+                            "client_url": dir::client_path(org_id, *user_id),
+                            "name": user.name(),
+                            "completed": completed,
+                            "completed_sections": completed_sections,
+                            "total_sections": sections.len(),
+                        }))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let move_options: Vec<_> = classes
+                .iter()
+                .filter(|(id, _)| Some(*id) != *class_id)
+                .map(|(id, x)| {
+                    json!({
+                        "value": id,
+                        "title": x.title(),
+                    })
+                })
+                .collect();
+
+            json!({
+                "key": group_key(class_id),
+                "title": title,
+                "in_class": class_id.is_some(),
+                "pupils": pupils,
+                "completed_count": completed_count,
+                "incomplete_count": members.len() - completed_count,
+                "move_options": move_options,
+            })
+        })
+        .collect();
+
+    let archived: Vec<_> = data
+        .archived_clients(&org_id)
+        .iter()
+        .filter_map(|(user_id, archived)| {
+            data.user_db.fetch(user_id).ok().flatten().map(|user| {
+                json!({
+                    "user_id": user_id,
+                    "name": user.name(),
+                    "archived": util::format_time_tz(archived.archived, &tz),
+                })
+            })
+        })
+        .collect();
+
+    let history: Vec<_> = data
+        .org_history(&org_id)
+        .iter()
+        .rev()
+        .map(|event| {
+            json!({
+                "time": util::format_time_tz(event.time, &tz),
+                "actor": user_name(data, &event.actor),
+                "description": event.description,
+            })
+        })
+        .collect();
+
+    let content: String = data
+        .handlebars
+        .render(
+            "rollover/rollover_page",
+            &json!({
+                "rollover_url": dir::org_path(org_id) + dir::ROLLOVER_PAGE,
+                "restore_url": dir::org_path(org_id) + dir::ROLLOVER_PAGE + "/restore",
+                "classes_url": dir::org_path(org_id) + dir::CLASSES_PAGE,
+                "groups": groups,
+                "empty": org.clients.is_empty(),
+                "credits": org.credits,
+                "archived": archived,
+                "history": history,
+            }),
+        )
+        .unwrap();
+
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+        ],
+    );
+
+    let nav = page::org_nav(
+        &ctx,
+        data,
+        org_id,
+        org,
+        dir::org_path(org_id) + dir::ROLLOVER_PAGE,
+    );
+
+    let org_page = data
+        .handlebars
+        .render(
+            "org/org_root",
+            &json!({
+                "header": header,
+                "org_nav": nav,
+                "body": content,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + dir::ROLLOVER_TITLE + " - " + &org.name,
+        dir::EXTENDED_APP_NAME.to_owned(),
+        org_page,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+#[get("/org/{org}/rollover")]
+pub async fn rollover_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_rollover_org(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => rollover_page(&data, ctx, org_id, &org),
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+/// Reads the plan from the rollover form, which has an action for completed pupils, an action for incomplete
+/// pupils, and a class to move to for each group. Groups missing from the form are left as they are.
+fn read_plan(data: &SharedData, org_id: &org::OrgKey, org: &org::Org, fields: &HashMap<String, String>) -> rollover::RolloverPlan {
+    let action = |name: String| {
+        fields
+            .get(&name)
+            .and_then(|x| rollover::ClientAction::from_str(x).ok())
+            .unwrap_or(rollover::ClientAction::Keep)
+    };
+    let groups = groups(data, org_id, org)
+        .into_iter()
+        .map(|(class_id, _, _)| {
+            let key: String = group_key(&class_id);
+            let move_to = match fields.get(&("move_".to_owned() + &key)).map(|x| x.as_str()) {
+                Some("none") => rollover::ClassTarget::NoClass,
+                Some(value) => match class::ClassKey::from_str(value) {
+                    Ok(target) => rollover::ClassTarget::Class(target),
+                    Err(_) => rollover::ClassTarget::Stay,
+                },
+                None => rollover::ClassTarget::Stay,
+            };
+            rollover::GroupPlan {
+                class_id,
+                completed: action("completed_".to_owned() + &key),
+                incomplete: action("incomplete_".to_owned() + &key),
+                move_to,
+            }
+        })
+        .collect();
+    rollover::RolloverPlan {
+        groups,
+        expire_credits: fields.contains_key("expire_credits"),
+    }
+}
+
+#[post("/org/{org}/rollover")]
+pub async fn rollover_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<Vec<(String, String)>>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_rollover_org(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let fields: HashMap<String, String> = form.into_inner().into_iter().collect();
+                            let plan = read_plan(&data, &org_id, &org, &fields);
+                            match data.rollover_org(&org_id, ctx.user_id, &plan) {
+                                Ok(_) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::org_path(org_id) + dir::ROLLOVER_PAGE)
+                                    .body(""),
+                                Err(e) => page::error_page(Some(ctx), &data, "Could not complete rollover", &e.to_string()),
+                            }
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RestoreClientForm {
+    user_id: user::UserKey,
+}
+
+#[post("/org/{org}/rollover/restore")]
+pub async fn restore_client_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+    form: web::Form<RestoreClientForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_rollover_org(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.archived_clients_db.fetch(&form.user_id) {
+                        Ok(Some(archived)) if archived.org_id == org_id => {
+                            let name: String = match data.user_db.fetch(&form.user_id) {
+                                Ok(Some(user)) => user.name(),
+                                _ => String::new(),
+                            };
+                            match data.restore_client(&form.user_id) {
+                                Ok(()) => {
+                                    let event = org::OrgEvent::new(Some(ctx.user_id), "Restored archived pupil ".to_owned() + &name);
+                                    if let Err(e) = data.add_org_event(&org_id, event) {
+                                        log::error!("Failed to record org event: {}", e);
+                                    }
+                                    HttpResponse::SeeOther()
+                                        .header(http::header::LOCATION, dir::org_path(org_id) + dir::ROLLOVER_PAGE)
+                                        .body("")
+                                }
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e))),
+                            }
+                        }
+                        Ok(_) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                            .set_body(Body::from("No matching archived pupil")),
+                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from(format!("Error: {}", e))),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -5,6 +6,7 @@ use std::sync::Arc;
 use actix_web::{body::Body, http, web, HttpRequest, HttpResponse};
 
 use actix_files::NamedFile;
+use actix_web::http::header::DispositionType;
 use actix_multipart::Multipart;
 use futures::{StreamExt, TryStreamExt};
 
@@ -14,10 +16,12 @@ use crate::data::SharedData;
 
 use crate::auth;
 use crate::dir;
+use crate::export;
 use crate::org;
 use crate::page;
 use crate::section;
 use crate::user;
+use crate::util;
 
 use section::SectionState;
 use user::Privilege;
@@ -36,12 +40,16 @@ pub fn choose_activities_page(
     org: &org::Org,
     user_id: user::UserKey,
     user: &user::User,
-    section_index: usize,
     section: &section::SectionInfo,
+    select_activity_url: String,
+    current_activity: Option<&str>,
 ) -> HttpResponse {
     let mut activities: String = String::new();
 
     for (id, activity) in section.activities.iter() {
+        if current_activity == Some(id.as_str()) {
+            continue;
+        }
         let desc: String = {
             match data.handlebars.render(&activity.activity_url, &()) {
                 Ok(data) => data,
@@ -68,7 +76,8 @@ pub fn choose_activities_page(
         "back_url": dir::client_path(org_id, user_id),
         "section_image_url": section.image_url,
         "activities": activities,
-        "select_activity_url": dir::client_path(org_id, user_id) + dir::SECTION_ROOT + "/" + &section_index.to_string() + dir::SELECT_ACTIVITY_PATH,
+        "select_activity_url": select_activity_url,
+        "changing_activity": current_activity.is_some(),
     })).unwrap();
 
     let header: String = page::path_header(
@@ -146,6 +155,31 @@ pub async fn section_page(
         }
     };
 
+    // Pupils get back any autosaved changes that weren't saved with the form.
+    let draft: Option<section::SectionDraft> = if can_edit {
+        data.section_drafts_db.fetch(&section_id).unwrap_or(None)
+    } else {
+        None
+    };
+    let drafted_section: section::Section;
+    let section_instance: &section::Section = match &draft {
+        Some(draft) if !draft.fields.is_empty() => {
+            let mut s = section_instance.clone();
+            apply_draft(&mut s, activity, &draft.fields);
+            drafted_section = s;
+            &drafted_section
+        }
+        _ => section_instance,
+    };
+    let draft_status: String = match &draft {
+        Some(section::SectionDraft {
+            saved: Some(time),
+            fields,
+            ..
+        }) if !fields.is_empty() => "Unsaved changes restored from ".to_owned() + &util::format_time(*time),
+        _ => String::new(),
+    };
+
     let textarea_properties: String = {
         if can_edit {
             String::new()
@@ -304,28 +338,17 @@ pub async fn section_page(
             let filename = p.file_name().to_owned().into_string().unwrap();
             let download_url = "/section/".to_owned() + &section_id.to_string() + "/asset/" + &filename + "/download";
             let view_url = "/section/".to_owned() + &section_id.to_string() + "/asset/" + &filename + "/view";
-            let media: String = {
-                if let Some(os_ext) = p.path().extension() {
-                    if let Some(ext_raw) = os_ext.to_str() {
-                        let ext: String = ext_raw.to_string().to_lowercase();
-                        if ext == "png" || ext == "jpg" || ext == "jpeg" {
-                            data.handlebars
-                                .render(
-                                    "sections/image_asset",
-                                    &json!({
-                                        "asset_url": &view_url,
-                                    }),
-                                )
-                                .unwrap()
-                        } else {
-                            String::new()
-                        }
-                    } else {
-                        String::new()
-                    }
-                } else {
-                    String::new()
-                }
+            let media: String = match section::AssetKind::from_filename(&filename).template() {
+                Some(template) => data
+                    .handlebars
+                    .render(
+                        template,
+                        &json!({
+                            "asset_url": &view_url,
+                        }),
+                    )
+                    .unwrap(),
+                None => String::new(),
             };
             if can_edit {
                 files += &data
@@ -434,6 +457,13 @@ pub async fn section_page(
     let show_delete: bool =
         ctx.user.user_agent.is_client() && !section_instance.state.is_completed();
 
+    let attempt_count: usize = data
+        .section_attempts_db
+        .fetch(&section_id)
+        .unwrap_or(None)
+        .map(|x| x.len())
+        .unwrap_or(0);
+
     let body: String = data
         .handlebars
         .render(
@@ -449,6 +479,9 @@ pub async fn section_page(
                 "activity_description": desc,
                 "show_delete": show_delete,
                 "delete_section_url": "/section/".to_owned() + &section_id.to_string() + "/delete",
+                "change_activity_url": "/section/".to_owned() + &section_id.to_string() + "/change_activity",
+                "attempts_url": "/section/".to_owned() + &section_id.to_string() + "/attempts",
+                "attempt_count": attempt_count,
                 "info_bubble": info_bubble,
                 "plan": &section_instance.plan,
                 "components": components,
@@ -458,8 +491,16 @@ pub async fn section_page(
                 "textarea_properties": textarea_properties,
                 "reflection": &section_instance.reflection,
                 "upload_section_url": "/section/".to_owned() + &section_id.to_string() + "/upload",
+                "download_section_url": "/section/".to_owned() + &section_id.to_string() + "/download",
+                "has_assets": has_assets,
                 "state_description": state_description,
                 "submit_properties": submit_properties,
+                "autosave": can_edit,
+                "draft_url": "/section/".to_owned() + &section_id.to_string() + "/draft",
+                "draft_revision": draft.as_ref().map(|x| x.revision).unwrap_or(0),
+                "draft_status": draft_status,
+                "trash_url": dir::client_path(org_id, user_id) + dir::TRASH_PATH,
+                "retention_days": dir::TRASH_RETENTION_DAYS,
             }),
         )
         .unwrap();
@@ -527,7 +568,7 @@ pub async fn section_get(
                     Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
                         Ok(Some(user)) => {
                             if user.user_agent.is_client() {
-                                if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                if data.can_view_user(&ctx, &user_id, &user)
                                     || ctx.user_id == user_id
                                 {
                                     match data.org_db.fetch(&org_id) {
@@ -554,8 +595,9 @@ pub async fn section_get(
                                                                                 &org,
                                                                                 user_id,
                                                                                 &user,
-                                                                                section_index,
                                                                                 &section,
+                                                                                dir::client_path(org_id, user_id) + dir::SECTION_ROOT + "/" + &section_index.to_string() + dir::SELECT_ACTIVITY_PATH,
+                                                                                None,
                                                                             )
                                                                         }
                                                                     },
@@ -572,8 +614,9 @@ pub async fn section_get(
                                                             &org,
                                                             user_id,
                                                             &user,
-                                                            section_index,
                                                             &section,
+                                                            dir::client_path(org_id, user_id) + dir::SECTION_ROOT + "/" + &section_index.to_string() + dir::SELECT_ACTIVITY_PATH,
+                                                            None,
                                                         ),
                                                     }
                                                 } else {
@@ -648,9 +691,12 @@ pub async fn select_activity_post(
                                 {
                                     if let Some(award) = data.awards.get(award_id) {
                                         let section = &award.sections[section_index];
-                                        if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                        if data.can_view_user(&ctx, &user_id, &user)
                                             || ctx.user_id == user_id
                                         {
+                                            if data.is_user_archived(&user.user_agent) {
+                                                return page::archived_org_page(Some(ctx), &data);
+                                            }
                                             match data.org_db.fetch(&org_id) {
                                                 Ok(Some(_)) => {
                                                     if section.activities.contains_key(&form.activity) {
@@ -739,10 +785,13 @@ pub async fn delete_section_get(
             Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
                 Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
                     Ok(Some(user)) => {
-                        if ctx.user.user_agent.can_view_user(&user.user_agent)
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
                             || ctx.user_id == section_instance.user_id
                         {
-                            match data.delete_section(&section_id) {
+                            if data.is_user_archived(&user.user_agent) {
+                                return page::archived_org_page(Some(ctx), &data);
+                            }
+                            match data.trash_section(&section_id, ctx.user_id) {
                                 Ok(_) => {
                                     let mut r = HttpResponse::SeeOther();
                                     if let Some(referer) = req.headers().get("Referer") {
@@ -795,9 +844,12 @@ pub async fn upload_section_post(
                     Ok(Some(mut section_instance)) => {
                         match data.user_db.fetch(&section_instance.user_id) {
                             Ok(Some(user)) => {
-                                if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                if data.can_view_user(&ctx, &section_instance.user_id, &user)
                                     || ctx.user_id == section_instance.user_id
                                 {
+                                    if data.is_user_archived(&user.user_agent) {
+                                        return page::archived_org_page(Some(ctx), &data);
+                                    }
                                     while let Ok(Some(mut field)) = payload.try_next().await {
                                         let content_type = field.content_disposition().unwrap();
                                         if let Some(fname) = content_type.get_filename() {
@@ -868,12 +920,9 @@ pub async fn upload_section_post(
                                                 "reflection" => section_instance.reflection = value,
                                                 "delete" => {
                                                     if !value.trim().is_empty() {
-                                                        let delete_filepath = data
-                                                            .path_for_asset(&section_id, &value);
-                                                        let _ = web::block(move || {
-                                                            std::fs::remove_file(&delete_filepath)
-                                                        })
-                                                        .await;
+                                                        if let Err(e) = data.trash_asset(&section_id, &value, ctx.user_id) {
+                                                            log::error!("Failed to move asset to trash: {}", e);
+                                                        }
                                                     }
                                                 }
                                                 _ => {
@@ -931,6 +980,12 @@ pub async fn upload_section_post(
                                         }
                                     }
                                     let _ = data.section_db.insert(&section_id, &section_instance);
+                                    // The form contains every field, so any autosaved values are now stale.
+                                    let mut draft: section::SectionDraft = data.section_drafts_db.fetch(&section_id).unwrap_or(None).unwrap_or_default();
+                                    draft.revision += 1;
+                                    draft.tab_id.clear();
+                                    draft.fields.clear();
+                                    let _ = data.section_drafts_db.insert(&section_id, &draft);
                                     let mut r = HttpResponse::SeeOther();
                                     if let Some(referer) = req.headers().get("Referer") {
                                         r.header(http::header::LOCATION, referer.clone());
@@ -963,6 +1018,110 @@ pub async fn upload_section_post(
     }
 }
 
+/// Applies autosaved field values on top of the saved section.
+/// Keys follow the names of the section form, except checkboxes which are keyed by
+/// `$check:name` with a comma separated list of the checked indices.
+fn apply_draft(section_instance: &mut section::Section, activity: &section::Activity, fields: &BTreeMap<String, String>) {
+    for (name, value) in fields.iter() {
+        let value: String = value.trim().to_owned();
+        match name.as_str() {
+            "plan" => section_instance.plan = value,
+            "reflection" => section_instance.reflection = value,
+            _ => {
+                if let Some(key) = name.strip_prefix(TEXT_PAT) {
+                    if activity.contains_input_component(key) {
+                        section_instance.input_data.insert(key.to_owned(), FormEntryData::Text(value));
+                    }
+                } else if let Some(key) = name.strip_prefix(RADIO_PAT) {
+                    if let Ok(selected_idx) = value.parse::<usize>() {
+                        if activity.contains_input_component(key) {
+                            section_instance.input_data.insert(key.to_owned(), FormEntryData::Index(selected_idx));
+                        }
+                    }
+                } else if let Some(key) = name.strip_prefix(CHECK_PAT) {
+                    if activity.contains_input_component(key) {
+                        let indices: Vec<usize> = value.split(',').filter_map(|x| x.trim().parse::<usize>().ok()).collect();
+                        section_instance.input_data.insert(key.to_owned(), FormEntryData::Indices(indices));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftRequest {
+    pub tab_id: String,
+    pub revision: u64,
+    pub fields: BTreeMap<String, String>,
+}
+
+#[post("/section/{section}/draft")]
+pub async fn draft_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    draft_req: web::Json<DraftRequest>,
+    section: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&section) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => {
+                    if ctx.user_id == section_instance.user_id && !section_instance.state.is_completed() {
+                        let draft_req = draft_req.into_inner();
+                        match data.section_drafts_db.fetch(&section_id) {
+                            Ok(stored) => {
+                                let mut draft: section::SectionDraft = stored.clone().unwrap_or_default();
+                                // Another tab has saved since this one loaded or last saved.
+                                if draft.revision != draft_req.revision && draft.tab_id != draft_req.tab_id {
+                                    HttpResponse::Conflict().json(json!({
+                                        "conflict": true,
+                                    }))
+                                } else {
+                                    let now = std::time::SystemTime::now();
+                                    draft.revision += 1;
+                                    draft.tab_id = draft_req.tab_id;
+                                    draft.saved = Some(now);
+                                    draft.fields = draft_req.fields;
+                                    // The draft is only written if no other tab has saved since it was checked.
+                                    match data.section_drafts_db.compare_and_swap(&section_id, stored.as_ref(), &draft) {
+                                        Ok(true) => HttpResponse::Ok().json(json!({
+                                            "revision": draft.revision,
+                                            "saved": util::format_time(now),
+                                        })),
+                                        Ok(false) => HttpResponse::Conflict().json(json!({
+                                            "conflict": true,
+                                        })),
+                                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                            .set_body(Body::from(format!("Error: {}", e))),
+                                    }
+                                }
+                            }
+                            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from(format!("Error: {}", e))),
+                        }
+                    } else {
+                        HttpResponse::new(http::StatusCode::FORBIDDEN)
+                            .set_body(Body::from("This section cannot be edited!"))
+                    }
+                }
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => HttpResponse::new(http::StatusCode::UNAUTHORIZED)
+                .set_body(Body::from("Not logged in!")),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct SetStateForm {
     pub state: String,
@@ -985,9 +1144,12 @@ pub async fn set_state_post(
                     Ok(Some(mut section_instance)) => {
                         match data.user_db.fetch(&section_instance.user_id) {
                             Ok(Some(user)) => {
-                                if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                if data.can_view_user(&ctx, &section_instance.user_id, &user)
                                     || ctx.user_id == section_instance.user_id
                                 {
+                                    if data.is_user_archived(&user.user_agent) {
+                                        return page::archived_org_page(Some(ctx), &data);
+                                    }
                                     let new_state = match form.state.as_str() {
                                         "InProgress" => SectionState::InProgress,
                                         "Rejected" => {
@@ -1041,6 +1203,12 @@ pub async fn set_state_post(
                                                     }
                                                 }
 
+                                                if new_state.is_completed() {
+                                                    if let Err(e) = data.issue_certificate(&section_instance.user_id) {
+                                                        log::error!("Failed to issue certificate: {}", e);
+                                                    }
+                                                }
+
                                                 if let SectionState::InReview(_) = new_state {
                                                     let org_id = user.user_agent.org_id().unwrap();
                                                     if let Ok(Some(mut org)) =
@@ -1128,9 +1296,12 @@ pub async fn set_outstanding_post(
                     Ok(Some(mut section_instance)) => {
                         match data.user_db.fetch(&section_instance.user_id) {
                             Ok(Some(user)) => {
-                                if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                if data.can_view_user(&ctx, &section_instance.user_id, &user)
                                     || ctx.user_id == section_instance.user_id
                                 {
+                                    if data.is_user_archived(&user.user_agent) {
+                                        return page::archived_org_page(Some(ctx), &data);
+                                    }
                                     section_instance.outstanding = &form.outstanding == "true";
                                     if let user::UserAgent::Client { .. } = &ctx.user.user_agent {
                                         return HttpResponse::new(http::StatusCode::BAD_REQUEST)
@@ -1201,6 +1372,32 @@ pub async fn set_outstanding_post(
     }
 }
 
+/// Serves an uploaded file, inline when viewed if the browser can display it safely.
+async fn asset_response(req: &HttpRequest, filepath: String, filename: String, action: Option<&str>) -> HttpResponse {
+    let disposition: Option<DispositionType> = match action {
+        Some("download") | None => Some(DispositionType::Attachment),
+        Some("view") => {
+            if section::AssetKind::from_filename(&filename).is_inline() {
+                Some(DispositionType::Inline)
+            } else {
+                Some(DispositionType::Attachment)
+            }
+        }
+        _ => None,
+    };
+    if let Some(disposition) = disposition {
+        if let Ok(file) = web::block(|| NamedFile::open(filepath)).await {
+            page::file_response(req, file, disposition, filename)
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                .set_body(Body::from("Asset not found!"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                .set_body(Body::from("Bad action!"))
+    }
+}
+
 #[get("/section/{section}/asset/{asset}/{action}")]
 pub async fn asset_get(
     data: web::Data<Arc<SharedData>>,
@@ -1213,30 +1410,422 @@ pub async fn asset_get(
             Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
                 Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
                     Ok(Some(user)) => {
-                        if ctx.user.user_agent.can_view_user(&user.user_agent)
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
                             || ctx.user_id == section_instance.user_id
                         {
                             let filename = (path.0).1.clone();
                             let filepath = data.path_for_asset(&section_id, &filename);
-                            if path.2 == Some("download".to_owned()) || path.2 == None {
-                                if let Ok(file) = web::block(|| NamedFile::open(filepath)).await {
-                                    file.into_response(&req).unwrap()
-                                } else {
-                                    HttpResponse::new(http::StatusCode::BAD_REQUEST)
-                                        .set_body(Body::from("Asset not found!"))
+                            asset_response(&req, filepath, filename, path.2.as_deref()).await
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
+#[get("/section/{section}/download")]
+pub async fn section_download_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    section: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&section) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
+                            || ctx.user_id == section_instance.user_id
+                        {
+                            match data.temp_path("zip") {
+                                Ok(path) => {
+                                    let filename: String = sanitize_filename::sanitize(
+                                        user.name() + " - " + &export::section_folder_name(&data, &section_instance) + ".zip",
+                                    );
+                                    let zip_data: Arc<SharedData> = data.get_ref().clone();
+                                    let zip_path: String = path.clone();
+                                    match web::block(move || {
+                                        export::write_section_zip(&zip_data, &zip_path, &user, section_id, section_instance)
+                                    })
+                                    .await
+                                    {
+                                        Ok(_) => page::temp_file_response(&req, path, filename).await,
+                                        Err(e) => page::temp_file_error(&path, e),
+                                    }
                                 }
-                            } else if path.2 == Some("view".to_owned()) {
-                                match async_std::fs::read(filepath).await {
-                                    Ok(data) => HttpResponse::new(http::StatusCode::OK)
-                                        .set_body(Body::from(data)),
-                                    Err(e) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
-                                        .set_body(Body::from(format!("Asset fetch failed: {}", e))),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e))),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
+#[get("/section/{section}/change_activity")]
+pub async fn change_activity_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    section: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&section) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
+                            || ctx.user_id == section_instance.user_id
+                        {
+                            if section_instance.state.is_completed() {
+                                page::error_page(Some(ctx), &data, "Section Completed", "The activity of a completed section cannot be changed.")
+                            } else if let Some(org_id) = user.user_agent.org_id() {
+                                let section_info = data
+                                    .awards
+                                    .get(&section_instance.award)
+                                    .and_then(|award| award.sections.get(section_instance.section_index));
+                                match (data.org_db.fetch(&org_id), section_info) {
+                                    (Ok(Some(org)), Some(section_info)) => choose_activities_page(
+                                        &data,
+                                        ctx,
+                                        org_id,
+                                        &org,
+                                        section_instance.user_id,
+                                        &user,
+                                        section_info,
+                                        "/section/".to_owned() + &section_id.to_string() + "/change_activity",
+                                        Some(&section_instance.activity),
+                                    ),
+                                    _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                        .set_body(Body::from("Failed to fetch org!")),
                                 }
                             } else {
+                                HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from("User is not a client!"))
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
+#[post("/section/{section}/change_activity")]
+pub async fn change_activity_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    form: web::Form<SelectSectionOptionForm>,
+    section: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&section) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
+                            || ctx.user_id == section_instance.user_id
+                        {
+                            if data.is_user_archived(&user.user_agent) {
+                                return page::archived_org_page(Some(ctx), &data);
+                            }
+                            let valid_activity: bool = data
+                                .awards
+                                .get(&section_instance.award)
+                                .and_then(|award| award.sections.get(section_instance.section_index))
+                                .map(|x| x.activities.contains_key(&form.activity))
+                                .unwrap_or(false);
+
+                            if section_instance.state.is_completed() {
+                                page::error_page(Some(ctx), &data, "Section Completed", "The activity of a completed section cannot be changed.")
+                            } else if !valid_activity || form.activity == section_instance.activity {
                                 HttpResponse::new(http::StatusCode::BAD_REQUEST)
-                                        .set_body(Body::from("Bad action!"))
+                                    .set_body(Body::from("Invalid activity id!"))
+                            } else {
+                                match data.change_section_activity(&section_id, section_instance, form.activity.clone(), ctx.user_id) {
+                                    Ok(_) => {
+                                        let mut r = HttpResponse::SeeOther();
+                                        r.header(http::header::LOCATION, "/section/".to_owned() + &section_id.to_string());
+                                        r.body("")
+                                    }
+                                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                        .set_body(Body::from(format!("Error: {}", e))),
+                                }
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
+fn attempts_page(
+    data: &SharedData,
+    ctx: auth::AuthContext,
+    org: &org::Org,
+    user: &user::User,
+    section_id: section::SectionKey,
+    section_instance: &section::Section,
+    attempts: &[section::SectionAttempt],
+) -> HttpResponse {
+    let section_info: Option<&section::SectionInfo> = data
+        .awards
+        .get(&section_instance.award)
+        .and_then(|award| award.sections.get(section_instance.section_index));
+    let section_name: String = section_info.map(|x| x.name.clone()).unwrap_or_default();
+    let org_id = user.user_agent.org_id().expect("User should have org_id!");
+
+    let mut attempts_body: String = String::new();
+    // Most recent attempts first.
+    for (n, attempt) in attempts.iter().enumerate().rev() {
+        let activity: Option<&section::Activity> = section_info.and_then(|x| x.activities.get(&attempt.section.activity));
+        let answers: Vec<serde_json::Value> = match activity {
+            Some(activity) => attempt
+                .section
+                .answers(activity)
+                .into_iter()
+                .map(|(title, answer)| json!({ "title": title, "answer": answer }))
+                .collect(),
+            None => Vec::new(),
+        };
+        let files: Vec<serde_json::Value> = export::list_files(&data.attempt_path(&section_id, n))
+            .into_iter()
+            .map(|filename| {
+                let url = "/section/".to_owned() + &section_id.to_string() + "/attempt/" + &n.to_string() + "/" + &filename;
+                json!({
+                    "filename": filename,
+                    "view_url": url.clone() + "/view",
+                    "download_url": url + "/download",
+                })
+            })
+            .collect();
+        let archived_by: String = match data.user_db.fetch(&attempt.archived_by) {
+            Ok(Some(archived_by)) => archived_by.name(),
+            _ => "Unknown".to_owned(),
+        };
+
+        attempts_body += &data
+            .handlebars
+            .render(
+                "sections/attempt",
+                &json!({
+                    "number": n + 1,
+                    "activity_name": activity.map(|x| x.name.clone()).unwrap_or_else(|| attempt.section.activity.clone()),
+                    "state": attempt.section.state.to_string(),
+                    "state_class": attempt.section.state.css_class(),
+                    "archived": util::format_time(attempt.archived),
+                    "archived_by": archived_by,
+                    "plan": &attempt.section.plan,
+                    "answers": answers,
+                    "reflection": &attempt.section.reflection,
+                    "files": files,
+                }),
+            )
+            .unwrap();
+    }
+
+    let body: String = data
+        .handlebars
+        .render(
+            "sections/attempts",
+            &json!({
+                "section_name": &section_name,
+                "back_url": "/section/".to_owned() + &section_id.to_string(),
+                "attempts": attempts_body,
+            }),
+        )
+        .unwrap();
+
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+            (
+                dir::client_path(org_id, section_instance.user_id),
+                user.name(),
+                Privilege::ClientLevel,
+            ),
+        ],
+    );
+
+    let header_properties: String = {
+        if ctx.user.user_agent.privilege() == Privilege::ClientLevel {
+            "hidden".to_owned()
+        } else {
+            String::new()
+        }
+    };
+
+    let root: String = data
+        .handlebars
+        .render(
+            "client/client_root",
+            &json!({
+                "header": header,
+                "body": body,
+                "header_properties": header_properties,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + &section_name + " Previous Attempts - " + &user.name(),
+        dir::EXTENDED_APP_NAME.to_owned(),
+        root,
+    )
+    .unwrap();
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+#[get("/section/{section}/attempts")]
+pub async fn attempts_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    section: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&section) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
+                            || ctx.user_id == section_instance.user_id
+                        {
+                            if let Some(org_id) = user.user_agent.org_id() {
+                                match data.org_db.fetch(&org_id) {
+                                    Ok(Some(org)) => {
+                                        let attempts: Vec<section::SectionAttempt> =
+                                            data.section_attempts_db.fetch(&section_id).unwrap_or(None).unwrap_or_default();
+                                        attempts_page(&data, ctx, &org, &user, section_id, &section_instance, &attempts)
+                                    }
+                                    _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                        .set_body(Body::from("Failed to fetch org!")),
+                                }
+                            } else {
+                                HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from("User is not a client!"))
                             }
-                            
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("No matching section")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid section_id"))
+    }
+}
+
+#[get("/section/{section}/attempt/{attempt}/{asset}/{action}")]
+pub async fn attempt_asset_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, usize, String, Option<String>)>,
+) -> HttpResponse {
+    if let Ok(section_id) = section::SectionKey::from_str(&(path.0).0) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.section_db.fetch(&section_id) {
+                Ok(Some(section_instance)) => match data.user_db.fetch(&section_instance.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &section_instance.user_id, &user)
+                            || ctx.user_id == section_instance.user_id
+                        {
+                            let filename: String = sanitize_filename::sanitize(&(path.0).2);
+                            let filepath = data.path_for_attempt_asset(&section_id, (path.0).1, &filename);
+                            asset_response(&req, filepath, filename, (path.0).3.as_deref()).await
                         } else {
                             page::not_authorized_page(Some(ctx), &data)
                         }
@@ -1278,7 +1867,7 @@ pub async fn section_id_get(
                     match data.user_db.fetch(&user_id) {
                         Ok(Some(user)) => {
                             if user.user_agent.is_client() {
-                                if ctx.user.user_agent.can_view_user(&user.user_agent)
+                                if data.can_view_user(&ctx, &user_id, &user)
                                     || ctx.user_id == user_id
                                 {
                                     let org_id =
@@ -1316,8 +1905,9 @@ pub async fn section_id_get(
                                                             &org,
                                                             user_id,
                                                             &user,
-                                                            section_index,
                                                             &section,
+                                                            dir::client_path(org_id, user_id) + dir::SECTION_ROOT + "/" + &section_index.to_string() + dir::SELECT_ACTIVITY_PATH,
+                                                            None,
                                                         ),
                                                     }
                                                 } else {
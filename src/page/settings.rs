@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::http::header::DispositionType;
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use futures::{StreamExt, TryStreamExt};
+use serde_json::json;
+
+use crate::auth::AuthContext;
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+fn settings_page(
+    data: &SharedData,
+    ctx: AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    settings: &org::OrgSettings,
+    err_msg: &str,
+) -> HttpResponse {
+    let awards: Vec<_> = data
+        .awards
+        .iter()
+        .map(|(id, x)| {
+            json!({
+                "title": x.name.clone(),
+                "award": id,
+                "selected": settings.default_award.as_ref() == Some(id),
+            })
+        })
+        .collect();
+
+    let timezones: Vec<_> = chrono_tz::TZ_VARIANTS
+        .iter()
+        .map(|tz| {
+            json!({
+                "name": tz.name(),
+                "selected": tz.name() == settings.timezone,
+            })
+        })
+        .collect();
+
+    let content: String = data
+        .handlebars
+        .render(
+            "org/org_settings",
+            &json!({
+                "settings_url": dir::org_path(org_id) + dir::ORG_SETTINGS_PATH,
+                "err_msg": err_msg,
+                "name": &org.name,
+                "has_logo": settings.has_logo,
+                "logo_url": dir::org_path(org_id) + dir::ORG_LOGO_PATH,
+                "contact_name": &settings.contact_name,
+                "contact_email": &settings.contact_email,
+                "contact_phone": &settings.contact_phone,
+                "notification_days": org.notification_interval.as_secs() / SECONDS_PER_DAY,
+                "max_notification_days": dir::MAX_NOTIFICATION_INTERVAL_DAYS,
+                "timezones": timezones,
+                "awards": awards,
+                "no_default_award": settings.default_award.is_none(),
+                "restrict_associates": settings.restrict_associates,
+            }),
+        )
+        .unwrap();
+
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+        ],
+    );
+
+    let nav = page::org_nav(
+        &ctx,
+        data,
+        org_id,
+        org,
+        dir::org_path(org_id) + dir::ORG_SETTINGS_PATH,
+    );
+
+    let org_page = data
+        .handlebars
+        .render(
+            "org/org_root",
+            &json!({
+                "header": header,
+                "org_nav": nav,
+                "body": content,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + dir::ORG_SETTINGS_TITLE + " - " + &org.name,
+        dir::EXTENDED_APP_NAME.to_owned(),
+        org_page,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+#[get("/org/{org}/settings")]
+pub async fn settings_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_edit_org(&org_id) {
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(org)) => {
+                            let settings = data.org_settings(&org_id);
+                            settings_page(&data, ctx, org_id, &org, &settings, "")
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+/// Applies the submitted settings to the org, returning a message to show the user if any of them are invalid.
+/// Nothing is changed unless every field is valid.
+fn apply_settings(
+    data: &SharedData,
+    org: &mut org::Org,
+    settings: &mut org::OrgSettings,
+    fields: &HashMap<String, String>,
+) -> Result<(), &'static str> {
+    let field = |name: &str| fields.get(name).map(|x| x.trim().to_owned()).unwrap_or_default();
+
+    let name: String = field("name");
+    settings.contact_name = field("contact_name");
+    settings.contact_email = field("contact_email");
+    settings.contact_phone = field("contact_phone");
+    settings.timezone = field("timezone");
+    settings.restrict_associates = fields.contains_key("restrict_associates");
+    let default_award: String = field("default_award");
+    settings.default_award = if default_award.is_empty() {
+        None
+    } else {
+        Some(default_award)
+    };
+
+    if !util::is_string_server_valid(&name) {
+        return Err("Please enter a valid organisation name.");
+    }
+    if !util::is_optional_string_server_valid(&settings.contact_name) {
+        return Err("The contact name contains invalid characters.");
+    }
+    if !settings.contact_email.is_empty() && !util::is_email_valid(&settings.contact_email) {
+        return Err("Please enter a valid contact email.");
+    }
+    if !settings.contact_phone.is_empty() && !util::is_phone_valid(&settings.contact_phone) {
+        return Err("Please enter a valid contact phone number.");
+    }
+    if !util::is_timezone_valid(&settings.timezone) {
+        return Err("Please choose a valid timezone.");
+    }
+    if let Some(award) = &settings.default_award {
+        if !data.awards.contains_key(award) {
+            return Err("Please choose a valid default award.");
+        }
+    }
+    let notification_days: u64 = match field("notification_days").parse() {
+        Ok(days) if util::is_notification_interval_valid(days) => days,
+        _ => return Err("Please choose how often reviewers should be reminded, in days."),
+    };
+
+    org.name = name;
+    org.notification_interval = Duration::from_secs(SECONDS_PER_DAY * notification_days);
+    Ok(())
+}
+
+#[post("/org/{org}/settings")]
+pub async fn settings_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    mut payload: Multipart,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_edit_org(&org_id) {
+                    if data.is_org_archived(&org_id) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    match data.org_db.fetch(&org_id) {
+                        Ok(Some(mut org)) => {
+                            let mut fields: HashMap<String, String> = HashMap::new();
+                            let mut logo: Option<Vec<u8>> = None;
+                            let mut logo_too_large: bool = false;
+
+                            while let Ok(Some(mut field)) = payload.try_next().await {
+                                let content_type = field.content_disposition().unwrap();
+                                let name: String = content_type.get_name().unwrap_or_default().to_owned();
+                                let is_file: bool = content_type
+                                    .get_filename()
+                                    .map(|x| !x.trim().is_empty())
+                                    .unwrap_or(false);
+                                let mut buffer: Vec<u8> = Vec::new();
+                                while let Some(chunk) = field.next().await {
+                                    if let Ok(chunk) = chunk {
+                                        if buffer.len() + chunk.len() > dir::MAX_ORG_LOGO_BYTES {
+                                            logo_too_large = true;
+                                        } else {
+                                            buffer.extend_from_slice(&chunk);
+                                        }
+                                    }
+                                }
+                                if name == "logo" {
+                                    if is_file {
+                                        logo = Some(buffer);
+                                    }
+                                } else {
+                                    fields.insert(name, String::from_utf8_lossy(&buffer).into_owned());
+                                }
+                            }
+
+                            let mut settings = data.org_settings(&org_id);
+                            if logo_too_large {
+                                return settings_page(
+                                    &data,
+                                    ctx,
+                                    org_id,
+                                    &org,
+                                    &settings,
+                                    "The logo is too large. Please upload an image smaller than 5MB.",
+                                );
+                            }
+                            if let Err(msg) = apply_settings(&data, &mut org, &mut settings, &fields) {
+                                return settings_page(&data, ctx, org_id, &org, &settings, msg);
+                            }
+
+                            if let Some(logo) = logo {
+                                match data.set_org_logo(&org_id, &logo) {
+                                    Ok(()) => settings.has_logo = true,
+                                    Err(e) => {
+                                        log::warn!("Failed to store logo for org {}: {}", org_id.to_string(), e);
+                                        return settings_page(
+                                            &data,
+                                            ctx,
+                                            org_id,
+                                            &org,
+                                            &settings,
+                                            "The logo could not be read. Please upload a PNG or JPEG image.",
+                                        );
+                                    }
+                                }
+                            } else if fields.contains_key("remove_logo") && settings.has_logo {
+                                settings.has_logo = false;
+                                if let Err(e) = std::fs::remove_file(data.org_logo_path(&org_id)) {
+                                    log::warn!("Failed to remove logo for org {}: {}", org_id.to_string(), e);
+                                }
+                            }
+
+                            if let Err(e) = data.org_settings_db.insert(&org_id, &settings) {
+                                return HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e)));
+                            }
+                            match data.org_db.insert(&org_id, &org) {
+                                Ok(()) => HttpResponse::SeeOther()
+                                    .header(http::header::LOCATION, dir::org_path(org_id) + dir::ORG_SETTINGS_PATH)
+                                    .body(""),
+                                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from(format!("Error: {}", e))),
+                            }
+                        }
+                        _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from("Failed to fetch org!")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[get("/org/{org}/logo")]
+pub async fn logo_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    org_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&org_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user.user_agent.can_view_org(&org_id) {
+                    let path: String = data.org_logo_path(&org_id);
+                    match web::block(move || NamedFile::open(path)).await {
+                        Ok(file) => page::file_response(&req, file, DispositionType::Inline, "logo.png".to_owned()),
+                        Err(_) => HttpResponse::new(http::StatusCode::NOT_FOUND)
+                            .set_body(Body::from("This organisation does not have a logo")),
+                    }
+                } else {
+                    page::not_authorized_page(Some(ctx), &data)
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
@@ -66,6 +66,7 @@ pub async fn stats_section_get(
                                 "share": share,
                                 "completions": activity_point.completed,
                                 "completion_rate": completion_rate,
+                                "switched_away": activity_point.switched_away,
                         }),
                             )
                             .unwrap();
@@ -89,6 +90,7 @@ pub async fn stats_section_get(
                         "choices": section_point.total,
                         "completions": total_completions,
                         "completion_rate": total_rate,
+                        "switches": section_point.switches,
                      })).unwrap();
 
 
@@ -0,0 +1,212 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::auth::AuthContext;
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+fn org_name(data: &SharedData, org_id: &org::OrgKey) -> String {
+    match data.org_db.fetch(org_id) {
+        Ok(Some(org)) => org.name,
+        _ => "Deleted organisation".to_owned(),
+    }
+}
+
+fn transfer_page(
+    data: &SharedData,
+    ctx: AuthContext,
+    org_id: org::OrgKey,
+    org: &org::Org,
+    user_id: user::UserKey,
+    user: &user::User,
+    err_msg: &str,
+) -> HttpResponse {
+    let mut orgs: Vec<(String, serde_json::Value)> = Vec::new();
+    data.org_db.for_each(|other_id: &org::OrgKey, other| {
+        if other_id != &org_id && !data.is_org_archived(other_id) {
+            orgs.push((
+                other.name.clone(),
+                json!({
+                    "org_id": other_id,
+                    "name": other.name,
+                    "credits": other.credits,
+                }),
+            ));
+        }
+    });
+    orgs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let transfers: Vec<_> = data
+        .client_transfers(&user_id)
+        .iter()
+        .map(|transfer| {
+            json!({
+                "time": util::format_time(transfer.time),
+                "from": org_name(data, &transfer.from_org),
+                "to": org_name(data, &transfer.to_org),
+                "actor": match data.user_db.fetch(&transfer.actor) {
+                    Ok(Some(actor)) => actor.name(),
+                    _ => "Deleted user".to_owned(),
+                },
+            })
+        })
+        .collect();
+
+    let body: String = data
+        .handlebars
+        .render(
+            "client/transfer",
+            &json!({
+                "back_url": dir::client_path(org_id, user_id),
+                "name": user.name(),
+                "org_name": &org.name,
+                "err_msg": err_msg,
+                "transfer_url": dir::client_path(org_id, user_id) + dir::TRANSFER_PATH,
+                "orgs": orgs.into_iter().map(|(_, x)| x).collect::<Vec<_>>(),
+                "transfers": transfers,
+            }),
+        )
+        .unwrap();
+
+    let header: String = page::path_header(
+        data,
+        &ctx.user.user_agent.privilege(),
+        &[
+            (
+                dir::ORGS_PAGE.to_owned(),
+                dir::ORGS_TITLE.to_owned(),
+                Privilege::RootLevel,
+            ),
+            (dir::org_path(org_id), org.name.clone(), Privilege::OrgLevel),
+            (
+                dir::client_path(org_id, user_id),
+                user.name(),
+                Privilege::ClientLevel,
+            ),
+            (
+                dir::client_path(org_id, user_id) + dir::TRANSFER_PATH,
+                dir::TRANSFER_TITLE.to_owned(),
+                Privilege::ClientLevel,
+            ),
+        ],
+    );
+
+    let root: String = data
+        .handlebars
+        .render(
+            "client/client_root",
+            &json!({
+                "header": header,
+                "body": body,
+                "header_properties": "",
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | " + dir::TRANSFER_TITLE + " - " + &user.name(),
+        dir::EXTENDED_APP_NAME.to_owned(),
+        root,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+#[get("/org/{org}/client/{user}/transfer")]
+pub async fn transfer_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_transfer_clients() {
+                        match (data.org_db.fetch(&org_id), data.user_db.fetch(&user_id)) {
+                            (Ok(Some(org)), Ok(Some(user))) if user.user_agent.org_id() == Some(org_id) => {
+                                transfer_page(&data, ctx, org_id, &org, user_id, &user, "")
+                            }
+                            (Ok(Some(_)), _) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("Could not find user!")),
+                            _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from("Failed to fetch org!")),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransferForm {
+    org_id: org::OrgKey,
+}
+
+#[post("/org/{org}/client/{user}/transfer")]
+pub async fn transfer_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: web::Form<TransferForm>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => {
+                    if ctx.user.user_agent.can_transfer_clients() {
+                        match (data.org_db.fetch(&org_id), data.user_db.fetch(&user_id)) {
+                            (Ok(Some(org)), Ok(Some(user))) if user.user_agent.org_id() == Some(org_id) => {
+                                let actor = ctx.user_id;
+                                match data.transfer_client(&user_id, &form.org_id, actor) {
+                                    Ok(()) => HttpResponse::SeeOther()
+                                        .header(http::header::LOCATION, dir::client_path(form.org_id, user_id))
+                                        .body(""),
+                                    Err(e) => transfer_page(&data, ctx, org_id, &org, user_id, &user, &e.to_string()),
+                                }
+                            }
+                            (Ok(Some(_)), _) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("Could not find user!")),
+                            _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                .set_body(Body::from("Failed to fetch org!")),
+                        }
+                    } else {
+                        page::not_authorized_page(Some(ctx), &data)
+                    }
+                }
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
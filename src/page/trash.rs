@@ -0,0 +1,242 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{body::Body, get, http, post, web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::data::SharedData;
+
+use crate::dir;
+use crate::org;
+use crate::page;
+use crate::trash;
+use crate::user;
+use crate::util;
+
+use user::Privilege;
+
+/// Describes a trash item, e.g. `photo.jpg` from `Creative Skills - Video Editing`.
+fn describe_item(data: &SharedData, item: &trash::TrashItem) -> (String, String) {
+    let section_description = |section: &crate::section::Section| -> String {
+        let mut description: String = String::new();
+        if let Some(section_info) = data
+            .awards
+            .get(&section.award)
+            .and_then(|award| award.sections.get(section.section_index))
+        {
+            description += &section_info.name;
+            if let Some(activity) = section_info.activities.get(&section.activity) {
+                description += &(" - ".to_owned() + &activity.name);
+            }
+        }
+        description
+    };
+
+    match item {
+        trash::TrashItem::Asset { section_id, filename } => (
+            filename.clone(),
+            match data.section_db.fetch(section_id) {
+                Ok(Some(section)) => "File from ".to_owned() + &section_description(&section),
+                _ => "File from a deleted section".to_owned(),
+            },
+        ),
+        trash::TrashItem::Section { section, .. } => {
+            (section_description(section), "Section and all of its files".to_owned())
+        }
+    }
+}
+
+#[get("/org/{org}/client/{user}/trash")]
+pub async fn client_trash_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    if let Ok(org_id) = org::OrgKey::from_str(&(path.0).0) {
+        if let Ok(user_id) = user::UserKey::from_str(&(path.0).1) {
+            match data.authenticate_context_from_request(&req, true) {
+                Ok(Some(ctx)) => match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => {
+                        if user.user_agent.org_id() != Some(org_id) {
+                            return HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                                .set_body(Body::from("User is not a client of this organisation!"));
+                        }
+                        if data.can_view_user(&ctx, &user_id, &user) || ctx.user_id == user_id {
+                            match data.org_db.fetch(&org_id) {
+                                Ok(Some(org)) => {
+                                    let mut entries: Vec<(trash::TrashKey, trash::TrashEntry)> = Vec::new();
+                                    data.trash_db.for_each(|trash_id: &trash::TrashKey, entry| {
+                                        if entry.user_id == user_id {
+                                            entries.push((*trash_id, entry));
+                                        }
+                                    });
+                                    entries.sort_by_key(|x| std::cmp::Reverse(x.1.deleted));
+
+                                    let mut rows: String = String::new();
+                                    for (trash_id, entry) in entries.iter() {
+                                        let (name, description) = describe_item(&data, &entry.item);
+                                        let deleted_by: String = match data.user_db.fetch(&entry.deleted_by) {
+                                            Ok(Some(deleted_by)) => deleted_by.name(),
+                                            _ => "Unknown".to_owned(),
+                                        };
+                                        rows += &data
+                                            .handlebars
+                                            .render(
+                                                "trash/trash_row",
+                                                &json!({
+                                                    "name": name,
+                                                    "description": description,
+                                                    "deleted": util::format_time(entry.deleted),
+                                                    "deleted_by": deleted_by,
+                                                    "expires": util::format_time(entry.expires()),
+                                                    "restore_url": dir::TRASH_PATH.to_owned() + "/" + &trash_id.to_string() + "/restore",
+                                                }),
+                                            )
+                                            .unwrap();
+                                    }
+
+                                    let body: String = data
+                                        .handlebars
+                                        .render(
+                                            "trash/trash_list",
+                                            &json!({
+                                                "back_url": dir::client_path(org_id, user_id),
+                                                "rows": rows,
+                                                "empty": entries.is_empty(),
+                                                "retention_days": dir::TRASH_RETENTION_DAYS,
+                                            }),
+                                        )
+                                        .unwrap();
+
+                                    let header: String = page::path_header(
+                                        &data,
+                                        &ctx.user.user_agent.privilege(),
+                                        &[
+                                            (
+                                                dir::ORGS_PAGE.to_owned(),
+                                                dir::ORGS_TITLE.to_owned(),
+                                                Privilege::RootLevel,
+                                            ),
+                                            (
+                                                dir::org_path(org_id),
+                                                org.name.clone(),
+                                                Privilege::OrgLevel,
+                                            ),
+                                            (
+                                                dir::client_path(org_id, user_id),
+                                                user.name(),
+                                                Privilege::ClientLevel,
+                                            ),
+                                            (
+                                                dir::client_path(org_id, user_id) + dir::TRASH_PATH,
+                                                dir::TRASH_TITLE.to_owned(),
+                                                Privilege::ClientLevel,
+                                            ),
+                                        ],
+                                    );
+
+                                    let header_properties: String = {
+                                        if ctx.user.user_agent.privilege() == Privilege::ClientLevel {
+                                            "hidden".to_owned()
+                                        } else {
+                                            String::new()
+                                        }
+                                    };
+
+                                    let root: String = data
+                                        .handlebars
+                                        .render(
+                                            "client/client_root",
+                                            &json!({
+                                                "header": header,
+                                                "body": body,
+                                                "header_properties": header_properties,
+                                            }),
+                                        )
+                                        .unwrap();
+
+                                    let body = page::render_page(
+                                        Some(ctx),
+                                        &data,
+                                        dir::APP_NAME.to_owned() + " | " + dir::TRASH_TITLE,
+                                        dir::EXTENDED_APP_NAME.to_owned(),
+                                        root,
+                                    )
+                                    .unwrap();
+
+                                    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+                                }
+                                _ => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                                    .set_body(Body::from("Failed to fetch org!")),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => page::redirect_to_login(&req),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        } else {
+            HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid org_id"))
+    }
+}
+
+#[post("/trash/{trash}/restore")]
+pub async fn restore_trash_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    trash_path: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(trash_id) = trash::TrashKey::from_str(&trash_path) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => match data.trash_db.fetch(&trash_id) {
+                Ok(Some(entry)) => match data.user_db.fetch(&entry.user_id) {
+                    Ok(Some(user)) => {
+                        if data.can_view_user(&ctx, &entry.user_id, &user) || ctx.user_id == entry.user_id {
+                            if data.is_user_archived(&user.user_agent) {
+                                return page::archived_org_page(Some(ctx), &data);
+                            }
+                            match data.restore_trash(&trash_id) {
+                                Ok(_) => {
+                                    let mut r = HttpResponse::SeeOther();
+                                    if let Some(referer) = req.headers().get("Referer") {
+                                        r.header(http::header::LOCATION, referer.clone());
+                                    }
+                                    r.body("")
+                                }
+                                Err(e) => page::error_page(Some(ctx), &data, "Could not restore item", &e.to_string()),
+                            }
+                        } else {
+                            page::not_authorized_page(Some(ctx), &data)
+                        }
+                    }
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                },
+                Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                    .set_body(Body::from("This item is no longer in the trash")),
+
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            },
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid trash_id"))
+    }
+}
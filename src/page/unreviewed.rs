@@ -28,9 +28,13 @@ pub async fn unreviewed_get(
                     match data.org_db.fetch(&org_id) {
                         Ok(Some(org)) => {
                             let mut rows: String = String::new();
+                            let restriction = data.associate_restriction(&ctx);
 
                             for section_id in org.unreviewed_sections.iter() {
                                 if let Ok(Some(section_instance)) = data.section_db.fetch(section_id) {
+                                    if matches!(&restriction, Some(x) if !x.contains(&section_instance.user_id)) {
+                                        continue;
+                                    }
                                     if let Ok(Some(user)) = data.user_db.fetch(&section_instance.user_id) {
                                         if let Some(award) = data.awards.get(&section_instance.award) {
                                             let date_str: String = {
@@ -4,12 +4,17 @@ use std::sync::Arc;
 
 use serde_json::json;
 
+use crate::auth::AuthContext;
 use crate::data::SharedData;
 
 use crate::dir;
+use crate::export;
 use crate::page;
 use crate::db;
+use crate::link;
+use crate::login;
 use crate::user;
+use crate::util;
 
 use actix_web::{get, post};
 
@@ -127,6 +132,16 @@ pub async fn user_get(
                                 &json!({
                                     "name": user.name(),
                                     "attributes": attrs,
+                                    "edit_url": if is_active_user {
+                                        Some(dir::user_path(user_id) + dir::EDIT_PROFILE_PATH)
+                                    } else {
+                                        None
+                                    },
+                                    "export_url": if is_active_user || ctx.user.user_agent.can_export_user_data() {
+                                        Some(dir::user_path(user_id) + dir::EXPORT_USER_DATA_PATH)
+                                    } else {
+                                        None
+                                    },
                                 }),
                             )
                             .unwrap();
@@ -206,9 +221,268 @@ pub async fn enable_notifications_get(
     }
 }
 
+fn edit_profile_page(
+    ctx: AuthContext,
+    data: &SharedData,
+    user_id: user::UserKey,
+    user: &user::User,
+    err_msg: &str,
+    info_msg: &str,
+) -> HttpResponse {
+    let page_body = data
+        .handlebars
+        .render(
+            "user/edit_profile",
+            &json!({
+                "back_url": dir::user_path(user_id),
+                "edit_url": dir::user_path(user_id) + dir::EDIT_PROFILE_PATH,
+                "change_email_url": dir::user_path(user_id) + "/change_email",
+                "forename": user.forename,
+                "surname": user.surname,
+                "email": user.email,
+                "err_msg": err_msg,
+                "info_msg": info_msg,
+            }),
+        )
+        .unwrap();
+
+    let body = page::render_page(
+        Some(ctx),
+        data,
+        dir::APP_NAME.to_owned() + " | Edit Profile",
+        dir::EXTENDED_APP_NAME.to_owned(),
+        page_body,
+    )
+    .unwrap();
+
+    HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+}
+
+/// Authenticates a request to edit a profile, which users can only do for themselves.
+fn own_profile(
+    data: &SharedData,
+    req: &HttpRequest,
+    user_id_str: &str,
+) -> Result<(AuthContext, user::UserKey, user::User), HttpResponse> {
+    let user_id = user::UserKey::from_str(user_id_str)
+        .map_err(|_| HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id")))?;
+    match data.authenticate_context_from_request(req, true) {
+        Ok(Some(ctx)) => {
+            if ctx.user_id == user_id {
+                let user = ctx.user.clone();
+                Ok((ctx, user_id, user))
+            } else {
+                Err(page::not_authorized_page(Some(ctx), data))
+            }
+        }
+        Ok(None) => Err(page::redirect_to_login(req)),
+
+        Err(e) => Err(HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e)))),
+    }
+}
+
+#[get("/user/{user}/edit")]
+pub async fn edit_profile_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    user_id_str: web::Path<String>,
+) -> HttpResponse {
+    match own_profile(&data, &req, &user_id_str) {
+        Ok((ctx, user_id, user)) => edit_profile_page(ctx, &data, user_id, &user, "", ""),
+        Err(response) => response,
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditProfileForm {
+    forename: String,
+    surname: String,
+}
+
+#[post("/user/{user}/edit")]
+pub async fn edit_profile_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    user_id_str: web::Path<String>,
+    form: web::Form<EditProfileForm>,
+) -> HttpResponse {
+    match own_profile(&data, &req, &user_id_str) {
+        Ok((ctx, user_id, mut user)) => {
+            let forename: &str = form.forename.trim();
+            let surname: &str = form.surname.trim();
+            if !util::is_string_server_valid(forename) {
+                return edit_profile_page(ctx, &data, user_id, &user, "Please enter a valid forename.", "");
+            }
+            if !util::is_string_server_valid(surname) {
+                return edit_profile_page(ctx, &data, user_id, &user, "Please enter a valid surname.", "");
+            }
+            user.forename = forename.to_owned();
+            user.surname = surname.to_owned();
+            match data.user_db.insert(&user_id, &user) {
+                Ok(()) => HttpResponse::SeeOther()
+                    .header(http::header::LOCATION, dir::user_path(user_id))
+                    .body(""),
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        }
+        Err(response) => response,
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangeEmailForm {
+    email: String,
+    password: String,
+}
+
+/// Sends a link to the new address, so that the email only changes once the user has shown they can receive it.
+#[post("/user/{user}/change_email")]
+pub async fn change_email_post(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    user_id_str: web::Path<String>,
+    form: web::Form<ChangeEmailForm>,
+) -> HttpResponse {
+    match own_profile(&data, &req, &user_id_str) {
+        Ok((ctx, user_id, user)) => {
+            let email: String = util::normalise_email(&form.email);
+            if data.login_db.authenticate(&user.email, &form.password).is_err() {
+                return edit_profile_page(ctx, &data, user_id, &user, "The password is incorrect.", "");
+            }
+            if !util::is_email_valid(&email) {
+                return edit_profile_page(ctx, &data, user_id, &user, "Please enter a valid email address.", "");
+            }
+            if email == user.email {
+                return edit_profile_page(ctx, &data, user_id, &user, "This is already your email address.", "");
+            }
+            if data.login_db.db().contains_key(&email).unwrap_or(true) {
+                return edit_profile_page(ctx, &data, user_id, &user, "This email is associated with another account.", "");
+            }
+
+            match data.link_manager.create_link(
+                link::Link::ChangeEmail(user_id, email.clone()),
+                std::time::Duration::from_secs(dir::CHANGE_EMAIL_LINK_TIMEOUT_SECS),
+            ) {
+                Ok(link_token) => {
+                    let link: String =
+                        dir::make_absolute_url(&(dir::CONFIRM_EMAIL_PATH.to_owned() + "/" + &link_token.to_string()));
+                    let subtitle = "You have asked to change the email address of your Senior Duke account to this address. <br><a href=\"".to_owned()
+                        + &link
+                        + "\">"
+                        + "Click here</a> to confirm the change. The link expires in 24 hours.";
+
+                    if data
+                        .send_email(
+                            &email,
+                            "Senior Duke - Confirm Your Email",
+                            "Confirm Your Email",
+                            &subtitle,
+                            "If you didn't ask for this change, you can ignore this email.",
+                        )
+                        .is_none()
+                    {
+                        log::error!("Failed to send email!");
+                    }
+
+                    let info_msg: String = "A confirmation link has been sent to ".to_owned()
+                        + &email
+                        + ". Your email will change once you follow the link.";
+                    edit_profile_page(ctx, &data, user_id, &user, "", &info_msg)
+                }
+                Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .set_body(Body::from(format!("Error: {}", e))),
+            }
+        }
+        Err(response) => response,
+    }
+}
+
+#[get("/user/confirm_email/{link_token}")]
+pub async fn confirm_email_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    link_token_str: web::Path<String>,
+) -> HttpResponse {
+    let ctx = match data.authenticate_context_from_request(&req, false) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            return HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e)))
+        }
+    };
+    let token = match link::LinkToken::from_str(&link_token_str) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("bad link")),
+    };
+    match data.link_manager.fetch(&token) {
+        Ok(Some(link::LinkEntry {
+            link: link::Link::ChangeEmail(user_id, email),
+            ..
+        })) => match data.change_email(&user_id, &email) {
+            Ok(old_email) => {
+                let _ = data.link_manager.destroy_link(&token);
+
+                let subtitle = "The email address of your Senior Duke account has been changed to ".to_owned()
+                    + &email
+                    + ". You will need to use the new address to log in.";
+                if data
+                    .send_email(
+                        &old_email,
+                        "Senior Duke - Email Changed",
+                        "Email Changed",
+                        &subtitle,
+                        "If you didn't make this change, please contact your organisation administrator.",
+                    )
+                    .is_none()
+                {
+                    log::error!("Failed to send email!");
+                }
+
+                let page_body = data
+                    .handlebars
+                    .render(
+                        "user/email_changed",
+                        &json!({
+                            "email": email,
+                            "login_url": dir::LOGIN_PAGE,
+                        }),
+                    )
+                    .unwrap();
+
+                let body = page::render_page(
+                    ctx,
+                    &data,
+                    dir::APP_NAME.to_owned() + " | Email Changed",
+                    dir::EXTENDED_APP_NAME.to_owned(),
+                    page_body,
+                )
+                .unwrap();
+
+                HttpResponse::new(http::StatusCode::OK).set_body(Body::from(body))
+            }
+            Err(login::LoginEntryError::UsernameExists) => page::error_page(
+                ctx,
+                &data,
+                "Could not change email",
+                "This email is now associated with another account.",
+            ),
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        },
+        Ok(_) => HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("bad link")),
+
+        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+            .set_body(Body::from(format!("Error: {}", e))),
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct DeleteUserForm {
     user_id: user::UserKey,
+    /// Set for a request for erasure, which also scrubs the user from the records that are kept after deletion.
+    erase: Option<String>,
 }
 
 #[post("/delete_user")]
@@ -224,6 +498,17 @@ pub async fn delete_user_post(
                     if !ctx.user.user_agent.can_delete_user(&target.user_agent) {
                         return page::not_authorized_page(Some(ctx), &data);
                     }
+                    if data.is_user_archived(&target.user_agent) {
+                        return page::archived_org_page(Some(ctx), &data);
+                    }
+                    if data.is_last_org_admin(&form.user_id, &target.user_agent) {
+                        return page::error_page(
+                            Some(ctx),
+                            &data,
+                            "Could not delete user",
+                            "This is the only administrator of the organisation. Invite another administrator before removing this account.",
+                        );
+                    }
                 }
                 Err(db::Error::DeserializeError(_)) => {
                     if !ctx.user.user_agent.can_delete_invalid_users() {
@@ -237,7 +522,12 @@ pub async fn delete_user_post(
             };
             // delete
 
-            match data.delete_user(&form.user_id) {
+            let deleted: bool = if form.erase.is_some() {
+                data.erase_user(&form.user_id, Some(ctx.user_id))
+            } else {
+                data.delete_user(&form.user_id, Some(ctx.user_id))
+            };
+            match deleted {
                 true => {
                     let mut r = HttpResponse::SeeOther();
                     if let Some(referer) = req.headers().get("Referer") {
@@ -255,3 +545,48 @@ pub async fn delete_user_post(
             .set_body(Body::from(format!("Error: {}", e))),
     }
 }
+
+/// Downloads a zip of everything held about a user, for the user themselves or an owner answering a subject access request.
+#[get("/user/{user}/export")]
+pub async fn export_user_data_get(
+    data: web::Data<Arc<SharedData>>,
+    req: HttpRequest,
+    user_id_str: web::Path<String>,
+) -> HttpResponse {
+    if let Ok(user_id) = user::UserKey::from_str(&user_id_str) {
+        match data.authenticate_context_from_request(&req, true) {
+            Ok(Some(ctx)) => {
+                if ctx.user_id != user_id && !ctx.user.user_agent.can_export_user_data() {
+                    return page::not_authorized_page(Some(ctx), &data);
+                }
+                match data.user_db.fetch(&user_id) {
+                    Ok(Some(user)) => match data.temp_path("zip") {
+                        Ok(path) => {
+                            let filename: String = sanitize_filename::sanitize(user.name() + " - Data.zip");
+                            let zip_data: Arc<SharedData> = data.get_ref().clone();
+                            let zip_path: String = path.clone();
+                            match web::block(move || export::write_subject_access_zip(&zip_data, &zip_path, user_id, &user))
+                                .await
+                            {
+                                Ok(_) => page::temp_file_response(&req, path, filename).await,
+                                Err(e) => page::temp_file_error(&path, e),
+                            }
+                        }
+                        Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                            .set_body(Body::from(format!("Error: {}", e))),
+                    },
+                    Ok(None) => HttpResponse::new(http::StatusCode::BAD_REQUEST)
+                        .set_body(Body::from("Could not find user!")),
+                    Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .set_body(Body::from(format!("Error: {}", e))),
+                }
+            }
+            Ok(None) => page::redirect_to_login(&req),
+
+            Err(e) => HttpResponse::new(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .set_body(Body::from(format!("Error: {}", e))),
+        }
+    } else {
+        HttpResponse::new(http::StatusCode::BAD_REQUEST).set_body(Body::from("Invalid user_id"))
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{certificate::text_width, data::SharedData, export, section, user, util::format_time};
+use printpdf::{
+    BuiltinFont, ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject, IndirectFontRef, Mm,
+    PdfDocument, PdfDocumentReference, PdfLayerReference, Px,
+};
+use std::io::BufWriter;
+
+const PAGE_WIDTH: f64 = 210.0;
+const PAGE_HEIGHT: f64 = 297.0;
+const MARGIN: f64 = 20.0;
+
+/// The largest size an image is drawn at, in mm.
+const MAX_IMAGE_WIDTH: f64 = PAGE_WIDTH - 2.0 * MARGIN;
+const MAX_IMAGE_HEIGHT: f64 = 120.0;
+
+#[derive(Clone, Copy)]
+enum Style {
+    Regular,
+    Bold,
+    Italic,
+}
+
+/// Lays out text and images down A4 pages, starting a new page when the current one is full.
+struct PdfWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    y: f64,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    italic: IndirectFontRef,
+}
+
+impl PdfWriter {
+    fn new(title: &str) -> Result<Self, printpdf::Error> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Portfolio");
+        let layer = doc.get_page(page).get_layer(layer);
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let italic = doc.add_builtin_font(BuiltinFont::HelveticaOblique)?;
+        Ok(Self {
+            doc,
+            layer,
+            y: PAGE_HEIGHT - MARGIN,
+            regular,
+            bold,
+            italic,
+        })
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Portfolio");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page unless there is `height` mm left on the current one.
+    fn ensure_space(&mut self, height: f64) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn gap(&mut self, height: f64) {
+        self.y -= height;
+    }
+
+    /// Writes text wrapped to the width of the page. Line breaks in the text are kept.
+    fn text(&mut self, text: &str, font_size: f64, style: Style) {
+        let line_height: f64 = font_size * 0.3528 * 1.4;
+        for line in wrap(text, font_size, PAGE_WIDTH - 2.0 * MARGIN) {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            let font = match style {
+                Style::Regular => &self.regular,
+                Style::Bold => &self.bold,
+                Style::Italic => &self.italic,
+            };
+            self.layer.use_text(line, font_size, Mm(MARGIN), Mm(self.y), font);
+        }
+    }
+
+    /// Draws an image centred on the page, scaled down to fit.
+    fn image(&mut self, image: export::EmbeddedImage) {
+        // The dpi sets the drawn size, so pick whichever makes the image fit both ways.
+        let dpi: f64 = (image.width as f64 * 25.4 / MAX_IMAGE_WIDTH)
+            .max(image.height as f64 * 25.4 / MAX_IMAGE_HEIGHT)
+            .max(150.0);
+        let width: f64 = image.width as f64 / dpi * 25.4;
+        let height: f64 = image.height as f64 / dpi * 25.4;
+        self.ensure_space(height + 5.0);
+        self.y -= height + 5.0;
+
+        Image::from(ImageXObject {
+            width: Px(image.width as usize),
+            height: Px(image.height as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: image.jpeg,
+            image_filter: Some(ImageFilter::DCT),
+            clipping_bbox: None,
+        })
+        .add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm((PAGE_WIDTH - width) / 2.0)),
+                translate_y: Some(Mm(self.y)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn finish(self) -> Result<Vec<u8>, printpdf::Error> {
+        let mut buf = BufWriter::new(Vec::new());
+        self.doc.save(&mut buf)?;
+        buf.into_inner().map_err(|e| printpdf::Error::Io(e.into_error()))
+    }
+}
+
+/// Splits text into lines which fit within `width` mm, breaking at spaces where possible.
+fn wrap(text: &str, font_size: f64, width: f64) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for paragraph in text.lines() {
+        let mut line: String = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate: String = if line.is_empty() {
+                word.to_owned()
+            } else {
+                line.clone() + " " + word
+            };
+            if text_width(&candidate, font_size) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words too long for a line on their own are split wherever they run out of space.
+            for c in word.chars() {
+                line.push(c);
+                if text_width(&line, font_size) > width {
+                    line.pop();
+                    lines.push(std::mem::take(&mut line));
+                    line.push(c);
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Converts the HTML of an activity description to plain text, keeping its line and paragraph breaks.
+fn html_to_text(html: &str) -> String {
+    let mut text: String = String::new();
+    let mut tag: Option<String> = None;
+    for c in html.chars() {
+        if let Some(name) = &mut tag {
+            if c != '>' {
+                name.push(c);
+                continue;
+            }
+            let name: String = name
+                .trim_matches('/')
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            if matches!(name.as_str(), "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4") && !text.ends_with("\n\n") {
+                text.truncate(text.trim_end_matches(' ').len());
+                text.push('\n');
+            }
+            tag = None;
+        } else if c == '<' {
+            tag = Some(String::new());
+        } else if c.is_whitespace() {
+            // Line breaks in the HTML source aren't line breaks on the page.
+            if !text.is_empty() && !text.ends_with(' ') && !text.ends_with('\n') {
+                text.push(' ');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text.trim()
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Renders a pupil's portfolio as an offline HTML document with their photos embedded.
+pub fn render_html(data: &SharedData, user: &user::User) -> String {
+    let sections = export::client_sections(data, user);
+    export::summary_document(data, user, &sections, true)
+}
+
+/// Renders a pupil's portfolio as an A4 PDF, with one page or more per section.
+pub fn render_pdf(data: &SharedData, user: &user::User) -> Result<Vec<u8>, printpdf::Error> {
+    let (award, org): (String, String) = match &user.user_agent {
+        user::UserAgent::Client { org_id, award, .. } => (
+            data.awards
+                .get(award)
+                .map(|x| x.name.clone())
+                .unwrap_or_else(|| award.clone()),
+            match data.org_db.fetch(org_id) {
+                Ok(Some(org)) => org.name,
+                _ => String::new(),
+            },
+        ),
+        _ => (String::new(), String::new()),
+    };
+
+    let mut pdf = PdfWriter::new(&(user.name() + " - " + &award + " Portfolio"))?;
+    pdf.text(&user.name(), 24.0, Style::Bold);
+    pdf.gap(2.0);
+    if org.is_empty() {
+        pdf.text(&award, 14.0, Style::Regular);
+    } else {
+        pdf.text(&(award + " - " + &org), 14.0, Style::Regular);
+    }
+    pdf.text(
+        &("Generated on ".to_owned() + &format_time(std::time::SystemTime::now())),
+        10.0,
+        Style::Italic,
+    );
+
+    for (section_id, section) in export::client_sections(data, user) {
+        pdf.new_page();
+        write_section(data, &mut pdf, &section_id, &section);
+    }
+
+    pdf.finish()
+}
+
+fn write_section(data: &SharedData, pdf: &mut PdfWriter, section_id: &section::SectionKey, section: &section::Section) {
+    let section_name: String = data
+        .awards
+        .get(&section.award)
+        .and_then(|award| award.sections.get(section.section_index))
+        .map(|x| x.name.clone())
+        .unwrap_or_default();
+
+    let (activity_name, description, answers): (String, String, Vec<(String, String)>) = match section.get_activity(data) {
+        Some(activity) => (
+            activity.name.clone(),
+            html_to_text(&export::activity_description(data, activity)),
+            section.answers(activity),
+        ),
+        None => (section.activity.clone(), String::new(), Vec::new()),
+    };
+
+    pdf.text(&(section_name + " - " + &activity_name), 18.0, Style::Bold);
+    let mut state: String = section.state.to_string();
+    if let Some(time) = section.state.time() {
+        state += &(" (".to_owned() + &format_time(time) + ")");
+    }
+    pdf.text(&state, 10.0, Style::Italic);
+    if !description.is_empty() {
+        pdf.gap(4.0);
+        pdf.text(&description, 11.0, Style::Regular);
+    }
+
+    let mut fields: Vec<(String, String)> = vec![("Plan".to_owned(), section.plan.clone())];
+    fields.extend(answers);
+    fields.push(("Reflection".to_owned(), section.reflection.clone()));
+    for (title, answer) in fields {
+        pdf.gap(4.0);
+        pdf.text(&title, 13.0, Style::Bold);
+        pdf.gap(1.0);
+        pdf.text(&answer, 11.0, Style::Regular);
+    }
+
+    let images = export::section_images(data, section_id);
+    if !images.is_empty() {
+        pdf.gap(4.0);
+        pdf.text("Evidence", 13.0, Style::Bold);
+        for (_, image) in images {
+            pdf.image(image);
+        }
+    }
+}
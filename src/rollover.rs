@@ -0,0 +1,80 @@
+use crate::{class::ClassKey, db, org::OrgKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What happens to a group of pupils at the end of the year.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientAction {
+    /// The pupil stays in the org, and is moved to the class chosen for their group.
+    Keep,
+    /// The pupil is removed from the org but their account and work are kept. They can no longer log in.
+    Archive,
+    Delete,
+}
+
+impl FromStr for ClientAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(ClientAction::Keep),
+            "archive" => Ok(ClientAction::Archive),
+            "delete" => Ok(ClientAction::Delete),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClassTarget {
+    Stay,
+    NoClass,
+    Class(ClassKey),
+}
+
+/// The actions for the pupils of one class, or those not in a class if `class_id` is `None`.
+#[derive(Debug, Clone)]
+pub struct GroupPlan {
+    pub class_id: Option<ClassKey>,
+    pub completed: ClientAction,
+    pub incomplete: ClientAction,
+    pub move_to: ClassTarget,
+}
+
+#[derive(Debug, Clone)]
+pub struct RolloverPlan {
+    pub groups: Vec<GroupPlan>,
+    /// Whether the unused credits of the org expire rather than carrying over to next year.
+    pub expire_credits: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RolloverSummary {
+    pub certified: usize,
+    pub archived: usize,
+    pub deleted: usize,
+    pub moved: usize,
+    pub expired_credits: u32,
+}
+
+impl fmt::Display for RolloverSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Year rollover: {} certificates issued, {} pupils archived, {} pupils deleted, {} pupils moved to a new class, {} unused credits expired.",
+            self.certified, self.archived, self.deleted, self.moved, self.expired_credits
+        )
+    }
+}
+
+/// A pupil who has left their org. Their account and work are kept, but they can't log in and aren't listed with the org's pupils.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedClient {
+    pub org_id: OrgKey,
+    pub archived: SystemTime,
+    pub archived_by: UserKey,
+}
+
+pub type ArchivedClientDb = db::Database<UserKey, ArchivedClient>;
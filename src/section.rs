@@ -1,7 +1,7 @@
 use crate::{db, define_uuid_key, user, data};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use maplit::hashmap;
 
@@ -142,7 +142,48 @@ impl Default for SectionState {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The kind of media an uploaded asset contains, used to decide how it is displayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssetKind {
+    Image,
+    Video,
+    Audio,
+    Pdf,
+    Other,
+}
+
+impl AssetKind {
+    pub fn from_filename(filename: &str) -> Self {
+        let mime = mime_guess::from_path(filename).first_or_octet_stream();
+        match (mime.type_(), mime.subtype()) {
+            // SVGs can carry scripts, so they are only ever downloaded.
+            (mime::IMAGE, mime::SVG) => AssetKind::Other,
+            (mime::IMAGE, _) => AssetKind::Image,
+            (mime::VIDEO, _) => AssetKind::Video,
+            (mime::AUDIO, _) => AssetKind::Audio,
+            (mime::APPLICATION, mime::PDF) => AssetKind::Pdf,
+            _ => AssetKind::Other,
+        }
+    }
+
+    /// The template used to embed the asset in the section page, if it can be displayed in the browser.
+    pub fn template(&self) -> Option<&'static str> {
+        match self {
+            AssetKind::Image => Some("sections/image_asset"),
+            AssetKind::Video => Some("sections/video_asset"),
+            AssetKind::Audio => Some("sections/audio_asset"),
+            AssetKind::Pdf => Some("sections/pdf_asset"),
+            AssetKind::Other => None,
+        }
+    }
+
+    /// Whether the asset is safe to be served inline when viewed.
+    pub fn is_inline(&self) -> bool {
+        *self != AssetKind::Other
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Section {
     pub section_index: usize,
     pub award: String,
@@ -175,6 +216,16 @@ impl Section {
         }
     }
 
+    /// Removes everything the pupil wrote, keeping only the activity and how far they got with it.
+    pub fn anonymise(&mut self) {
+        self.plan.clear();
+        self.reflection.clear();
+        self.input_data.clear();
+        if let SectionState::Rejected(reason) = &mut self.state {
+            reason.clear();
+        }
+    }
+
     pub fn get_activity<'a, 'b>(&'a self, data: &'b data::SharedData) -> Option<&'b Activity> {
         if let Some(award) = data.awards.get(&self.award) {
             if let Some(section) = award.sections.get(self.section_index) {
@@ -183,6 +234,31 @@ impl Section {
         }
         None
     }
+
+    /// Returns the (title, answer) pairs for each input item of the activity, in display order.
+    pub fn answers(&self, activity: &Activity) -> Vec<(String, String)> {
+        let mut answers: Vec<(String, String)> = Vec::new();
+        for component in activity.components.iter() {
+            if let ActivityComponent::InputItem(item) = component {
+                let answer: String = match (&item.ty, self.input_data.get(&item.name)) {
+                    (FormEntryType::Text { .. }, Some(FormEntryData::Text(text))) => text.clone(),
+                    (FormEntryType::Radio(options), Some(FormEntryData::Index(idx))) => {
+                        options.get(*idx).cloned().unwrap_or_default()
+                    }
+                    (FormEntryType::Checkbox(options), Some(FormEntryData::Indices(indices))) => {
+                        indices
+                            .iter()
+                            .filter_map(|idx| options.get(*idx).cloned())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    }
+                    _ => String::new(),
+                };
+                answers.push((item.title.clone(), answer));
+            }
+        }
+        answers
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -216,6 +292,52 @@ define_uuid_key!(SectionKey);
 
 pub type SectionDb = db::Database<SectionKey, Section>;
 
+/// Autosaved field values that haven't been saved with the section form yet.
+/// The revision is bumped on every save so that stale tabs can be detected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SectionDraft {
+    pub revision: u64,
+    pub tab_id: String,
+    pub saved: Option<SystemTime>,
+    /// Kept sorted so that a draft always serialises the same way, as saves compare the stored draft.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// A previous attempt at a section, archived when a different activity was chosen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionAttempt {
+    pub section: Section,
+    pub archived: SystemTime,
+    pub archived_by: user::UserKey,
+}
+
+#[derive(Debug)]
+pub enum SectionError {
+    IoError(std::io::Error),
+    DbError(db::Error),
+}
+
+impl std::fmt::Display for SectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SectionError::IoError(e) => e.fmt(f),
+            SectionError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for SectionError {
+    fn from(e: std::io::Error) -> Self {
+        SectionError::IoError(e)
+    }
+}
+
+impl From<db::Error> for SectionError {
+    fn from(e: db::Error) -> Self {
+        SectionError::DbError(e)
+    }
+}
+
 impl SectionInfo {
     pub fn silver_sections_list() -> [SectionInfo; 6] {
         [
@@ -0,0 +1,68 @@
+use crate::{credit, db, org::OrgKey, user::UserKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::SystemTime;
+
+/// A record of a pupil being moved from one organisation to another along with their work.
+/// Transfers are kept after the pupil or either organisation is deleted, so the name of the pupil is stored too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub user_id: UserKey,
+    pub name: String,
+    pub from_org: OrgKey,
+    pub to_org: OrgKey,
+    pub time: SystemTime,
+    pub actor: UserKey,
+}
+
+impl Transfer {
+    pub fn new(user_id: UserKey, name: String, from_org: OrgKey, to_org: OrgKey, actor: UserKey) -> Self {
+        Self {
+            user_id,
+            name,
+            from_org,
+            to_org,
+            time: SystemTime::now(),
+            actor,
+        }
+    }
+}
+
+define_uuid_key!(TransferKey);
+
+pub type TransferDb = db::Database<TransferKey, Transfer>;
+
+#[derive(Debug)]
+pub enum TransferError {
+    NotClient,
+    NoOrg,
+    SameOrg,
+    Archived,
+    CreditError(credit::CreditError),
+    DbError(db::Error),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::NotClient => write!(f, "Only pupils can be transferred."),
+            TransferError::NoOrg => write!(f, "The organisation could not be found."),
+            TransferError::SameOrg => write!(f, "The pupil is already in this organisation."),
+            TransferError::Archived => write!(f, "Pupils can't be transferred to or from an archived organisation."),
+            TransferError::CreditError(e) => e.fmt(f),
+            TransferError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<credit::CreditError> for TransferError {
+    fn from(e: credit::CreditError) -> Self {
+        TransferError::CreditError(e)
+    }
+}
+
+impl From<db::Error> for TransferError {
+    fn from(e: db::Error) -> Self {
+        TransferError::DbError(e)
+    }
+}
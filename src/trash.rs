@@ -0,0 +1,114 @@
+use crate::{data::SharedData, db, dir, section, user};
+use async_std::task;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TrashItem {
+    /// A single uploaded file, stored in the trash directory under the key of the entry.
+    Asset {
+        section_id: section::SectionKey,
+        filename: String,
+    },
+    /// A section that was reset, whose asset directory is stored in the trash directory under the key of the entry.
+    Section {
+        section_id: section::SectionKey,
+        section: section::Section,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// The pupil who the deleted work belongs to.
+    pub user_id: user::UserKey,
+    pub deleted_by: user::UserKey,
+    pub deleted: SystemTime,
+    pub item: TrashItem,
+}
+
+impl TrashEntry {
+    pub fn new(user_id: user::UserKey, deleted_by: user::UserKey, item: TrashItem) -> Self {
+        Self {
+            user_id,
+            deleted_by,
+            deleted: SystemTime::now(),
+            item,
+        }
+    }
+
+    pub fn expires(&self) -> SystemTime {
+        self.deleted + Duration::from_secs(dir::TRASH_RETENTION_DAYS * 24 * 60 * 60)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now > self.expires()
+    }
+}
+
+define_uuid_key!(TrashKey);
+
+pub type TrashDb = db::Database<TrashKey, TrashEntry>;
+
+#[derive(Debug)]
+pub enum TrashError {
+    NoEntry,
+    /// The section that a file belonged to has been deleted.
+    NoSection,
+    /// The pupil has already started a different activity for the section.
+    SectionInUse,
+    /// The section a file belonged to has been approved, so its evidence can no longer be changed.
+    SectionCompleted,
+    /// The pupil has moved on to a different award since the section was deleted.
+    AwardChanged,
+    IoError(std::io::Error),
+    DbError(db::Error),
+}
+
+impl fmt::Display for TrashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrashError::NoEntry => write!(f, "The item could not be found in the trash!"),
+            TrashError::NoSection => write!(
+                f,
+                "The section this file belonged to no longer exists. Restore the section first."
+            ),
+            TrashError::SectionInUse => write!(
+                f,
+                "A different activity has been chosen for this section. Reset the section before restoring this one."
+            ),
+            TrashError::SectionCompleted => write!(
+                f,
+                "The section this file belonged to has been completed, so files can no longer be added to it."
+            ),
+            TrashError::AwardChanged => write!(
+                f,
+                "This section belongs to an award the pupil is no longer enrolled on, so it can't be restored."
+            ),
+            TrashError::IoError(e) => e.fmt(f),
+            TrashError::DbError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for TrashError {
+    fn from(e: std::io::Error) -> Self {
+        TrashError::IoError(e)
+    }
+}
+
+impl From<db::Error> for TrashError {
+    fn from(e: db::Error) -> Self {
+        TrashError::DbError(e)
+    }
+}
+
+pub async fn trash_purge_process(data: Arc<SharedData>) {
+    log::trace!("Starting trash purge process...");
+    loop {
+        data.purge_expired_trash();
+        // Items only need to be removed at some point after they expire.
+        task::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
@@ -12,6 +12,32 @@ pub struct User {
     pub notifications: bool,
 
     pub user_agent: UserAgent,
+
+    /// The id of a pupil in their school's management information system, used to match them when syncing a roster.
+    pub external_id: Option<String>,
+}
+
+/// The user record from before external ids were added, kept so that existing users can be upgraded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserV1 {
+    pub email: String,
+    pub forename: String,
+    pub surname: String,
+    pub notifications: bool,
+    pub user_agent: UserAgent,
+}
+
+impl From<UserV1> for User {
+    fn from(v1: UserV1) -> Self {
+        Self {
+            email: v1.email,
+            forename: v1.forename,
+            surname: v1.surname,
+            notifications: v1.notifications,
+            user_agent: v1.user_agent,
+            external_id: None,
+        }
+    }
 }
 
 impl User {
@@ -167,6 +193,86 @@ impl UserAgent {
         }
     }
 
+    pub fn can_revoke_certificates(&self) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            _ => false,
+        }
+    }
+
+    pub fn can_manage_org_admins(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            UserAgent::Admin => true,
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_manage_classes(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            UserAgent::Admin => true,
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_edit_org(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_import_users(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            UserAgent::Admin => true,
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_rollover_org(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_request_credits(&self, org_id: &OrgKey) -> bool {
+        match self {
+            UserAgent::Organisation(agent_org_id) => agent_org_id == org_id,
+            _ => false,
+        }
+    }
+
+    pub fn can_approve_credit_requests(&self) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the user can export everything held about another user, to answer a subject access request.
+    /// Anyone can export their own data.
+    pub fn can_export_user_data(&self) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            _ => false,
+        }
+    }
+
+    pub fn can_transfer_clients(&self) -> bool {
+        match self {
+            UserAgent::Owner => true,
+            _ => false,
+        }
+    }
+
     pub fn can_add_admin(&self) -> bool {
         match self {
             UserAgent::Owner => true,
@@ -27,6 +27,44 @@ pub fn is_email_valid(s: &str) -> bool {
     }
 }
 
+/// How the part of an email before the `@` is treated when normalising it. Almost all mail servers ignore its case,
+/// but it is allowed to be case sensitive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailLocalPart {
+    Preserve,
+    Lowercase,
+}
+
+/// Normalises an email for use as a username, so that the same address always gives the same account. Surrounding
+/// whitespace is removed and the domain is lowercased, and the local part is handled as set by `dir::EMAIL_LOCAL_PART`.
+pub fn normalise_email(s: &str) -> String {
+    let s: &str = s.trim();
+    match s.rsplit_once('@') {
+        Some((local, domain)) => {
+            let local: String = match crate::dir::EMAIL_LOCAL_PART {
+                EmailLocalPart::Preserve => local.to_owned(),
+                EmailLocalPart::Lowercase => local.to_lowercase(),
+            };
+            local + "@" + &domain.to_lowercase()
+        }
+        None => s.to_owned(),
+    }
+}
+
+pub fn is_phone_valid(s: &str) -> bool {
+    let digits: usize = s.chars().filter(|c| c.is_ascii_digit()).count();
+    (6..=15).contains(&digits)
+        && s.chars().all(|c| c.is_ascii_digit() || c == '+' || c == ' ' || c == '-' || c == '(' || c == ')')
+}
+
+pub fn is_timezone_valid(s: &str) -> bool {
+    s.parse::<chrono_tz::Tz>().is_ok()
+}
+
+pub fn is_notification_interval_valid(days: u64) -> bool {
+    (1..=crate::dir::MAX_NOTIFICATION_INTERVAL_DAYS).contains(&days)
+}
+
 // Note: passwords dont have to abide by the char restrictions of most other inputs since they are never shown.
 pub fn is_password_valid(p: &str) -> bool {
     if p.len() >= 6 {
@@ -42,4 +80,15 @@ pub fn gen_password(len: usize) -> String {
         .take(len)
         .map(char::from)
         .collect()
-}
\ No newline at end of file
+}
+
+pub fn format_time(time: std::time::SystemTime) -> String {
+    let datetime: chrono::DateTime<chrono::offset::Local> = time.into();
+    datetime.format("%d %B %Y at %H:%M").to_string()
+}
+
+/// Formats a time in the timezone of an organisation.
+pub fn format_time_tz(time: std::time::SystemTime, tz: &chrono_tz::Tz) -> String {
+    let datetime: chrono::DateTime<chrono::Utc> = time.into();
+    datetime.with_timezone(tz).format("%d %B %Y at %H:%M").to_string()
+}